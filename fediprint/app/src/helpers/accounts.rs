@@ -0,0 +1,52 @@
+use crate::helpers::activitypub::outbox_activities::send_actor_delete;
+use crate::helpers::search::remove_from_index;
+use shared::db::file::FullFile;
+use shared::db::jobs::{count_unfinished_deliveries, is_actor_delete_queued};
+use shared::db::profile::FullProfile;
+use shared::AppState;
+use std::sync::Arc;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+pub async fn delete_profile(profile: &FullProfile, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Removes a local or remote profile with everything it owns: the stored files, the search
+    //! documents of its models and notes and the database rows, including the account of local profiles.
+    //! The stored files are only removed once the rows are gone, so a failed deletion keeps them.
+    let files = FullFile::get_all_by_profile_id(&profile.id, state.pool.clone()).await?;
+    let deleted = FullProfile::delete_with_content(&profile.id, state.pool.clone()).await?;
+    for id in deleted {
+        remove_from_index(&id, &state.ms).await?;
+    }
+    for file in files {
+        match state.s3.delete_object(format!("/{}", file.id)).await {
+            Ok(d) => debug!("S3 Response: {:?}", d),
+            Err(e) => error!("Failed to delete file {} of {}: {e}", file.id, profile.id),
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_account(profile_id: &Uuid, state: Arc<AppState>) -> anyhow::Result<i64> {
+    //! Run by the `DELETE_ACCOUNT` job. The `Delete` for the followers' servers is queued first, the
+    //! profile is only deleted once all deliveries signed with its key are done. Returns the number
+    //! of deliveries still waiting, the job tries again until there are none left.
+    //! Transactions of the account as buyer are deleted with it, the ones as seller are kept for the
+    //! buyers with the seller set to NULL.
+    let profile = match FullProfile::get_by_id(profile_id, state.pool.clone()).await {
+        Ok(d) => d,
+        // Already deleted by an earlier try
+        Err(sqlx::Error::RowNotFound) => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    if !is_actor_delete_queued(&profile.id, &profile.server_id, state.pool.clone()).await? {
+        if let Err(e) = send_actor_delete(&profile, state.clone()).await {
+            error!("Failed to federate deletion of {}: {e}", profile.server_id);
+        }
+    }
+    let unfinished = count_unfinished_deliveries(&profile.id, state.pool.clone()).await?;
+    if unfinished > 0 {
+        return Ok(unfinished);
+    }
+    delete_profile(&profile, state).await?;
+    Ok(0)
+}
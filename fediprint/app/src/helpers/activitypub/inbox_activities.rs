@@ -1,31 +1,454 @@
+use crate::helpers::interactions::{move_followers, respond_to_follow};
+use crate::helpers::search::{index_model, index_note, remove_from_index};
+use anyhow::{anyhow, bail};
+use serde_json::Value;
+use shared::db::blocks::{CreateBlock, FullBlock};
+use shared::db::boosts::{CreateBoost, FullBoost};
+use shared::db::followers::{CreateFollower, FullFollower};
+use shared::db::instances::FullInstance;
+use shared::db::likes::{CreateLike, FullLike};
+use shared::db::model::{FullModel, FullModelWithRelationsIds};
+use shared::db::note::FullNote;
+use shared::db::profile::FullProfile;
+use shared::db::reports::CreateReport;
+use shared::db::tombstones::Tombstone;
+use shared::db::visibility::Viewer;
+use shared::db::InstanceModeration;
+use shared::helpers::activities::{
+    get_local_copy, get_remote_activity, normalize_context, store_note_response, ModelOrNote,
+};
+use shared::helpers::keys::KeyService;
+use shared::helpers::media::delete_media_of_model;
+use shared::models::activitypub::note::NoteResponse;
 use shared::models::inbox::InboxEvent;
-use tracing::debug;
+use shared::AppState;
+use std::sync::Arc;
+use tracing::{debug, info};
+use url::Url;
+use uuid::{uuid, Uuid};
 
-pub async fn handle_create(event: InboxEvent) -> anyhow::Result<()> {
+pub fn is_same_host(a: &str, b: &str) -> bool {
+    //! Objects and activities can only be created, updated or deleted by actors of the server hosting them
+    match (Url::parse(a), Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.host_str().is_some() && a.host_str() == b.host_str(),
+        _ => false,
+    }
+}
+
+fn parse_object(event: &InboxEvent) -> anyhow::Result<NoteResponse> {
+    //! Parses an embedded note or model, which usually inherits the `@context` of the activity
+    let mut object = event.object.clone();
+    let Some(fields) = object.as_object_mut() else {
+        bail!("Object is not embedded")
+    };
+    let context = match fields.get("@context") {
+        Some(d) => normalize_context(d),
+        None => normalize_context(&event.context),
+    };
+    fields.insert("@context".to_string(), context);
+    Ok(serde_json::from_value(object)?)
+}
+
+async fn get_authored_copy(
+    server_id: &str,
+    event: &InboxEvent,
+    state: &AppState,
+) -> anyhow::Result<Option<ModelOrNote>> {
+    //! Finds the stored note or model and makes sure the actor is its original author
+    let Some(copy) = get_local_copy(server_id, state.pool.clone()).await else {
+        return Ok(None);
+    };
+    let author = FullProfile::get_by_server_id(&event.actor, state.pool.clone()).await?;
+    let author_id = match &copy {
+        ModelOrNote::Note(d) => d.actor_id,
+        ModelOrNote::Model(d) => d.profile_id,
+    };
+    if author_id != author.id {
+        bail!("{} is not the author of {}", event.actor, server_id)
+    }
+    Ok(Some(copy))
+}
+
+async fn get_instance_id(profile_id: &Uuid, state: &AppState) -> anyhow::Result<Uuid> {
+    Ok(FullProfile::get_by_id(profile_id, state.pool.clone())
+        .await?
+        .instance)
+}
+
+pub async fn handle_create(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Create: {:?}", event);
+    let Some(object_id) = get_object_id(&event.object) else {
+        bail!("Create object has no id")
+    };
+    if !is_same_host(object_id, &event.actor) {
+        bail!("{} can't create {}", event.actor, object_id)
+    }
+    if Tombstone::exists(object_id, state.pool.clone()).await? {
+        debug!("Ignoring Create of deleted object {}", object_id);
+        return Ok(());
+    }
+    let stored = match event.object.as_str() {
+        // Only a link, the remote server is the source of truth
+        Some(url) => get_remote_activity(url.to_string(), state.clone())
+            .await
+            .map_err(|e| anyhow!("Failed to fetch {}: {:?}", url, e))?,
+        None => {
+            if event.object["type"].as_str() != Some("Note") {
+                debug!("Ignoring Create of {}", event.object["type"]);
+                return Ok(());
+            }
+            let data = parse_object(&event)?;
+            if data.id != object_id || data.attributed_to != event.actor {
+                bail!(
+                    "{} can't create objects of {}",
+                    event.actor,
+                    data.attributed_to
+                )
+            }
+            let author = FullProfile::get_by_server_id_or_fetch(
+                &event.actor,
+                &KeyService::new(&state.env),
+                state.pool.clone(),
+            )
+            .await?;
+            let instance = FullInstance::get_by_id(&author.instance, state.pool.clone()).await?;
+            store_note_response(data, instance, state.clone())
+                .await
+                .map_err(|e| anyhow!("Failed to store {}: {:?}", event.id, e))?
+        }
+    };
+    // A fetched object may name another author than the actor
+    let author = FullProfile::get_by_id(&get_author_id(&stored), state.pool.clone()).await?;
+    if !is_same_host(&author.server_id, &event.actor) {
+        bail!(
+            "{} can't create objects of {}",
+            event.actor,
+            author.server_id
+        )
+    }
+    match stored {
+        ModelOrNote::Note(d) => {
+            let instance_id = get_instance_id(&d.actor_id, &state).await?;
+            index_note(&d, &d.actor_id, &instance_id, &state.ms).await?
+        }
+        ModelOrNote::Model(d) => {
+            let instance_id = get_instance_id(&d.profile_id, &state).await?;
+            index_model(&d, &d.profile_id, &instance_id, &state.ms).await?
+        }
+    };
+    Ok(())
+}
+
+async fn update_actor(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Actors send their own profile, e.g. after a key rotation. The embedded copy isn't trusted,
+    //! the profile is fetched again from its server instead.
+    let Some(object_id) = get_object_id(&event.object) else {
+        bail!("Update object has no id")
+    };
+    if object_id != event.actor {
+        bail!("{} can't update {}", event.actor, object_id)
+    }
+    let Ok(profile) = FullProfile::get_by_server_id(object_id, state.pool.clone()).await else {
+        debug!("Ignoring Update of unknown actor {}", object_id);
+        return Ok(());
+    };
+    profile
+        .refresh_public_key(&KeyService::new(&state.env), state.pool.clone())
+        .await?
+        .refresh_aliases(&KeyService::new(&state.env), state.pool.clone())
+        .await?;
     Ok(())
 }
 
-pub async fn handle_update(event: InboxEvent) -> anyhow::Result<()> {
+pub async fn handle_update(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Update: {:?}", event);
+    match event.object["type"].as_str() {
+        Some("Note") => update_status(event, state).await,
+        Some("Person" | "Service" | "Application" | "Group" | "Organization") => {
+            update_actor(event, state).await
+        }
+        _ => {
+            info!(
+                "Rejecting Update of unsupported {} from {}",
+                event.object["type"], event.actor
+            );
+            Ok(())
+        }
+    }
+}
+
+async fn update_status(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Notes and models are both sent as `Note`
+    let data = parse_object(&event)?;
+    if data.attributed_to != event.actor {
+        bail!(
+            "{} can't update objects of {}",
+            event.actor,
+            data.attributed_to
+        )
+    }
+    if Tombstone::exists(&data.id, state.pool.clone()).await? {
+        debug!("Ignoring Update of deleted object {}", data.id);
+        return Ok(());
+    }
+    let Some(copy) = get_authored_copy(&data.id, &event, &state).await? else {
+        debug!("Ignoring Update of unknown object {}", data.id);
+        return Ok(());
+    };
+    match copy {
+        ModelOrNote::Note(d) => {
+            let note = FullNote::update_from_note_response(&d.id, data, state.pool.clone()).await?;
+            let instance_id = get_instance_id(&note.actor_id, &state).await?;
+            index_note(&note, &note.actor_id, &instance_id, &state.ms).await?;
+        }
+        ModelOrNote::Model(d) => {
+            let model =
+                FullModelWithRelationsIds::update_from_note_response(&d.id, data, state.clone())
+                    .await?;
+            let instance_id = get_instance_id(&model.profile_id, &state).await?;
+            index_model(&model, &model.profile_id, &instance_id, &state.ms).await?;
+        }
+    };
     Ok(())
 }
-pub async fn handle_delete(event: InboxEvent) -> anyhow::Result<()> {
+
+pub async fn handle_delete(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Delete: {:?}", event);
+    let Some(object_id) = get_object_id(&event.object) else {
+        bail!("Delete object has no id")
+    };
+    if !is_same_host(object_id, &event.actor) {
+        bail!("{} can't delete {}", event.actor, object_id)
+    }
+    // Kept even for unknown objects, the Delete may overtake the Create
+    Tombstone::create(object_id, state.pool.clone()).await?;
+    let Some(copy) = get_authored_copy(object_id, &event, &state).await? else {
+        debug!("Ignoring Delete of unknown object {}", object_id);
+        return Ok(());
+    };
+    match copy {
+        ModelOrNote::Note(d) => {
+            remove_from_index(&d.id, &state.ms).await?;
+            d.delete(state.pool.clone()).await?;
+        }
+        ModelOrNote::Model(d) => {
+            remove_from_index(&d.id, &state.ms).await?;
+            delete_media_of_model(&d.id, state.clone()).await?;
+            FullModel::delete_by_id_and_profile_id(&d.id, &d.profile_id, state.pool.clone())
+                .await?;
+        }
+    };
     Ok(())
 }
 
-pub async fn handle_follow(event: InboxEvent) -> anyhow::Result<()> {
+pub async fn handle_follow(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Follow: {:?}", event);
-    Ok(())
+    let object = match event.object.as_str() {
+        Some(d) => d,
+        None => bail!("Follow object is not an id"),
+    };
+    let profile = match FullProfile::get_by_server_id(object, state.pool.clone()).await {
+        Ok(d) if d.instance == uuid!("00000000-0000-0000-0000-000000000000") => d,
+        _ => bail!("Followed profile {} is not local", object),
+    };
+    let follower = FullProfile::get_by_server_id_or_fetch(
+        &event.actor,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await?;
+    // Follows from silenced instances always need the approval of the user
+    let silenced = FullInstance::get_moderation_of_profile(&follower.id, state.pool.clone())
+        .await?
+        == InstanceModeration::Silenced;
+    let follow = CreateFollower {
+        profile_id: profile.id,
+        follower_id: follower.id,
+        pending: profile.manually_approves_followers || silenced,
+        activity_id: Some(event.id),
+    }
+    .create(state.pool.clone())
+    .await?;
+    if follow.pending {
+        debug!("Follow by {} awaits approval", follower.server_id);
+        return Ok(());
+    }
+    FullProfile::refresh_follower_count(&profile.id, state.pool.clone()).await?;
+    respond_to_follow(&follow, "Accept", state.pool.clone()).await
+}
+async fn get_answered_follow(event: &InboxEvent, state: &AppState) -> anyhow::Result<FullFollower> {
+    //! Finds the outgoing follow an `Accept` or `Reject` refers to
+    let follow_id = match event.object.as_str() {
+        Some(d) => Some(d),
+        None => event.object["id"].as_str(),
+    };
+    let followed = FullProfile::get_by_server_id(&event.actor, state.pool.clone()).await?;
+    let follow = match follow_id {
+        Some(id) => FullFollower::get_by_activity_id(id, state.pool.clone())
+            .await
+            .ok(),
+        None => None,
+    };
+    let follow = match follow {
+        Some(d) => d,
+        // Some servers don't echo our id, so fall back to the follower in the embedded Follow
+        None => {
+            let Some(actor) = event.object["actor"].as_str() else {
+                bail!("Answered follow not found")
+            };
+            let follower = FullProfile::get_by_server_id(actor, state.pool.clone()).await?;
+            FullFollower::get_by_profile_id_and_follower_id(
+                &followed.id,
+                &follower.id,
+                state.pool.clone(),
+            )
+            .await?
+        }
+    };
+    if follow.profile_id != followed.id {
+        bail!("{} can't answer follows for other profiles", event.actor)
+    }
+    Ok(follow)
 }
-pub async fn handle_accept(event: InboxEvent) -> anyhow::Result<()> {
+
+pub async fn handle_accept(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Accept: {:?}", event);
+    let follow = get_answered_follow(&event, &state).await?;
+    let follow = follow.accept(state.pool.clone()).await?;
+    FullProfile::refresh_following_count(&follow.follower_id, state.pool.clone()).await?;
     Ok(())
 }
 
-pub async fn handle_reject(event: InboxEvent) -> anyhow::Result<()> {
+pub async fn handle_reject(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Reject: {:?}", event);
+    let follow = get_answered_follow(&event, &state).await?;
+    let follower_id = follow.follower_id;
+    follow.delete(state.pool.clone()).await?;
+    FullProfile::refresh_following_count(&follower_id, state.pool.clone()).await?;
+    Ok(())
+}
+
+pub async fn handle_block(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Remote profiles blocking local ones lose the follows in both directions,
+    //! and the local profile doesn't see their content anymore
+    debug!("Block: {:?}", event);
+    let Some(object_id) = get_object_id(&event.object) else {
+        bail!("Block object has no id")
+    };
+    let blocked = match FullProfile::get_by_server_id(object_id, state.pool.clone()).await {
+        Ok(d) if d.instance == uuid!("00000000-0000-0000-0000-000000000000") => d,
+        _ => bail!("Blocked profile {} is not local", object_id),
+    };
+    let actor = FullProfile::get_by_server_id_or_fetch(
+        &event.actor,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await?;
+    CreateBlock {
+        profile_id: actor.id,
+        target_id: blocked.id,
+        activity_id: Some(event.id),
+    }
+    .create(state.pool.clone())
+    .await?;
+    FullFollower::delete_between(&actor.id, &blocked.id, state.pool.clone()).await?;
+    for profile_id in [&actor.id, &blocked.id] {
+        FullProfile::refresh_follower_count(profile_id, state.pool.clone()).await?;
+        FullProfile::refresh_following_count(profile_id, state.pool.clone()).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_move(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! A remote profile moved to another account. The local followers follow the new account instead,
+    //! as long as it lists the moved profile in `alsoKnownAs`.
+    debug!("Move: {:?}", event);
+    if get_object_id(&event.object) != Some(event.actor.as_str()) {
+        bail!("{} can only move itself", event.actor)
+    }
+    let Some(target_id) = event.target.as_deref() else {
+        bail!("Move has no target")
+    };
+    let Ok(origin) = FullProfile::get_by_server_id(&event.actor, state.pool.clone()).await else {
+        debug!("Ignoring Move of unknown profile {}", event.actor);
+        return Ok(());
+    };
+    let target = FullProfile::get_by_server_id_or_fetch(
+        target_id,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await?
+    .refresh_aliases(&KeyService::new(&state.env), state.pool.clone())
+    .await?;
+    if target.id == origin.id || !target.also_known_as.contains(&origin.server_id) {
+        bail!("{} is no alias of {}", origin.server_id, target.server_id)
+    }
+    move_followers(&origin, &target, state.pool.clone()).await?;
+    FullProfile::set_moved_to(&origin.id, Some(&target.server_id), state.pool.clone()).await?;
+    Ok(())
+}
+
+fn get_author_id(copy: &ModelOrNote) -> Uuid {
+    match copy {
+        ModelOrNote::Note(d) => d.actor_id,
+        ModelOrNote::Model(d) => d.profile_id,
+    }
+}
+
+pub async fn handle_flag(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Reports of remote moderators about a local profile, one report per flagged note or model
+    debug!("Flag: {:?}", event);
+    let object_ids: Vec<&str> = match &event.object {
+        Value::Array(d) => d.iter().filter_map(get_object_id).collect(),
+        d => get_object_id(d).into_iter().collect(),
+    };
+    let mut profile_id = None;
+    let mut statuses = vec![];
+    for object_id in object_ids {
+        if let Some(copy) = get_local_copy(object_id, state.pool.clone()).await {
+            statuses.push(copy);
+        } else if let Ok(d) = FullProfile::get_by_server_id(object_id, state.pool.clone()).await {
+            profile_id = Some(d.id);
+        }
+    }
+    let Some(profile_id) = profile_id.or(statuses.first().map(get_author_id)) else {
+        bail!("Flag doesn't reference a known profile")
+    };
+    let profile = FullProfile::get_by_id(&profile_id, state.pool.clone()).await?;
+    if profile.instance != uuid!("00000000-0000-0000-0000-000000000000") {
+        bail!("Flagged profile {} is not local", profile.server_id)
+    }
+    let reporter = FullProfile::get_by_server_id_or_fetch(
+        &event.actor,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await?;
+    let statuses: Vec<(Option<Uuid>, Option<Uuid>)> = statuses
+        .iter()
+        .filter(|v| get_author_id(v) == profile.id)
+        .map(get_note_and_model_id)
+        .collect();
+    // A flag without statuses reports the profile itself
+    let statuses = if statuses.is_empty() {
+        vec![(None, None)]
+    } else {
+        statuses
+    };
+    for (note_id, model_id) in statuses {
+        CreateReport {
+            reporter_id: reporter.id,
+            profile_id: profile.id,
+            note_id,
+            model_id,
+            reason: event.content.clone().unwrap_or_default(),
+            activity_id: Some(event.id.clone()),
+        }
+        .create(state.pool.clone())
+        .await?;
+    }
     Ok(())
 }
 
@@ -39,17 +462,307 @@ pub async fn handle_remove(event: InboxEvent) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn handle_like(event: InboxEvent) -> anyhow::Result<()> {
+fn get_object_id(object: &Value) -> Option<&str> {
+    //! Objects are either referenced by their id or embedded
+    match object.as_str() {
+        Some(d) => Some(d),
+        None => object["id"].as_str(),
+    }
+}
+
+fn get_note_and_model_id(copy: &ModelOrNote) -> (Option<Uuid>, Option<Uuid>) {
+    match copy {
+        ModelOrNote::Note(d) => (Some(d.id), None),
+        ModelOrNote::Model(d) => (None, Some(d.id)),
+    }
+}
+
+async fn can_see_copy(
+    actor: &FullProfile,
+    copy: &ModelOrNote,
+    state: &AppState,
+) -> anyhow::Result<bool> {
+    let viewer = Viewer::from_profile_id(&actor.id);
+    Ok(match copy {
+        ModelOrNote::Note(d) => viewer.can_view_note(&d.id, state.pool.clone()).await?,
+        ModelOrNote::Model(d) => viewer.can_view_model(&d.id, state.pool.clone()).await?,
+    })
+}
+
+pub async fn handle_like(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Like: {:?}", event);
+    let Some(object_id) = get_object_id(&event.object) else {
+        bail!("Like object has no id")
+    };
+    let Some(copy) = get_local_copy(object_id, state.pool.clone()).await else {
+        debug!("Ignoring Like of unknown object {}", object_id);
+        return Ok(());
+    };
+    let (note_id, model_id) = get_note_and_model_id(&copy);
+    let actor = FullProfile::get_by_server_id_or_fetch(
+        &event.actor,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await?;
+    if !can_see_copy(&actor, &copy, &state).await? {
+        debug!(
+            "Ignoring Like of {} hidden from {}",
+            object_id, actor.server_id
+        );
+        return Ok(());
+    }
+    CreateLike {
+        profile_id: actor.id,
+        note_id,
+        model_id,
+        activity_id: Some(event.id),
+    }
+    .create(state.pool.clone())
+    .await?;
     Ok(())
 }
 
-pub async fn handle_announce(event: InboxEvent) -> anyhow::Result<()> {
+pub async fn handle_announce(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
     debug!("Announce: {:?}", event);
+    let Some(object_id) = get_object_id(&event.object) else {
+        bail!("Announce object has no id")
+    };
+    let Some(copy) = get_local_copy(object_id, state.pool.clone()).await else {
+        debug!("Ignoring Announce of unknown object {}", object_id);
+        return Ok(());
+    };
+    let (note_id, model_id) = get_note_and_model_id(&copy);
+    let actor = FullProfile::get_by_server_id_or_fetch(
+        &event.actor,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await?;
+    if !can_see_copy(&actor, &copy, &state).await? {
+        debug!(
+            "Ignoring Announce of {} hidden from {}",
+            object_id, actor.server_id
+        );
+        return Ok(());
+    }
+    CreateBoost {
+        profile_id: actor.id,
+        note_id,
+        model_id,
+        activity_id: Some(event.id),
+    }
+    .create(state.pool.clone())
+    .await?;
     Ok(())
 }
 
-pub async fn handle_undo(event: InboxEvent) -> anyhow::Result<()> {
-    debug!("Undo: {:?}", event);
+async fn undo_like(
+    event: &InboxEvent,
+    actor: &FullProfile,
+    state: &AppState,
+) -> anyhow::Result<()> {
+    let like = match FullLike::get_by_activity_id(
+        get_object_id(&event.object).unwrap_or_default(),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        // Some servers don't keep the id of the original activity, so fall back to the liked object
+        Err(_) => {
+            let Some(object_id) = get_object_id(&event.object["object"]) else {
+                bail!("Undone like not found")
+            };
+            let Some(copy) = get_local_copy(object_id, state.pool.clone()).await else {
+                bail!("Undone like not found")
+            };
+            let (note_id, model_id) = get_note_and_model_id(&copy);
+            FullLike::get_by_profile_id_and_object(&actor.id, note_id, model_id, state.pool.clone())
+                .await?
+        }
+    };
+    if like.profile_id != actor.id {
+        bail!("{} can't undo likes of other profiles", event.actor)
+    }
+    like.delete(state.pool.clone()).await?;
+    Ok(())
+}
+
+async fn undo_announce(
+    event: &InboxEvent,
+    actor: &FullProfile,
+    state: &AppState,
+) -> anyhow::Result<()> {
+    let boost = match FullBoost::get_by_activity_id(
+        get_object_id(&event.object).unwrap_or_default(),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(_) => {
+            let Some(object_id) = get_object_id(&event.object["object"]) else {
+                bail!("Undone boost not found")
+            };
+            let Some(copy) = get_local_copy(object_id, state.pool.clone()).await else {
+                bail!("Undone boost not found")
+            };
+            let (note_id, model_id) = get_note_and_model_id(&copy);
+            FullBoost::get_by_profile_id_and_object(
+                &actor.id,
+                note_id,
+                model_id,
+                state.pool.clone(),
+            )
+            .await?
+        }
+    };
+    if boost.profile_id != actor.id {
+        bail!("{} can't undo boosts of other profiles", event.actor)
+    }
+    boost.delete(state.pool.clone()).await?;
+    Ok(())
+}
+
+async fn undo_follow(
+    event: &InboxEvent,
+    actor: &FullProfile,
+    state: &AppState,
+) -> anyhow::Result<()> {
+    let follow = match FullFollower::get_by_activity_id(
+        get_object_id(&event.object).unwrap_or_default(),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(_) => {
+            let Some(object_id) = get_object_id(&event.object["object"]) else {
+                bail!("Undone follow not found")
+            };
+            let followed = FullProfile::get_by_server_id(object_id, state.pool.clone()).await?;
+            FullFollower::get_by_profile_id_and_follower_id(
+                &followed.id,
+                &actor.id,
+                state.pool.clone(),
+            )
+            .await?
+        }
+    };
+    if follow.follower_id != actor.id {
+        bail!("{} can't undo follows of other profiles", event.actor)
+    }
+    let profile_id = follow.profile_id;
+    follow.delete(state.pool.clone()).await?;
+    FullProfile::refresh_follower_count(&profile_id, state.pool.clone()).await?;
+    Ok(())
+}
+
+async fn undo_block(
+    event: &InboxEvent,
+    actor: &FullProfile,
+    state: &AppState,
+) -> anyhow::Result<()> {
+    let Some(object_id) = get_object_id(&event.object["object"]) else {
+        bail!("Undone block has no object")
+    };
+    let blocked = FullProfile::get_by_server_id(object_id, state.pool.clone()).await?;
+    FullBlock::get_by_profile_id_and_target_id(&actor.id, &blocked.id, state.pool.clone())
+        .await?
+        .delete(state.pool.clone())
+        .await?;
     Ok(())
 }
+
+pub async fn handle_undo(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
+    debug!("Undo: {:?}", event);
+    let actor = FullProfile::get_by_server_id(&event.actor, state.pool.clone()).await?;
+    match event.object["type"].as_str() {
+        Some("Like") => undo_like(&event, &actor, &state).await,
+        Some("Announce") => undo_announce(&event, &actor, &state).await,
+        Some("Follow") => undo_follow(&event, &actor, &state).await,
+        Some("Block") => undo_block(&event, &actor, &state).await,
+        _ => {
+            debug!("Ignoring Undo of {}", event.object["type"]);
+            Ok(())
+        }
+    }
+}
+
+pub async fn handle_inbox_event(event: InboxEvent, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Dispatches a verified activity to the handler of its type.
+    //! Activities addressed to local profiles that blocked the actor are dropped.
+    if FullBlock::is_blocked_by_any(&event.actor, &event.get_addressed(), state.pool.clone())
+        .await?
+    {
+        debug!("Dropping {} of blocked {}", event.event_type, event.actor);
+        return Ok(());
+    }
+    let event_type = event.event_type.clone();
+    match event_type.as_str() {
+        "Announce" => handle_announce(event, state).await,
+        "Create" => handle_create(event, state).await,
+        "Update" => handle_update(event, state).await,
+        "Delete" => handle_delete(event, state).await,
+        "Follow" => handle_follow(event, state).await,
+        "Accept" => handle_accept(event, state).await,
+        "Reject" => handle_reject(event, state).await,
+        "Remove" => handle_remove(event).await,
+        "Like" => handle_like(event, state).await,
+        "Undo" => handle_undo(event, state).await,
+        "Add" => handle_add(event).await,
+        "Block" => handle_block(event, state).await,
+        "Flag" => handle_flag(event, state).await,
+        "Move" => handle_move(event, state).await,
+        _ => {
+            debug!("Unknown event: {}", event_type);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_same_host() {
+        assert!(is_same_host(
+            "https://mastodon.online/users/Mawoka/statuses/1",
+            "https://mastodon.online/users/Mawoka"
+        ));
+        assert!(!is_same_host(
+            "https://evil.example/users/Mawoka/statuses/1",
+            "https://mastodon.online/users/Mawoka"
+        ));
+        assert!(!is_same_host(
+            "https://mastodon.online.evil.example/statuses/1",
+            "https://mastodon.online/users/Mawoka"
+        ));
+        assert!(!is_same_host("not a url", "not a url"));
+    }
+
+    #[test]
+    fn test_get_object_id() {
+        // Flags reference the profile by id and may embed the statuses
+        let objects = serde_json::json!([
+            "https://fedi.example/users/alice",
+            {"id": "https://fedi.example/notes/1", "type": "Note"},
+            {"type": "Note"}
+        ]);
+        let ids: Vec<&str> = objects
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(get_object_id)
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                "https://fedi.example/users/alice",
+                "https://fedi.example/notes/1"
+            ]
+        );
+    }
+}
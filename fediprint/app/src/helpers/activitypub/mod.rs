@@ -1 +1,2 @@
 pub mod inbox_activities;
+pub mod outbox_activities;
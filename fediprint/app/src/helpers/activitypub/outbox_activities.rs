@@ -0,0 +1,210 @@
+use crate::helpers::interactions::queue_activity;
+use anyhow::bail;
+use serde_json::json;
+use shared::db::followers::FullFollower;
+use shared::db::instances::InstanceActor;
+use shared::db::mentions::FullMention;
+use shared::db::profile::FullProfile;
+use shared::db::reports::FullReport;
+use shared::db::EventAudience;
+use shared::helpers::activities::{get_local_copy, ModelOrNote};
+use shared::models::activitypub::{get_addressing, Activity, ActivityPubModel, Profile};
+use shared::AppState;
+use std::sync::Arc;
+use tracing::debug;
+use uuid::{uuid, Uuid};
+
+pub async fn send_status_activity(
+    activity_type: &str,
+    id: &Uuid,
+    profile_id: &Uuid,
+    audience: &EventAudience,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    //! Sends a `Create` or `Update` with the note or model as object to all followers and mentioned profiles
+    if *audience == EventAudience::Nobody {
+        debug!(
+            "Not federating {} of {} as it is private",
+            activity_type, id
+        );
+        return Ok(());
+    }
+    let profile = FullProfile::get_by_id(profile_id, state.pool.clone()).await?;
+    let mut object =
+        ActivityPubModel::get_by_id(id, state.pool.clone(), state.env.public_url.clone()).await?;
+    if object.attributed_to != profile.server_id {
+        bail!("{} is not the author of {}", profile.server_id, id)
+    }
+    let mentioned = FullMention::get_mentioned_profiles(id, state.pool.clone()).await?;
+    // The addressing of the object follows its stored audience
+    let (to, mut cc) = (object.to.clone(), object.cc.clone());
+    let mut recipients = match audience {
+        EventAudience::Public | EventAudience::Followers => {
+            FullFollower::get_follower_profiles(&profile.id, state.pool.clone()).await?
+        }
+        _ => vec![],
+    };
+    for mention in mentioned {
+        if !recipients.iter().any(|v| v.id == mention.id) {
+            recipients.push(mention);
+        }
+    }
+    // Replies also go to the author of the parent
+    if let Some(parent) = &object.in_reply_to {
+        if let Some(copy) = get_local_copy(parent, state.pool.clone()).await {
+            let author_id = match copy {
+                ModelOrNote::Note(d) => d.actor_id,
+                ModelOrNote::Model(d) => d.profile_id,
+            };
+            let author = FullProfile::get_by_id(&author_id, state.pool.clone()).await?;
+            if !cc.contains(&author.server_id) && !to.contains(&author.server_id) {
+                cc.push(author.server_id.clone());
+            }
+            if !recipients.iter().any(|v| v.id == author.id) {
+                recipients.push(author);
+            }
+        }
+    }
+    object.to = to.clone();
+    object.cc = cc.clone();
+    let activity_id = match activity_type {
+        "Create" => format!("{}/activity", object.id),
+        _ => format!(
+            "{}#{}s/{}",
+            object.id,
+            activity_type.to_lowercase(),
+            chrono::Utc::now().timestamp()
+        ),
+    };
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: activity_id,
+        type_field: activity_type.to_string(),
+        actor: profile.server_id.clone(),
+        object: serde_json::to_value(&object)?,
+        to,
+        cc,
+    };
+    queue_activity(&activity, &profile.id, &recipients, state.pool.clone()).await
+}
+
+pub async fn send_status_delete(
+    server_id: &str,
+    profile_id: &Uuid,
+    audience: &EventAudience,
+    mentioned: Vec<FullProfile>,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    //! Replaces the note or model with a `Tombstone` everywhere it was sent to. The audience and the
+    //! mentioned profiles have to be the ones of the object, so the `Delete` is addressed the same way.
+    let profile = FullProfile::get_by_id(profile_id, state.pool.clone()).await?;
+    let (to, cc) = get_addressing(&profile.server_id, audience, &mentioned);
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: format!("{server_id}#delete/{}", Uuid::now_v7()),
+        type_field: "Delete".to_string(),
+        actor: profile.server_id.clone(),
+        object: json!({
+            "id": server_id,
+            "type": "Tombstone",
+        }),
+        to,
+        cc,
+    };
+    let mut recipients = match audience {
+        EventAudience::Public | EventAudience::Followers => {
+            FullFollower::get_follower_profiles(&profile.id, state.pool.clone()).await?
+        }
+        _ => vec![],
+    };
+    for mention in mentioned {
+        if !recipients.iter().any(|v| v.id == mention.id) {
+            recipients.push(mention);
+        }
+    }
+    queue_activity(&activity, &profile.id, &recipients, state.pool.clone()).await
+}
+
+pub async fn send_actor_delete(profile: &FullProfile, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Tells the servers of all followers that the actor is gone. The deliveries are signed with the
+    //! key of the account, so it has to be kept until they are done.
+    let (to, cc) = get_addressing(&profile.server_id, &EventAudience::Public, &[]);
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: format!("{}#delete/{}", profile.server_id, Uuid::now_v7()),
+        type_field: "Delete".to_string(),
+        actor: profile.server_id.clone(),
+        object: json!(profile.server_id),
+        to,
+        cc,
+    };
+    let followers = FullFollower::get_follower_profiles(&profile.id, state.pool.clone()).await?;
+    queue_activity(&activity, &profile.id, &followers, state.pool.clone()).await
+}
+
+pub async fn send_move(
+    profile: &FullProfile,
+    target: &FullProfile,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    //! Tells the followers that the profile moved to the target, which lists it in `alsoKnownAs`
+    let (to, cc) = get_addressing(&profile.server_id, &EventAudience::Followers, &[]);
+    let activity = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}#moves/{}", profile.server_id, Uuid::now_v7()),
+        "type": "Move",
+        "actor": profile.server_id,
+        "object": profile.server_id,
+        "target": target.server_id,
+        "to": to,
+        "cc": cc,
+    });
+    let followers = FullFollower::get_follower_profiles(&profile.id, state.pool.clone()).await?;
+    queue_activity(&activity, &profile.id, &followers, state.pool.clone()).await
+}
+
+pub async fn send_actor_update(profile: FullProfile, state: Arc<AppState>) -> anyhow::Result<()> {
+    //! Sends the current actor to the followers, e.g. so remote servers pick up a new public key
+    let (to, cc) = get_addressing(&profile.server_id, &EventAudience::Public, &[]);
+    let profile_id = profile.id;
+    let activity = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}#updates/{}", profile.server_id, Uuid::now_v7()),
+        "type": "Update",
+        "actor": profile.server_id.clone(),
+        "object": Profile::from_local(profile, &state.env.public_url),
+        "to": to,
+        "cc": cc,
+    });
+    let followers = FullFollower::get_follower_profiles(&profile_id, state.pool.clone()).await?;
+    queue_activity(&activity, &profile_id, &followers, state.pool.clone()).await
+}
+
+pub async fn send_report_flag(
+    report: &FullReport,
+    profile: &FullProfile,
+    object_ids: Vec<String>,
+    state: Arc<AppState>,
+) -> anyhow::Result<String> {
+    //! Forwards a report to the server of the reported profile. It is sent by the instance actor,
+    //! so the reporter stays anonymous to the remote moderators.
+    let actor = InstanceActor::get_local(state.pool.clone()).await?;
+    let id = format!("{}#reports/{}", actor.actor_id(), report.id);
+    let objects = [vec![profile.server_id.clone()], object_ids].concat();
+    let activity = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": id,
+        "type": "Flag",
+        "actor": actor.actor_id(),
+        "content": report.reason,
+        "object": objects,
+    });
+    queue_activity(
+        &activity,
+        &uuid!("00000000-0000-0000-0000-000000000000"),
+        std::slice::from_ref(profile),
+        state.pool.clone(),
+    )
+    .await?;
+    Ok(id)
+}
@@ -2,20 +2,21 @@ use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
+use axum::http::header::USER_AGENT;
+use axum::http::HeaderMap;
 use chrono::{Duration, Local, Utc};
 use jsonwebtoken::{
     decode, encode, errors, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
 };
-use openssl::pkey::{PKey, Private};
 use serde::{Deserialize, Serialize};
+use shared::db::AccountRole;
 use std::fmt;
+use std::net::SocketAddr;
 use uuid::Uuid;
 
 #[cfg(test)]
 use crate::{TEST_ACCOUNT_UUID, TEST_PROFILE_UUID};
 #[cfg(test)]
-use openssl::rsa::Rsa;
-#[cfg(test)]
 use shared::db::account::FullAccount;
 #[cfg(test)]
 use shared::db::profile::FullProfile;
@@ -30,7 +31,11 @@ pub struct Claims {
     pub display_name: String,
     pub profile_id: Uuid,
     pub server_id: String,
-    pub private_key: String,
+    #[serde(default)]
+    pub role: AccountRole,
+    // Tokens without a session can't be renewed
+    #[serde(default)]
+    pub sid: Uuid,
     pub exp: i64,
     pub iat: i64,
 }
@@ -43,7 +48,8 @@ pub struct InputClaims {
     pub display_name: String,
     pub profile_id: Uuid,
     pub server_id: String,
-    pub private_key: String,
+    pub role: AccountRole,
+    pub sid: Uuid,
 }
 
 #[derive(Debug, Clone)]
@@ -56,15 +62,13 @@ pub struct UserState {
     pub server_id: String,
     pub exp: i64,
     pub iat: i64,
-    pub private_key: PKey<Private>,
+    pub role: AccountRole,
+    pub sid: Uuid,
 }
 
 impl UserState {
-    pub fn from_claims(input: Claims, key: &str) -> anyhow::Result<UserState> {
-        use base64::{engine::general_purpose, Engine as _};
-        let pkcs8_key = general_purpose::STANDARD.decode(input.private_key)?;
-        let private_key = PKey::private_key_from_pem_passphrase(&pkcs8_key, key.as_ref())?;
-        Ok(UserState {
+    pub fn from_claims(input: Claims) -> UserState {
+        UserState {
             sub: input.sub,
             email: input.email,
             username: input.username,
@@ -73,8 +77,9 @@ impl UserState {
             server_id: input.server_id,
             iat: input.iat,
             exp: input.exp,
-            private_key,
-        })
+            role: input.role,
+            sid: input.sid,
+        }
     }
     #[cfg(test)]
     pub async fn get_fake(pool: PgPool) -> UserState {
@@ -84,8 +89,6 @@ impl UserState {
         let profile = FullProfile::get_by_id(&TEST_PROFILE_UUID, pool.clone())
             .await
             .unwrap();
-        let rsa_key = Rsa::private_key_from_pem(account.private_key.as_ref()).unwrap();
-        let pkey = PKey::from_rsa(rsa_key).unwrap();
         UserState {
             sub: account.id,
             email: account.email,
@@ -95,7 +98,8 @@ impl UserState {
             server_id: profile.server_id,
             iat: 123456i64,
             exp: 123456i64,
-            private_key: pkey,
+            role: account.role,
+            sid: Uuid::nil(),
         }
     }
 }
@@ -108,8 +112,9 @@ pub fn generate_jwt(input_claims: InputClaims, secret: String) -> String {
         profile_id: input_claims.profile_id,
         username: input_claims.username,
         server_id: input_claims.server_id,
-        private_key: input_claims.private_key,
         sub: input_claims.sub,
+        role: input_claims.role,
+        sid: input_claims.sid,
         iat: now.timestamp(),
         exp: (now + Duration::hours(1)).timestamp(),
     };
@@ -161,6 +166,25 @@ pub fn check_if_token_was_valid(jwt: String, secret: String) -> Result<Claims, F
     }
 }
 
+pub fn get_client_ip(headers: &HeaderMap, addr: &SocketAddr) -> String {
+    //! Behind a reverse proxy the peer is the proxy, so the forwarded address is preferred.
+    //! It is only shown in the session list and not trusted for anything else.
+    headers
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| addr.ip().to_string())
+}
+
+pub fn get_user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
 pub fn get_password_hash(password: String) -> String {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
@@ -176,3 +200,78 @@ pub fn check_password_hash(plain_password: String, hashed_password: &str) -> boo
         .verify_password(plain_password.as_bytes(), &parsed_hash)
         .is_ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_claims(sid: Uuid) -> InputClaims {
+        InputClaims {
+            sub: TEST_ACCOUNT_UUID,
+            email: "test@mawoka.eu".to_string(),
+            username: "testuser".to_string(),
+            display_name: "testuser".to_string(),
+            profile_id: TEST_PROFILE_UUID,
+            server_id: "http://localhost:3000/api/v1/user/testuser".to_string(),
+            role: AccountRole::default(),
+            sid,
+        }
+    }
+
+    #[test]
+    fn test_read_jwt() {
+        let sid = Uuid::now_v7();
+        let jwt = generate_jwt(input_claims(sid), "secret".to_string());
+        let claims = read_jwt(jwt.clone(), "secret".to_string()).unwrap().claims;
+        assert_eq!((claims.sub, claims.sid), (TEST_ACCOUNT_UUID, sid));
+        assert!(read_jwt(jwt.clone(), "other secret".to_string()).is_err());
+        assert!(check_if_token_was_valid(jwt, "other secret".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_check_if_token_was_valid() {
+        let encode_claims = |exp: i64| {
+            let claims = Claims {
+                sub: TEST_ACCOUNT_UUID,
+                email: "test@mawoka.eu".to_string(),
+                username: "testuser".to_string(),
+                display_name: "testuser".to_string(),
+                profile_id: TEST_PROFILE_UUID,
+                server_id: "http://localhost:3000/api/v1/user/testuser".to_string(),
+                role: AccountRole::default(),
+                sid: Uuid::nil(),
+                exp,
+                iat: exp - 3600,
+            };
+            encode(
+                &Header::default(),
+                &claims,
+                &EncodingKey::from_secret("secret".as_ref()),
+            )
+            .unwrap()
+        };
+        // Expired tokens can still be renewed for a while, but not read
+        let expired = encode_claims((Utc::now() - Duration::days(1)).timestamp());
+        assert!(read_jwt(expired.clone(), "secret".to_string()).is_err());
+        assert!(check_if_token_was_valid(expired, "secret".to_string()).is_ok());
+        let too_old = encode_claims((Utc::now() - Duration::days(31)).timestamp());
+        assert!(check_if_token_was_valid(too_old, "secret".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_password_hash() {
+        let hash = get_password_hash("password".to_string());
+        assert!(check_password_hash("password".to_string(), &hash));
+        assert!(!check_password_hash("Password".to_string(), &hash));
+    }
+
+    #[test]
+    fn test_get_client_ip() {
+        let addr: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_client_ip(&headers, &addr), "10.0.0.1");
+        headers.insert("X-Forwarded-For", "203.0.113.7, 10.0.0.1".parse().unwrap());
+        assert_eq!(get_client_ip(&headers, &addr), "203.0.113.7");
+        assert_eq!(get_user_agent(&headers), None);
+    }
+}
@@ -1,51 +1,530 @@
 use crate::helpers::auth::UserState;
-use crate::helpers::sign::sign_post_request_with_body;
-use shared::helpers::config::Config;
-use shared::models::activitypub::FollowRequest;
+use serde::Serialize;
+use serde_json::json;
+use shared::db::blocks::{CreateBlock, FullBlock};
+use shared::db::boosts::{CreateBoost, FullBoost};
+use shared::db::followers::{CreateFollower, FullFollower};
+use shared::db::jobs;
+use shared::db::likes::{CreateLike, FullLike};
+use shared::models::activitypub::{Activity, FollowRequest, NoteJoinedModel, PUBLIC_ADDRESS};
+use sqlx::PgPool;
 
 use shared::db::profile::FullProfile;
-use uuid::Uuid;
+use uuid::{uuid, Uuid};
+
+pub async fn queue_activity<T: Serialize>(
+    activity: &T,
+    sender: &Uuid,
+    recipients: &[FullProfile],
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    //! Queues the signed delivery of the activity to the inboxes of all remote recipients
+    let json_data = serde_json::to_string(activity)?;
+    jobs::deliver_activity(&json_data, sender, recipients, pool).await?;
+    Ok(())
+}
 
 pub async fn follow_user(
     to_follow: &FullProfile,
-    claims: &UserState,
-    env: &Config,
-) -> anyhow::Result<()> {
+    follower: &FullProfile,
+    pool: PgPool,
+) -> anyhow::Result<FullFollower> {
+    //! Stores a pending follow and sends the `Follow` to the remote profile.
+    //! The follow is completed once the `Accept` arrives in the inbox.
+    if to_follow.instance == uuid!("00000000-0000-0000-0000-000000000000") {
+        // Local profiles don't need any federation
+        let follow = CreateFollower {
+            profile_id: to_follow.id,
+            follower_id: follower.id,
+            pending: to_follow.manually_approves_followers,
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await?;
+        FullProfile::refresh_follower_count(&to_follow.id, pool.clone()).await?;
+        FullProfile::refresh_following_count(&follower.id, pool).await?;
+        return Ok(follow);
+    }
+    let follow = CreateFollower {
+        profile_id: to_follow.id,
+        follower_id: follower.id,
+        pending: true,
+        activity_id: Some(format!("{}#follows/{}", follower.server_id, Uuid::now_v7())),
+    }
+    .create(pool.clone())
+    .await?;
     let data = FollowRequest {
         context: "https://www.w3.org/ns/activitystreams".to_string(),
-        id: format!("{}/{}", env.public_url, Uuid::new_v4()),
+        id: follow.activity_id.clone().unwrap(),
         type_field: "Follow".to_string(),
+        actor: follower.server_id.clone(),
+        object: to_follow.server_id.clone(),
+    };
+    queue_activity(&data, &follower.id, std::slice::from_ref(to_follow), pool).await?;
+    Ok(follow)
+}
+
+pub async fn unfollow_user(
+    to_unfollow: &FullProfile,
+    follower: &FullProfile,
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    //! Sends an `Undo` for the follow and drops the relation.
+    let follow = FullFollower::get_by_profile_id_and_follower_id(
+        &to_unfollow.id,
+        &follower.id,
+        pool.clone(),
+    )
+    .await?;
+    if to_unfollow.instance == uuid!("00000000-0000-0000-0000-000000000000") {
+        follow.delete(pool.clone()).await?;
+        FullProfile::refresh_follower_count(&to_unfollow.id, pool.clone()).await?;
+        FullProfile::refresh_following_count(&follower.id, pool).await?;
+        return Ok(());
+    }
+    let follow_id = follow
+        .activity_id
+        .clone()
+        .unwrap_or_else(|| format!("{}#follows/{}", follower.server_id, follow.id));
+    let data = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: format!("{follow_id}/undo"),
+        type_field: "Undo".to_string(),
+        actor: follower.server_id.clone(),
+        object: json!({
+            "id": follow_id,
+            "type": "Follow",
+            "actor": follower.server_id,
+            "object": to_unfollow.server_id,
+        }),
+        ..Default::default()
+    };
+    queue_activity(
+        &data,
+        &follower.id,
+        std::slice::from_ref(to_unfollow),
+        pool.clone(),
+    )
+    .await?;
+    follow.delete(pool.clone()).await?;
+    FullProfile::refresh_following_count(&follower.id, pool).await?;
+    Ok(())
+}
+
+pub async fn move_followers(
+    origin: &FullProfile,
+    target: &FullProfile,
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    //! Re-points the follows of local profiles from the moved profile to its new account.
+    //! The alias has to be verified before. Local profiles that block the target only drop the old follow.
+    for follower in FullFollower::get_follower_profiles(&origin.id, pool.clone()).await? {
+        if follower.instance != uuid!("00000000-0000-0000-0000-000000000000") {
+            continue;
+        }
+        let blocked =
+            FullBlock::get_by_profile_id_and_target_id(&follower.id, &target.id, pool.clone())
+                .await
+                .is_ok();
+        let following =
+            FullFollower::get_by_profile_id_and_follower_id(&target.id, &follower.id, pool.clone())
+                .await
+                .is_ok();
+        if !blocked && !following && follower.id != target.id {
+            follow_user(target, &follower, pool.clone()).await?;
+        }
+        unfollow_user(origin, &follower, pool.clone()).await?;
+    }
+    Ok(())
+}
+
+pub async fn block_user(
+    to_block: &FullProfile,
+    claims: &UserState,
+    pool: PgPool,
+) -> anyhow::Result<FullBlock> {
+    //! Stores the block, drops the follows in both directions and sends a `Block` to remote profiles
+    let is_local = to_block.instance == uuid!("00000000-0000-0000-0000-000000000000");
+    let block = CreateBlock {
+        profile_id: claims.profile_id,
+        target_id: to_block.id,
+        activity_id: (!is_local).then(|| format!("{}#blocks/{}", claims.server_id, Uuid::now_v7())),
+    }
+    .create(pool.clone())
+    .await?;
+    FullFollower::delete_between(&claims.profile_id, &to_block.id, pool.clone()).await?;
+    for profile_id in [&claims.profile_id, &to_block.id] {
+        FullProfile::refresh_follower_count(profile_id, pool.clone()).await?;
+        FullProfile::refresh_following_count(profile_id, pool.clone()).await?;
+    }
+    if is_local {
+        return Ok(block);
+    }
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: block.activity_id.clone().unwrap_or_default(),
+        type_field: "Block".to_string(),
         actor: claims.server_id.clone(),
-        object: to_follow.server_id.clone().to_string(),
-    };
-    let json_data = serde_json::to_string(&data).unwrap();
-    let data_signature = sign_post_request_with_body(
-        &to_follow.inbox,
-        json_data.as_ref(),
-        claims.private_key.clone(),
-        format!("{}#main-key", &to_follow.server_id),
-    )?;
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(
-        "Accept",
-        reqwest::header::HeaderValue::from_str("application/activity+json").unwrap(),
-    );
-    headers.insert(
-        "Digest",
-        reqwest::header::HeaderValue::from_str(&data_signature.1).unwrap(),
-    );
-    headers.insert(
-        "Signature",
-        reqwest::header::HeaderValue::from_str(&data_signature.1).unwrap(),
-    );
-    let ap_client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()
-        .unwrap();
-    ap_client
-        .post(to_follow.inbox.clone())
-        .body(json_data)
-        .send()
+        object: json!(to_block.server_id),
+        ..Default::default()
+    };
+    queue_activity(
+        &activity,
+        &claims.profile_id,
+        std::slice::from_ref(to_block),
+        pool,
+    )
+    .await?;
+    Ok(block)
+}
+
+pub async fn unblock_user(
+    to_unblock: &FullProfile,
+    claims: &UserState,
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    //! Sends an `Undo` for the block to remote profiles and drops it. Follows aren't restored.
+    let block = FullBlock::get_by_profile_id_and_target_id(
+        &claims.profile_id,
+        &to_unblock.id,
+        pool.clone(),
+    )
+    .await?;
+    if to_unblock.instance != uuid!("00000000-0000-0000-0000-000000000000") {
+        let block_id = block
+            .activity_id
+            .clone()
+            .unwrap_or_else(|| format!("{}#blocks/{}", claims.server_id, block.id));
+        let activity = Activity {
+            context: "https://www.w3.org/ns/activitystreams".to_string(),
+            id: format!("{block_id}/undo"),
+            type_field: "Undo".to_string(),
+            actor: claims.server_id.clone(),
+            object: json!({
+                "id": block_id,
+                "type": "Block",
+                "actor": claims.server_id,
+                "object": to_unblock.server_id,
+            }),
+            ..Default::default()
+        };
+        queue_activity(
+            &activity,
+            &claims.profile_id,
+            std::slice::from_ref(to_unblock),
+            pool.clone(),
+        )
         .await?;
+    }
+    block.delete(pool).await?;
+    Ok(())
+}
+
+pub async fn respond_to_follow(
+    follow: &FullFollower,
+    response_type: &str,
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    //! Sends an `Accept` or `Reject` for a received follow to the follower
+    let profile = FullProfile::get_by_id(&follow.profile_id, pool.clone()).await?;
+    let follower = FullProfile::get_by_id(&follow.follower_id, pool.clone()).await?;
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: format!(
+            "{}#{}s/follows/{}",
+            profile.server_id,
+            response_type.to_lowercase(),
+            follow.id
+        ),
+        type_field: response_type.to_string(),
+        actor: profile.server_id.clone(),
+        object: json!({
+            "id": follow.activity_id,
+            "type": "Follow",
+            "actor": follower.server_id,
+            "object": profile.server_id,
+        }),
+        ..Default::default()
+    };
+    queue_activity(&activity, &profile.id, &[follower], pool).await
+}
+
+fn get_status_server_id(status: &NoteJoinedModel) -> anyhow::Result<String> {
+    match &status.server_id {
+        Some(d) => Ok(d.clone()),
+        None => anyhow::bail!("Status has no ActivityPub id"),
+    }
+}
+
+pub async fn like_status(
+    status: &NoteJoinedModel,
+    claims: &UserState,
+    pool: PgPool,
+) -> anyhow::Result<FullLike> {
+    //! Stores the like and sends a `Like` to the author
+    let object_id = get_status_server_id(status)?;
+    let like = CreateLike {
+        profile_id: claims.profile_id,
+        note_id: status.note_id,
+        model_id: status.model_id,
+        activity_id: Some(format!("{}#likes/{}", claims.server_id, Uuid::now_v7())),
+    }
+    .create(pool.clone())
+    .await?;
+    let author = FullProfile::get_by_id(&status.profile_id, pool.clone()).await?;
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: like.activity_id.clone().unwrap_or_default(),
+        type_field: "Like".to_string(),
+        actor: claims.server_id.clone(),
+        object: json!(object_id),
+        ..Default::default()
+    };
+    queue_activity(&activity, &claims.profile_id, &[author], pool).await?;
+    Ok(like)
+}
+
+pub async fn unlike_status(
+    status: &NoteJoinedModel,
+    claims: &UserState,
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    //! Sends an `Undo` for the like and drops it
+    let object_id = get_status_server_id(status)?;
+    let like = FullLike::get_by_profile_id_and_object(
+        &claims.profile_id,
+        status.note_id,
+        status.model_id,
+        pool.clone(),
+    )
+    .await?;
+    let like_id = like
+        .activity_id
+        .clone()
+        .unwrap_or_else(|| format!("{}#likes/{}", claims.server_id, like.id));
+    let author = FullProfile::get_by_id(&status.profile_id, pool.clone()).await?;
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: format!("{like_id}/undo"),
+        type_field: "Undo".to_string(),
+        actor: claims.server_id.clone(),
+        object: json!({
+            "id": like_id,
+            "type": "Like",
+            "actor": claims.server_id,
+            "object": object_id,
+        }),
+        ..Default::default()
+    };
+    queue_activity(&activity, &claims.profile_id, &[author], pool.clone()).await?;
+    like.delete(pool).await?;
     Ok(())
 }
+
+async fn get_boost_recipients(
+    status: &NoteJoinedModel,
+    claims: &UserState,
+    pool: PgPool,
+) -> anyhow::Result<Vec<FullProfile>> {
+    //! Boosts go to the own followers and the author
+    let mut recipients =
+        FullFollower::get_follower_profiles(&claims.profile_id, pool.clone()).await?;
+    if !recipients.iter().any(|v| v.id == status.profile_id) {
+        recipients.push(FullProfile::get_by_id(&status.profile_id, pool).await?);
+    }
+    Ok(recipients)
+}
+
+pub async fn boost_status(
+    status: &NoteJoinedModel,
+    claims: &UserState,
+    pool: PgPool,
+) -> anyhow::Result<FullBoost> {
+    //! Stores the boost and sends an `Announce` to the followers and the author
+    let object_id = get_status_server_id(status)?;
+    let boost = CreateBoost {
+        profile_id: claims.profile_id,
+        note_id: status.note_id,
+        model_id: status.model_id,
+        activity_id: Some(format!("{}#boosts/{}", claims.server_id, Uuid::now_v7())),
+    }
+    .create(pool.clone())
+    .await?;
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: boost.activity_id.clone().unwrap_or_default(),
+        type_field: "Announce".to_string(),
+        actor: claims.server_id.clone(),
+        object: json!(object_id),
+        to: vec![PUBLIC_ADDRESS.to_string()],
+        cc: vec![
+            status.profile_server_id.clone(),
+            format!("{}/followers", claims.server_id),
+        ],
+    };
+    let recipients = get_boost_recipients(status, claims, pool.clone()).await?;
+    queue_activity(&activity, &claims.profile_id, &recipients, pool).await?;
+    Ok(boost)
+}
+
+pub async fn unboost_status(
+    status: &NoteJoinedModel,
+    claims: &UserState,
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    //! Sends an `Undo` for the boost and drops it
+    let object_id = get_status_server_id(status)?;
+    let boost = FullBoost::get_by_profile_id_and_object(
+        &claims.profile_id,
+        status.note_id,
+        status.model_id,
+        pool.clone(),
+    )
+    .await?;
+    let boost_id = boost
+        .activity_id
+        .clone()
+        .unwrap_or_else(|| format!("{}#boosts/{}", claims.server_id, boost.id));
+    let activity = Activity {
+        context: "https://www.w3.org/ns/activitystreams".to_string(),
+        id: format!("{boost_id}/undo"),
+        type_field: "Undo".to_string(),
+        actor: claims.server_id.clone(),
+        object: json!({
+            "id": boost_id,
+            "type": "Announce",
+            "actor": claims.server_id,
+            "object": object_id,
+        }),
+        to: vec![PUBLIC_ADDRESS.to_string()],
+        cc: vec![
+            status.profile_server_id.clone(),
+            format!("{}/followers", claims.server_id),
+        ],
+    };
+    let recipients = get_boost_recipients(status, claims, pool.clone()).await?;
+    queue_activity(&activity, &claims.profile_id, &recipients, pool.clone()).await?;
+    boost.delete(pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TEST_PROFILE_UUID;
+
+    const ALICE: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c68");
+    const BOB: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c69");
+
+    async fn get_profile(id: &Uuid, pool: &PgPool) -> FullProfile {
+        FullProfile::get_by_id(id, pool.clone()).await.unwrap()
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles")
+        )
+    )]
+    async fn test_move_followers(pool: PgPool) {
+        let origin = get_profile(&TEST_PROFILE_UUID, &pool).await;
+        let target = get_profile(&ALICE, &pool).await;
+        let bob = get_profile(&BOB, &pool).await;
+        follow_user(&origin, &bob, pool.clone()).await.unwrap();
+        move_followers(&origin, &target, pool.clone())
+            .await
+            .unwrap();
+        assert!(
+            FullFollower::get_by_profile_id_and_follower_id(&ALICE, &BOB, pool.clone())
+                .await
+                .is_ok()
+        );
+        assert!(FullFollower::get_by_profile_id_and_follower_id(
+            &TEST_PROFILE_UUID,
+            &BOB,
+            pool.clone()
+        )
+        .await
+        .is_err());
+        assert_eq!(get_profile(&ALICE, &pool).await.follower_count, 1);
+        assert_eq!(
+            get_profile(&TEST_PROFILE_UUID, &pool).await.follower_count,
+            0
+        );
+        assert_eq!(get_profile(&BOB, &pool).await.following_count, 1);
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles")
+        )
+    )]
+    async fn test_move_followers_skips_blocking_followers(pool: PgPool) {
+        let origin = get_profile(&TEST_PROFILE_UUID, &pool).await;
+        let target = get_profile(&ALICE, &pool).await;
+        let bob = get_profile(&BOB, &pool).await;
+        follow_user(&origin, &bob, pool.clone()).await.unwrap();
+        CreateBlock {
+            profile_id: BOB,
+            target_id: ALICE,
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        move_followers(&origin, &target, pool.clone())
+            .await
+            .unwrap();
+        assert!(
+            FullFollower::get_by_profile_id_and_follower_id(&ALICE, &BOB, pool.clone())
+                .await
+                .is_err()
+        );
+        assert_eq!(get_profile(&BOB, &pool).await.following_count, 0);
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles")
+        )
+    )]
+    async fn test_block_user_drops_follows(pool: PgPool) {
+        let claims = UserState::get_fake(pool.clone()).await;
+        let profile = get_profile(&TEST_PROFILE_UUID, &pool).await;
+        let alice = get_profile(&ALICE, &pool).await;
+        follow_user(&alice, &profile, pool.clone()).await.unwrap();
+        follow_user(&profile, &alice, pool.clone()).await.unwrap();
+        assert_eq!(get_profile(&ALICE, &pool).await.follower_count, 1);
+        let block = block_user(&alice, &claims, pool.clone()).await.unwrap();
+        // No Block is federated to local profiles
+        assert_eq!(block.activity_id, None);
+        for (profile_id, follower_id) in
+            [(&ALICE, &TEST_PROFILE_UUID), (&TEST_PROFILE_UUID, &ALICE)]
+        {
+            assert!(FullFollower::get_by_profile_id_and_follower_id(
+                profile_id,
+                follower_id,
+                pool.clone()
+            )
+            .await
+            .is_err());
+        }
+        for id in [&TEST_PROFILE_UUID, &ALICE] {
+            let profile = get_profile(id, &pool).await;
+            assert_eq!((profile.follower_count, profile.following_count), (0, 0));
+        }
+        unblock_user(&alice, &claims, pool.clone()).await.unwrap();
+        assert!(FullBlock::get_by_profile_id_and_target_id(
+            &TEST_PROFILE_UUID,
+            &ALICE,
+            pool.clone()
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::helpers::accounts::delete_account;
+use crate::helpers::activitypub::inbox_activities::handle_inbox_event;
+use shared::db::jobs::{
+    claim_job, get_backoff, get_due_job_ids, save_failed_job, save_success_job, FullJob,
+    JobResponseFailure, JobType,
+};
+use shared::db::processed_activities::ProcessedActivity;
+use shared::models::inbox::InboxEvent;
+use shared::AppState;
+use sqlx::postgres::PgListener;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+async fn process_activity(
+    job: &FullJob,
+    state: Arc<AppState>,
+) -> Result<String, JobResponseFailure> {
+    let event: InboxEvent = serde_json::from_str(job.input_data.as_deref().unwrap_or_default())
+        .map_err(|e| JobResponseFailure::never_try(&format!("Invalid activity: {e}")))?;
+    let event_type = event.event_type.clone();
+    handle_inbox_event(event, state)
+        .await
+        .map_err(|e| JobResponseFailure {
+            try_in: Some(get_backoff(job.tries)),
+            failure_message: format!("Handling {event_type} failed: {e}"),
+        })?;
+    Ok(event_type)
+}
+
+async fn process_account_deletion(
+    job: &FullJob,
+    state: Arc<AppState>,
+) -> Result<String, JobResponseFailure> {
+    let profile_id: Uuid = job
+        .input_data
+        .as_deref()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| JobResponseFailure::never_try(&format!("Invalid profile id: {e}")))?;
+    let unfinished = delete_account(&profile_id, state)
+        .await
+        .map_err(|e| JobResponseFailure {
+            try_in: Some(get_backoff(job.tries)),
+            failure_message: format!("Deleting account failed: {e}"),
+        })?;
+    if unfinished > 0 {
+        return Err(JobResponseFailure {
+            try_in: Some(get_backoff(job.tries)),
+            failure_message: format!("Waiting for {unfinished} deliveries of {profile_id}"),
+        });
+    }
+    Ok(profile_id.to_string())
+}
+
+async fn run_app_job(job_id: i32, state: Arc<AppState>) -> Result<(), sqlx::Error> {
+    let Some(job) = claim_job(job_id, true, state.pool.clone()).await? else {
+        return Ok(());
+    };
+    debug!("Processing app job {job_id}");
+    let start_time = Instant::now();
+    let data = match job.job_type {
+        JobType::ProcessInboxActivity => process_activity(&job, state.clone()).await,
+        JobType::DeleteAccount => process_account_deletion(&job, state.clone()).await,
+        _ => Err(JobResponseFailure::never_try(
+            "Job is processed by the worker",
+        )),
+    };
+    let elapsed = start_time.elapsed().as_millis();
+    match data {
+        Ok(d) => save_success_job(d, elapsed, job.id as i32, state.pool.clone()).await,
+        Err(e) => {
+            error!("App job {job_id} failed: {}", e.failure_message);
+            save_failed_job(e, elapsed, &job, state.pool.clone()).await
+        }
+    }
+}
+
+async fn run_due_app_jobs(state: Arc<AppState>) -> Result<(), sqlx::Error> {
+    for job_id in get_due_job_ids(true, state.pool.clone()).await? {
+        run_app_job(job_id, state.clone()).await?;
+    }
+    let expired = ProcessedActivity::delete_expired(state.pool.clone()).await?;
+    if expired > 0 {
+        debug!("Forgot {expired} processed activity ids");
+    }
+    Ok(())
+}
+
+pub async fn run_app_jobs(state: Arc<AppState>) {
+    //! Works through the queued inbox activities and account deletions. Runs inside the app instead
+    //! of the worker, as the handlers need the search index and the storage.
+    let mut listener = match PgListener::connect_with(&state.pool).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("App jobs can't listen for new jobs: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = listener.listen("worker_update").await {
+        error!("App jobs can't listen for new jobs: {}", e);
+        return;
+    }
+    let periodic_check_interval = Duration::from_secs(300); // 5 minutes
+    loop {
+        let res = tokio::select! {
+            notification = listener.recv() => match notification {
+                Ok(notification) => match notification.payload().parse::<i32>() {
+                    Ok(job_id) => run_app_job(job_id, state.clone()).await,
+                    Err(_) => Ok(()),
+                },
+                Err(e) => {
+                    debug!("Failed to receive notification: {:?}", e);
+                    Ok(())
+                }
+            },
+            _ = tokio::time::sleep(periodic_check_interval) => run_due_app_jobs(state.clone()).await,
+        };
+        if let Err(e) = res {
+            error!("App jobs failed: {}", e);
+        }
+    }
+}
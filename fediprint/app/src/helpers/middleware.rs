@@ -1,4 +1,4 @@
-use axum::extract::State;
+use axum::extract::{ConnectInfo, State};
 use axum::http::header::SET_COOKIE;
 use axum::http::{HeaderValue, Response};
 use axum::{
@@ -6,6 +6,7 @@ use axum::{
     http::{Request, StatusCode},
     middleware::Next,
 };
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum_extra::extract::cookie::{Cookie, CookieJar};
@@ -13,9 +14,12 @@ use serde::Serialize;
 use shared::AppState;
 
 use crate::helpers::auth::{
-    check_if_token_was_valid, generate_jwt, read_jwt, InputClaims, UserState,
+    check_if_token_was_valid, generate_jwt, get_client_ip, read_jwt, Claims, InputClaims, UserState,
 };
 use shared::db::account::FullAccount;
+use shared::db::sessions::FullSession;
+use shared::db::AccountRole;
+use shared::helpers::config::EmailVerification;
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
@@ -37,9 +41,16 @@ pub async fn auth_middleware(
     };
 
     let claims = read_jwt(token.clone(), data.env.jwt_secret.clone());
-    if claims.is_ok() {
+    if let Ok(claims) = claims {
+        // Revoked sessions and suspended accounts have to stop working right away, not only once the token expires
+        match FullSession::is_active(&claims.claims.sid, &claims.claims.sub, data.pool.clone())
+            .await
+        {
+            Ok(true) => (),
+            _ => return Err(StatusCode::UNAUTHORIZED),
+        }
         req.extensions_mut()
-            .insert(UserState::from_claims(claims.unwrap().claims, &data.env.jwt_secret).unwrap());
+            .insert(UserState::from_claims(claims.claims));
         return Ok(next.run(req).await);
     }
     tracing::debug!("Checking if token was valid");
@@ -47,9 +58,30 @@ pub async fn auth_middleware(
         Ok(d) => d,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
-    match FullAccount::get_by_id(&invalid_claims.sub, data.pool.clone()).await {
-        Ok(_) => (),
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    // Same rules as above, only active sessions get a new token
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| get_client_ip(req.headers(), addr));
+    match FullSession::touch(
+        &invalid_claims.sid,
+        &invalid_claims.sub,
+        ip.as_deref(),
+        data.pool.clone(),
+    )
+    .await
+    {
+        Ok(Some(_)) => (),
+        _ => return Err(StatusCode::UNAUTHORIZED),
+    }
+    // Role changes are picked up here
+    let account = match FullAccount::get_by_id(&invalid_claims.sub, data.pool.clone()).await {
+        Ok(d) => d,
+        _ => return Err(StatusCode::UNAUTHORIZED),
+    };
+    let invalid_claims = Claims {
+        role: account.role,
+        ..invalid_claims
     };
     tracing::debug!("Renewing jwt token");
 
@@ -61,7 +93,8 @@ pub async fn auth_middleware(
             username: invalid_claims.username.clone(),
             display_name: invalid_claims.display_name.clone(),
             server_id: invalid_claims.server_id.clone(),
-            private_key: invalid_claims.private_key.clone(),
+            role: invalid_claims.role,
+            sid: invalid_claims.sid,
         },
         data.env.jwt_secret.clone(),
     );
@@ -73,7 +106,7 @@ pub async fn auth_middleware(
         .build()
         .to_string();
     req.extensions_mut()
-        .insert(UserState::from_claims(invalid_claims, &data.env.jwt_secret).unwrap());
+        .insert(UserState::from_claims(invalid_claims));
     let mut resp = next.run(req).await;
     resp.headers_mut()
         .insert(SET_COOKIE, HeaderValue::from_str(&auth_cookie).unwrap());
@@ -107,3 +140,63 @@ pub async fn auth_middleware(
         (StatusCode::UNAUTHORIZED, Json(json_error))
     })?;*/
 }
+
+async fn require_role(
+    data: &AppState,
+    mut req: Request<Body>,
+    next: Next,
+    role: AccountRole,
+) -> Result<Response<Body>, StatusCode> {
+    //! Runs after `auth_middleware`. The role is read from the database, so revoked roles take
+    //! effect immediately and not only once the token is renewed.
+    let Some(claims) = req.extensions().get::<UserState>() else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    let account = match FullAccount::get_by_id(&claims.sub, data.pool.clone()).await {
+        Ok(d) => d,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+    if account.suspended || account.role < role {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Some(claims) = req.extensions_mut().get_mut::<UserState>() {
+        claims.role = account.role;
+    }
+    Ok(next.run(req).await)
+}
+
+pub async fn moderator_middleware(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, StatusCode> {
+    require_role(&data, req, next, AccountRole::Moderator).await
+}
+
+pub async fn admin_middleware(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, StatusCode> {
+    require_role(&data, req, next, AccountRole::Admin).await
+}
+
+pub async fn verified_middleware(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, StatusCode> {
+    //! Runs after `auth_middleware` on routes that publish content, which need a verified email
+    //! if the instance asks for it
+    if data.env.email_verification != EmailVerification::Posting {
+        return Ok(next.run(req).await);
+    }
+    let Some(claims) = req.extensions().get::<UserState>() else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    match FullAccount::get_by_id(&claims.sub, data.pool.clone()).await {
+        Ok(d) if d.verified.is_none() => Ok(next.run(req).await),
+        Ok(_) => Err(StatusCode::FORBIDDEN),
+        Err(_) => Err(StatusCode::UNAUTHORIZED),
+    }
+}
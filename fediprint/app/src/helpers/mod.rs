@@ -1,10 +1,13 @@
+pub mod accounts;
 pub mod activitypub;
 pub mod auth;
 pub mod interactions;
+pub mod jobs;
 pub mod middleware;
 pub mod printables;
 pub mod search;
 pub mod sign;
+pub mod viewer;
 
 use axum::body::Body;
 use axum::http::header::ToStrError;
@@ -117,30 +120,36 @@ impl IntoResponse for AppError {
     }
 }
 
-pub fn ensure_ap_header(headers: &HeaderMap) -> Result<(), Response> {
+pub fn ensure_ap_header(headers: &HeaderMap) -> Result<(), Box<Response>> {
     let is_ap_h = is_ap_header(headers)?;
     if is_ap_h {
         return Ok(());
     };
-    Err(Response::builder()
-        .status(StatusCode::NOT_ACCEPTABLE)
-        .body(Body::from(""))
-        .unwrap())
+    Err(Box::new(
+        Response::builder()
+            .status(StatusCode::NOT_ACCEPTABLE)
+            .body(Body::from(""))
+            .unwrap(),
+    ))
 }
 
-pub fn is_ap_header(headers: &HeaderMap) -> Result<bool, Response> {
+pub fn is_ap_header(headers: &HeaderMap) -> Result<bool, Box<Response>> {
     let accept_h = match headers.get("accept") {
         Some(d) => d.to_str().map_err(|_| {
-            Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::from("Failed to read header"))
-                .unwrap()
+            Box::new(
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to read header"))
+                    .unwrap(),
+            )
         })?,
         None => {
-            return Err(Response::builder()
-                .status(StatusCode::NOT_ACCEPTABLE)
-                .body(Body::from("Accept header empty"))
-                .unwrap());
+            return Err(Box::new(
+                Response::builder()
+                    .status(StatusCode::NOT_ACCEPTABLE)
+                    .body(Body::from("Accept header empty"))
+                    .unwrap(),
+            ));
         }
     };
     Ok(accept_h.contains("application/activity+json")
@@ -261,9 +261,7 @@ async fn save_file_to_s3(
         .to_str()
         .unwrap();
     let resp = client.get(url).send().await.unwrap();
-    let body_with_io_error = resp
-        .bytes_stream()
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+    let body_with_io_error = resp.bytes_stream().map_err(io::Error::other);
     let body_reader = StreamReader::new(body_with_io_error);
     futures::pin_mut!(body_reader);
     state
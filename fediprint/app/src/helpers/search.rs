@@ -23,6 +23,8 @@ pub struct MsModel {
     pub updated_at: DateTime<Utc>,
     pub tags: Vec<String>,
     pub profile_id: Uuid,
+    #[serde(default)]
+    pub instance_id: Option<Uuid>,
     pub record_type: RecordType,
     pub image_ids: Vec<Uuid>,
 }
@@ -40,6 +42,7 @@ impl MsModel {
                     updated_at: self.updated_at,
                     tags: self.tags,
                     profile_id: self.profile_id,
+                    instance_id: self.instance_id,
                     record_type: RecordType::Model,
                     image_ids: self.image_ids,
                 }],
@@ -60,6 +63,7 @@ impl MsModel {
 pub async fn index_model(
     model: &FullModelWithRelationsIds,
     profile_id: &Uuid,
+    instance_id: &Uuid,
     index: &Index,
 ) -> Result<(), Error> {
     if !model.published {
@@ -73,6 +77,7 @@ pub async fn index_model(
         summary: Some(model.summary.clone()),
         tags: model.tags.clone(),
         profile_id: *profile_id,
+        instance_id: Some(*instance_id),
         created_at: model.created_at,
         updated_at: model.updated_at,
         record_type: RecordType::Note,
@@ -84,7 +89,16 @@ pub async fn index_model(
     Ok(())
 }
 
-pub async fn index_note(note: &FullNote, profile_id: &Uuid, index: &Index) -> Result<(), Error> {
+pub async fn remove_from_index(id: &Uuid, index: &Index) -> Result<(), Error> {
+    MsModel::delete_if_existing(id, index).await
+}
+
+pub async fn index_note(
+    note: &FullNote,
+    profile_id: &Uuid,
+    instance_id: &Uuid,
+    index: &Index,
+) -> Result<(), Error> {
     if note.audience != EventAudience::Public {
         MsModel::delete_if_existing(&note.id, index).await?;
         return Ok(());
@@ -98,6 +112,7 @@ pub async fn index_note(note: &FullNote, profile_id: &Uuid, index: &Index) -> Re
         updated_at: note.updated_at,
         created_at: note.created_at,
         profile_id: *profile_id,
+        instance_id: Some(*instance_id),
         record_type: RecordType::Note,
         image_ids: vec![],
     }
@@ -151,18 +166,44 @@ impl SafeSearchResults {
     }
 }
 
+fn get_exclusion_filter(attribute: &str, ids: &[Uuid]) -> Option<String> {
+    if ids.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "NOT {attribute} IN [{}]",
+        ids.iter()
+            .map(|v| format!("\"{v}\""))
+            .collect::<Vec<String>>()
+            .join(", ")
+    ))
+}
+
 pub async fn search(
     query: &str,
     page: i64,
     page_size: i64,
+    hidden_instances: &[Uuid],
+    hidden_profiles: &[Uuid],
     index: &Index,
 ) -> Result<SafeSearchResults, Error> {
-    let resp: SearchResults<MsModel> = index
-        .search()
+    //! Results from the hidden instances and profiles are left out
+    let filter = [
+        get_exclusion_filter("instance_id", hidden_instances),
+        get_exclusion_filter("profile_id", hidden_profiles),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>()
+    .join(" AND ");
+    let mut search = index.search();
+    search
         .with_query(query)
         .with_hits_per_page(page_size as usize)
-        .with_page(page as usize)
-        .execute()
-        .await?;
+        .with_page(page as usize);
+    if !filter.is_empty() {
+        search.with_filter(&filter);
+    }
+    let resp: SearchResults<MsModel> = search.execute().await?;
     Ok(SafeSearchResults::from_ms(resp))
 }
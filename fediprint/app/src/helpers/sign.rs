@@ -1,89 +1,482 @@
 use anyhow::bail;
+use axum::http::{HeaderMap, Method, Uri};
 use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
 use openssl::hash::MessageDigest;
-use openssl::pkey::{PKey, Private};
+use openssl::pkey::PKey;
 use openssl::sha;
-use openssl::sign::Signer;
-use url::Url;
-
-fn sign_data_to_base64(key: PKey<Private>, data: &[u8]) -> anyhow::Result<String> {
-    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
-    signer.update(data)?;
-    let signature_vec = signer.sign_to_vec()?;
-    Ok(general_purpose::STANDARD.encode(signature_vec))
+use openssl::sign::Verifier;
+use regex::Regex;
+use shared::db::profile::FullProfile;
+use shared::helpers::keys::KeyService;
+use shared::helpers::profile::fetch_actor;
+use sqlx::PgPool;
+
+// Mastodon accepts signatures up to 12 hours old and allows for one hour of clock skew
+const SIGNATURE_EXPIRATION_WINDOW: i64 = 12 * 60 * 60;
+const SIGNATURE_CLOCK_SKEW: i64 = 60 * 60;
+
+lazy_static! {
+    static ref SIGNATURE_PARAM_REGEX: Regex = Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
 }
 
-fn get_now_in_format() -> String {
-    let dt = chrono::Local::now();
-    dt.format("%d %b %Y %H:%M:%S %Z").to_string() // 18 Dec 2019 10:08:46 GMT
+#[derive(Debug, PartialEq)]
+pub struct SignatureHeader {
+    pub key_id: String,
+    pub algorithm: Option<String>,
+    pub headers: Vec<String>,
+    pub signature: Vec<u8>,
 }
 
-pub fn sign_get_request_by_details(
-    path: &str,
-    host: &str,
-    key: PKey<Private>,
-    key_id: String,
-) -> anyhow::Result<String> {
-    // https://docs.joinmastodon.org/spec/security/#http-sign
-    let headers = "(request-target) host date";
-    let now = get_now_in_format();
-    let data_to_sign = format!("(request-target): get {path}\nhost: {host}\ndate: {now}");
-    let signature = sign_data_to_base64(key, data_to_sign.as_ref())?;
-    Ok(format!(
-        "keyId=\"{key_id}\",headers=\"{headers}\",signature=\"{signature}\""
-    ))
+impl SignatureHeader {
+    pub fn parse(raw: &str) -> anyhow::Result<SignatureHeader> {
+        let mut key_id: Option<String> = None;
+        let mut algorithm: Option<String> = None;
+        // If the headers parameter is missing, only the date header is signed
+        let mut headers: Vec<String> = vec!["date".to_string()];
+        let mut signature: Option<Vec<u8>> = None;
+        for caps in SIGNATURE_PARAM_REGEX.captures_iter(raw) {
+            let value = caps[2].to_string();
+            match &caps[1] {
+                "keyId" => key_id = Some(value),
+                "algorithm" => algorithm = Some(value),
+                "headers" => headers = value.split_whitespace().map(|h| h.to_lowercase()).collect(),
+                "signature" => signature = Some(general_purpose::STANDARD.decode(value)?),
+                _ => (),
+            }
+        }
+        let (Some(key_id), Some(signature)) = (key_id, signature) else {
+            bail!("Signature header is missing keyId or signature")
+        };
+        Ok(SignatureHeader {
+            key_id,
+            algorithm,
+            headers,
+            signature,
+        })
+    }
+
+    /// The actor the key belongs to, e.g. `https://mastodon.online/users/Mawoka`
+    /// for the key `https://mastodon.online/users/Mawoka#main-key`
+    pub fn actor_id(&self) -> &str {
+        self.key_id.split('#').next().unwrap_or(&self.key_id)
+    }
 }
 
-pub fn sign_get_request_by_url(
-    url: String,
-    key: PKey<Private>,
-    key_id: String,
+pub fn build_signing_string(
+    signed_headers: &[String],
+    method: &Method,
+    path_and_query: &str,
+    headers: &HeaderMap,
 ) -> anyhow::Result<String> {
-    let url = Url::parse(&url)?;
-    let host = match url.host_str() {
-        Some(d) => d,
-        None => bail!("Host is none"),
-    };
-    let path = url.path();
-    sign_get_request_by_details(path, host, key, key_id)
+    let mut lines: Vec<String> = vec![];
+    for name in signed_headers {
+        if name == "(request-target)" {
+            lines.push(format!(
+                "(request-target): {} {path_and_query}",
+                method.as_str().to_lowercase()
+            ));
+            continue;
+        }
+        let values = headers
+            .get_all(name.as_str())
+            .iter()
+            .map(|v| v.to_str())
+            .collect::<Result<Vec<&str>, _>>()?;
+        if values.is_empty() {
+            bail!("Signed header {name} is missing")
+        }
+        lines.push(format!("{name}: {}", values.join(", ")));
+    }
+    Ok(lines.join("\n"))
 }
 
-pub fn sign_post_request_with_hash(
-    path: &str,
-    host: &str,
-    body_hash: &str,
-    key: PKey<Private>,
-    key_id: String,
-) -> anyhow::Result<String> {
-    let headers = "(request-target) host date digest";
-    let now = get_now_in_format();
-    let data_to_sign = format!(
-        "(request-target): get {path}\nhost: {host}\ndate: {now}\ndigest: sha-256={body_hash}"
-    );
-    let signature = sign_data_to_base64(key, data_to_sign.as_ref())?;
-    Ok(format!(
-        "keyId=\"{key_id}\",headers=\"{headers}\",signature=\"{signature}\""
-    ))
+pub fn verify_signature_with_key(
+    public_key_pem: &str,
+    data: &[u8],
+    signature: &[u8],
+) -> anyhow::Result<bool> {
+    let key = PKey::public_key_from_pem(public_key_pem.as_bytes())?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+    verifier.update(data)?;
+    Ok(verifier.verify(signature)?)
 }
 
-pub fn sign_post_request_with_body(
-    url: &str,
-    body_hash: &[u8],
-    key: PKey<Private>,
-    key_id: String,
-) -> anyhow::Result<(String, String)> {
-    // (Signature, Digest) -header returns
+pub fn check_digest(headers: &HeaderMap, body: &[u8]) -> anyhow::Result<()> {
+    let digest = match headers.get("digest") {
+        Some(d) => d.to_str()?,
+        None => bail!("Digest header is missing"),
+    };
     let mut hasher = sha::Sha256::new();
-    hasher.update(body_hash);
+    hasher.update(body);
     let hash = general_purpose::STANDARD.encode(hasher.finish());
-    let url = Url::parse(url)?;
-    let host = match url.host_str() {
-        Some(d) => d,
-        None => bail!("Host is none"),
+    // The header may contain multiple digests, we only need the SHA-256 one to match
+    for part in digest.split(',') {
+        if let Some((algorithm, value)) = part.trim().split_once('=') {
+            if algorithm.eq_ignore_ascii_case("sha-256") && value == hash {
+                return Ok(());
+            }
+        }
+    }
+    bail!("Digest does not match body")
+}
+
+pub fn check_date(headers: &HeaderMap) -> anyhow::Result<()> {
+    let date = match headers.get("date") {
+        Some(d) => DateTime::parse_from_rfc2822(d.to_str()?)?,
+        None => bail!("Date header is missing"),
+    };
+    let age = Utc::now().timestamp() - date.timestamp();
+    if age > SIGNATURE_EXPIRATION_WINDOW {
+        bail!("Signature expired")
+    }
+    if age < -SIGNATURE_CLOCK_SKEW {
+        bail!("Signature date is in the future")
+    }
+    Ok(())
+}
+
+/// Verifies a Mastodon-compatible HTTP signature and returns the profile the key belongs to.
+/// The body has to be passed for POST requests, so the Digest header can be checked.
+pub async fn verify_request(
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Option<&[u8]>,
+    key_service: &KeyService,
+    pool: PgPool,
+) -> anyhow::Result<FullProfile> {
+    let signature = match headers.get("signature") {
+        Some(d) => SignatureHeader::parse(d.to_str()?)?,
+        None => bail!("Signature header is missing"),
     };
-    let path = url.path();
-    Ok((
-        sign_post_request_with_hash(path, host, &hash, key, key_id)?,
-        format!("sha-256={hash}"),
-    ))
+    if let Some(algorithm) = &signature.algorithm {
+        if algorithm != "rsa-sha256" && algorithm != "hs2019" {
+            bail!("Unsupported signature algorithm {algorithm}")
+        }
+    }
+    let mut required_headers = vec!["(request-target)", "host", "date"];
+    if body.is_some() {
+        required_headers.push("digest");
+    }
+    for required in required_headers {
+        if !signature.headers.iter().any(|h| h == required) {
+            bail!("{required} is not part of the signed headers")
+        }
+    }
+    check_date(headers)?;
+    if let Some(body) = body {
+        check_digest(headers, body)?;
+    }
+    let path_and_query = uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or(uri.path());
+    let signing_string = build_signing_string(&signature.headers, method, path_and_query, headers)?;
+
+    let verify = |public_key: &str| {
+        verify_signature_with_key(public_key, signing_string.as_bytes(), &signature.signature)
+    };
+    let profile = match FullProfile::get_by_server_id(signature.actor_id(), pool.clone()).await {
+        Ok(d) => d,
+        Err(sqlx::Error::RowNotFound) => {
+            // Unknown actors are only stored once their key verified the signature
+            let actor = fetch_actor(signature.actor_id(), key_service, pool.clone()).await?;
+            if actor.id != signature.actor_id() {
+                bail!("Key {} doesn't belong to {}", signature.key_id, actor.id)
+            }
+            if !verify(&actor.public_key.public_key_pem)? {
+                bail!("Signature is invalid")
+            }
+            return FullProfile::create_from_fetched_actor(actor, key_service, pool).await;
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if verify(&profile.public_key)? {
+        return Ok(profile);
+    }
+    // The cached key might be outdated, so fetch the current one before giving up
+    if !FullProfile::claim_key_refresh(&profile.id, pool.clone()).await? {
+        bail!("Signature is invalid")
+    }
+    let profile = profile.refresh_public_key(key_service, pool).await?;
+    if verify(&profile.public_key)? {
+        return Ok(profile);
+    }
+    bail!("Signature is invalid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use openssl::pkey::Private;
+    use openssl::rsa::Rsa;
+    use shared::helpers::config::{Config, EmailVerification, SmtpData};
+    use shared::helpers::sign::{sign_data_to_base64, sign_post_request_with_body};
+
+    fn key_service() -> KeyService {
+        KeyService::new(&Config {
+            database_url: String::new(),
+            jwt_secret: "secret".to_string(),
+            key_encryption_key: "kek".to_string(),
+            public_url: "https://fedi.print".to_string(),
+            base_domain: "fedi.print".to_string(),
+            s3_base_url: String::new(),
+            s3_region: String::new(),
+            s3_username: String::new(),
+            s3_password: String::new(),
+            s3_bucket_name: String::new(),
+            meilisearch_url: String::new(),
+            meilisearch_key: String::new(),
+            registration_disabled: false,
+            authorized_fetch: false,
+            email_verification: EmailVerification::Optional,
+            admins: vec![],
+            smtp: SmtpData {
+                server: String::new(),
+                username: String::new(),
+                password: String::new(),
+                port: 0,
+                email: String::new(),
+            },
+            stripe: None,
+        })
+    }
+
+    fn signed_headers(body: &[u8], date: &str, key: PKey<Private>) -> HeaderMap {
+        let mut hasher = sha::Sha256::new();
+        hasher.update(body);
+        let digest = format!(
+            "SHA-256={}",
+            general_purpose::STANDARD.encode(hasher.finish())
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("localhost.local"));
+        headers.insert("date", HeaderValue::from_str(date).unwrap());
+        headers.insert("digest", HeaderValue::from_str(&digest).unwrap());
+        let signed: Vec<String> = vec!["(request-target)", "host", "date", "digest"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let signing_string = build_signing_string(
+            &signed,
+            &Method::POST,
+            "/api/v1/user/testuser/inbox",
+            &headers,
+        )
+        .unwrap();
+        let signature = sign_data_to_base64(key, signing_string.as_bytes()).unwrap();
+        headers.insert(
+            "signature",
+            HeaderValue::from_str(&format!(
+                "keyId=\"https://mastodon.online/users/Mawoka#main-key\",algorithm=\"rsa-sha256\",headers=\"{}\",signature=\"{signature}\"",
+                signed.join(" ")
+            ))
+            .unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_parse_signature_header() {
+        let header = SignatureHeader::parse(
+            r#"keyId="https://mastodon.online/users/Mawoka#main-key",algorithm="rsa-sha256",headers="(request-target) host date digest",signature="YWJj""#,
+        )
+        .unwrap();
+        assert_eq!(header.actor_id(), "https://mastodon.online/users/Mawoka");
+        assert_eq!(header.algorithm, Some("rsa-sha256".to_string()));
+        assert_eq!(
+            header.headers,
+            vec!["(request-target)", "host", "date", "digest"]
+        );
+        assert_eq!(header.signature, b"abc");
+        assert!(SignatureHeader::parse(r#"algorithm="rsa-sha256""#).is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let public_key = String::from_utf8(rsa.public_key_to_pem().unwrap()).unwrap();
+        let key = PKey::from_rsa(rsa).unwrap();
+        let body = br#"{"type":"Follow"}"#;
+        let headers = signed_headers(body, &Utc::now().to_rfc2822(), key);
+        let signature =
+            SignatureHeader::parse(headers.get("signature").unwrap().to_str().unwrap()).unwrap();
+        check_date(&headers).unwrap();
+        check_digest(&headers, body).unwrap();
+        let signing_string = build_signing_string(
+            &signature.headers,
+            &Method::POST,
+            "/api/v1/user/testuser/inbox",
+            &headers,
+        )
+        .unwrap();
+        assert!(verify_signature_with_key(
+            &public_key,
+            signing_string.as_bytes(),
+            &signature.signature
+        )
+        .unwrap());
+        // Forged body
+        assert!(check_digest(&headers, br#"{"type":"Delete"}"#).is_err());
+        // Different target
+        let signing_string = build_signing_string(
+            &signature.headers,
+            &Method::POST,
+            "/api/v1/user/otheruser/inbox",
+            &headers,
+        )
+        .unwrap();
+        assert!(!verify_signature_with_key(
+            &public_key,
+            signing_string.as_bytes(),
+            &signature.signature
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_sign_post_request() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let public_key = String::from_utf8(rsa.public_key_to_pem().unwrap()).unwrap();
+        let key = PKey::from_rsa(rsa).unwrap();
+        let body = br#"{"type":"Accept"}"#;
+        let (signature, digest, date) = sign_post_request_with_body(
+            "http://localhost:8000/api/v1/user/testuser/inbox",
+            body,
+            key,
+            "http://localhost:8000/api/v1/user/other#main-key".to_string(),
+        )
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("localhost:8000"));
+        headers.insert("date", HeaderValue::from_str(&date).unwrap());
+        headers.insert("digest", HeaderValue::from_str(&digest).unwrap());
+        check_date(&headers).unwrap();
+        check_digest(&headers, body).unwrap();
+        let signature = SignatureHeader::parse(&signature).unwrap();
+        let signing_string = build_signing_string(
+            &signature.headers,
+            &Method::POST,
+            "/api/v1/user/testuser/inbox",
+            &headers,
+        )
+        .unwrap();
+        assert!(verify_signature_with_key(
+            &public_key,
+            signing_string.as_bytes(),
+            &signature.signature
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_check_date() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let stale = Utc::now() - chrono::Duration::hours(13);
+        let headers = signed_headers(b"", &stale.to_rfc2822(), key.clone());
+        assert!(check_date(&headers).is_err());
+        let future = Utc::now() + chrono::Duration::hours(2);
+        let headers = signed_headers(b"", &future.to_rfc2822(), key);
+        assert!(check_date(&headers).is_err());
+    }
+
+    async fn store_remote_profile(public_key: &str, pool: &PgPool) {
+        let instance: uuid::Uuid = sqlx::query_scalar(
+            r#"INSERT INTO instances (base_url, software) VALUES ('https://mastodon.online', 'mastodon') RETURNING id"#,
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO profile (username, server_id, display_name, summary, inbox, outbox, public_key, instance)
+            VALUES ('Mawoka', 'https://mastodon.online/users/Mawoka', 'Mawoka', '',
+            'https://mastodon.online/users/Mawoka/inbox', 'https://mastodon.online/users/Mawoka/outbox', $1, $2)"#,
+        )
+        .bind(public_key)
+        .bind(instance)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_verify_request(pool: PgPool) {
+        let rsa = Rsa::generate(2048).unwrap();
+        let public_key = String::from_utf8(rsa.public_key_to_pem().unwrap()).unwrap();
+        let key = PKey::from_rsa(rsa).unwrap();
+        store_remote_profile(&public_key, &pool).await;
+        let uri: Uri = "/api/v1/user/testuser/inbox".parse().unwrap();
+        let body = br#"{"type":"Follow"}"#;
+        let key_service = key_service();
+        let headers = signed_headers(body, &Utc::now().to_rfc2822(), key);
+        let profile = verify_request(
+            &Method::POST,
+            &uri,
+            &headers,
+            Some(body),
+            &key_service,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(profile.server_id, "https://mastodon.online/users/Mawoka");
+        // Forged body
+        assert!(verify_request(
+            &Method::POST,
+            &uri,
+            &headers,
+            Some(br#"{"type":"Delete"}"#),
+            &key_service,
+            pool.clone()
+        )
+        .await
+        .is_err());
+        // Unsigned request
+        let mut unsigned = headers.clone();
+        unsigned.remove("signature");
+        assert!(verify_request(
+            &Method::POST,
+            &uri,
+            &unsigned,
+            Some(body),
+            &key_service,
+            pool.clone()
+        )
+        .await
+        .is_err());
+        // The digest has to be signed for requests with a body
+        let mut without_digest = headers.clone();
+        let signature = headers.get("signature").unwrap().to_str().unwrap().replace(
+            "headers=\"(request-target) host date digest\"",
+            "headers=\"(request-target) host date\"",
+        );
+        without_digest.insert("signature", HeaderValue::from_str(&signature).unwrap());
+        assert!(verify_request(
+            &Method::POST,
+            &uri,
+            &without_digest,
+            Some(body),
+            &key_service,
+            pool.clone()
+        )
+        .await
+        .is_err());
+        // Unsupported algorithm
+        let mut hmac = headers.clone();
+        let signature = headers
+            .get("signature")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .replace("rsa-sha256", "hmac-sha256");
+        hmac.insert("signature", HeaderValue::from_str(&signature).unwrap());
+        assert!(
+            verify_request(&Method::POST, &uri, &hmac, Some(body), &key_service, pool)
+                .await
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,107 @@
+use crate::helpers::auth::read_jwt;
+use crate::helpers::sign::{verify_request, SignatureHeader};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::CookieJar;
+use shared::db::instances::FullInstance;
+use shared::db::sessions::FullSession;
+use shared::db::visibility::Viewer;
+use shared::db::InstanceModeration;
+use shared::helpers::keys::KeyService;
+use shared::AppState;
+use tracing::debug;
+use url::Url;
+use uuid::Uuid;
+
+async fn get_session_profile_id(cookie_jar: &CookieJar, state: &AppState) -> Option<Uuid> {
+    //! Only active sessions count, same as in the auth middleware
+    let cookie = cookie_jar.get("authorization_key")?;
+    let claims = read_jwt(cookie.value().to_string(), state.env.jwt_secret.clone())
+        .ok()?
+        .claims;
+    FullSession::is_active(&claims.sid, &claims.sub, state.pool.clone())
+        .await
+        .ok()?
+        .then_some(claims.profile_id)
+}
+
+pub async fn get_viewer(
+    cookie_jar: &CookieJar,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Viewer {
+    //! Takes the viewer from the session cookie or else from a verified HTTP signature.
+    //! Requests without either are anonymous and only see public notes and published models.
+    if let Some(profile_id) = get_session_profile_id(cookie_jar, state).await {
+        return Viewer::from_profile_id(&profile_id);
+    }
+    if headers.contains_key("signature") {
+        match verify_request(
+            method,
+            uri,
+            headers,
+            None,
+            &KeyService::new(&state.env),
+            state.pool.clone(),
+        )
+        .await
+        {
+            Ok(d) => return Viewer::from_profile_id(&d.id),
+            Err(e) => debug!("Ignoring invalid signature of fetch: {}", e),
+        }
+    }
+    Viewer::anonymous()
+}
+
+pub async fn get_authorized_viewer(
+    cookie_jar: &CookieJar,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<Viewer, Response> {
+    //! Same as `get_viewer`, but in authorized fetch mode remote servers have to sign their requests
+    //! and suspended instances are refused. Local users are still let in by their session.
+    if !state.env.authorized_fetch {
+        return Ok(get_viewer(cookie_jar, method, uri, headers, state).await);
+    }
+    if let Some(profile_id) = get_session_profile_id(cookie_jar, state).await {
+        return Ok(Viewer::from_profile_id(&profile_id));
+    }
+    let Some(signature) = headers
+        .get("signature")
+        .and_then(|d| d.to_str().ok())
+        .and_then(|d| SignatureHeader::parse(d).ok())
+    else {
+        return Err(StatusCode::UNAUTHORIZED.into_response());
+    };
+    // Check the block list before the key of the signer gets fetched
+    let host = Url::parse(signature.actor_id())
+        .ok()
+        .and_then(|d| d.host_str().map(|d| d.to_string()))
+        .unwrap_or_default();
+    if FullInstance::get_moderation_by_host(&host, state.pool.clone()).await
+        == InstanceModeration::Suspended
+    {
+        debug!("Refusing fetch from suspended instance {}", host);
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    match verify_request(
+        method,
+        uri,
+        headers,
+        None,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => Ok(Viewer::from_profile_id(&d.id)),
+        Err(e) => {
+            debug!("Refusing fetch with invalid signature: {}", e);
+            Err(StatusCode::UNAUTHORIZED.into_response())
+        }
+    }
+}
@@ -1,11 +1,17 @@
 use dotenvy::dotenv;
-use shared::db::instances::CreateInstance;
+use shared::db::account::FullAccount;
+use shared::db::instances::{CreateInstance, InstanceActor};
 use shared::helpers::config::Config;
+use shared::helpers::keys::KeyService;
 use shared::AppState;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::helpers::middleware::auth_middleware;
+use crate::helpers::jobs::run_app_jobs;
+use crate::helpers::middleware::{
+    admin_middleware, auth_middleware, moderator_middleware, verified_middleware,
+};
 use crate::routes::api::v1;
 use awscreds::Credentials;
 use axum::extract::DefaultBodyLimit;
@@ -94,6 +100,17 @@ pub async fn get_state(pool: Option<PgPool>) -> Arc<AppState> {
     .create_local(sqlx_pool.clone())
     .await
     .expect("Could not save instance data to DB!");
+    let key_service = KeyService::new(&config);
+    InstanceActor::create_local_if_missing(&key_service, sqlx_pool.clone())
+        .await
+        .expect("Could not create the instance actor!");
+    FullAccount::promote_admins(&config.admins, sqlx_pool.clone())
+        .await
+        .expect("Could not promote the configured admins!");
+    key_service
+        .encrypt_plain_keys(sqlx_pool.clone())
+        .await
+        .expect("Could not encrypt the private keys!");
 
     let client =
         meilisearch_sdk::Client::new(&config.meilisearch_url, Some(&config.meilisearch_key));
@@ -102,7 +119,13 @@ pub async fn get_state(pool: Option<PgPool>) -> Arc<AppState> {
     client.create_index("fedi_print", Some("id")).await.unwrap();
     client
         .index("fedi_print")
-        .set_filterable_attributes(&["created_at", "tags", "record_type", "profile_id"])
+        .set_filterable_attributes(&[
+            "created_at",
+            "tags",
+            "record_type",
+            "profile_id",
+            "instance_id",
+        ])
         .await
         .unwrap();
     client
@@ -122,6 +145,7 @@ pub async fn get_state(pool: Option<PgPool>) -> Arc<AppState> {
 
 pub async fn get_server() -> Router {
     let state = get_state(None).await;
+    tokio::spawn(run_app_jobs(state.clone()));
 
     let cors = CorsLayer::new()
         // allow `GET` and `POST` when accessing the resource
@@ -134,6 +158,14 @@ pub async fn get_server() -> Router {
         .layer(cors)
         .route("/api/v1/auth/create", post(v1::auth::create_user))
         .route("/api/v1/auth/login", post(v1::auth::login))
+        .route("/api/v1/auth/login/2fa", post(v1::auth::login_two_factor))
+        .route("/api/v1/auth/forgot", post(v1::auth::forgot_password))
+        .route("/api/v1/auth/reset", post(v1::auth::reset_password))
+        .route("/api/v1/auth/verify", get(v1::auth::verify_email))
+        .route(
+            "/api/v1/auth/verify/resend",
+            post(v1::auth::resend_verification_email),
+        )
         .route(
             "/api/v1/auth/me",
             get(v1::auth::get_me_handler).route_layer(middleware::from_fn_with_state(
@@ -141,6 +173,69 @@ pub async fn get_server() -> Router {
                 auth_middleware,
             )),
         )
+        .route(
+            "/api/v1/auth/logout",
+            post(v1::sessions::logout).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/logout_everywhere",
+            post(v1::sessions::logout_everywhere).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/sessions",
+            get(v1::sessions::get_sessions).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/sessions/:id",
+            delete(v1::sessions::revoke_session).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/2fa",
+            get(v1::two_factor::get_two_factor).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/2fa/setup",
+            post(v1::two_factor::setup_two_factor).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/2fa/confirm",
+            post(v1::two_factor::confirm_two_factor).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/2fa/disable",
+            post(v1::two_factor::disable_two_factor).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/auth/delete",
+            post(v1::auth::delete_own_account).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
         .route(
             "/.well-known/webfinger",
             get(routes::well_known::webfinger::handler),
@@ -161,7 +256,158 @@ pub async fn get_server() -> Router {
             "/api/v1/user/:username/following",
             get(v1::activitypub::profile::get_following),
         )
-        /*
+        .route(
+            "/api/v1/manage/report",
+            post(v1::reports::create_report).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/admin/reports",
+            get(v1::admin::get_reports)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    moderator_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/reports/:id",
+            post(v1::admin::set_report_status)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    moderator_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/models/:id/unpublish",
+            post(v1::admin::unpublish_model)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    moderator_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/suspend",
+            post(v1::admin::set_account_suspended)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    moderator_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/role",
+            post(v1::admin::set_account_role)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id",
+            delete(v1::admin::delete_account)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/profiles/:id/purge",
+            post(v1::admin::purge_remote_profile)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/instances",
+            get(v1::admin::get_moderated_instances)
+                .post(v1::admin::set_instance_moderation)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/instances/export",
+            get(v1::admin::export_instance_moderation)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/instances/import",
+            post(v1::admin::import_instance_moderation)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/stats",
+            get(v1::admin::get_stats)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/admin/audit_log",
+            get(v1::admin::get_audit_log)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
         .route(
             "/api/v1/manage/follow",
             post(v1::manage::follow_user_route).route_layer(middleware::from_fn_with_state(
@@ -169,14 +415,146 @@ pub async fn get_server() -> Router {
                 auth_middleware,
             )),
         )
-        */
+        .route(
+            "/api/v1/manage/unfollow",
+            post(v1::manage::unfollow_user_route).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/block",
+            post(v1::manage::block_user_route).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/unblock",
+            post(v1::manage::unblock_user_route).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/blocks",
+            get(v1::manage::get_blocks).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/mute",
+            post(v1::manage::mute_user_route).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/unmute",
+            post(v1::manage::unmute_user_route).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/mutes",
+            get(v1::manage::get_mutes).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/aliases",
+            get(v1::migration::get_aliases)
+                .post(v1::migration::set_aliases)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/manage/move",
+            post(v1::migration::move_account).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/keys/rotate",
+            post(v1::keys::rotate_key).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/follow_requests",
+            get(v1::follow_requests::get_follow_requests).route_layer(
+                middleware::from_fn_with_state(state.clone(), auth_middleware),
+            ),
+        )
+        .route(
+            "/api/v1/manage/follow_requests/accept",
+            post(v1::follow_requests::accept_follow_request).route_layer(
+                middleware::from_fn_with_state(state.clone(), auth_middleware),
+            ),
+        )
+        .route(
+            "/api/v1/manage/follow_requests/reject",
+            post(v1::follow_requests::reject_follow_request).route_layer(
+                middleware::from_fn_with_state(state.clone(), auth_middleware),
+            ),
+        )
+        .route(
+            "/api/v1/manage/followers/manual_approval",
+            put(v1::follow_requests::set_manual_approval).route_layer(
+                middleware::from_fn_with_state(state.clone(), auth_middleware),
+            ),
+        )
         .route(
             "/api/v1/manage/interact/note",
-            post(v1::interact::post_note).route_layer(middleware::from_fn_with_state(
+            post(v1::interact::post_note)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    verified_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/manage/interact/like",
+            post(v1::interact::like).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/interact/unlike",
+            post(v1::interact::unlike).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/interact/boost",
+            post(v1::interact::boost).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/manage/interact/unboost",
+            post(v1::interact::unboost).route_layer(middleware::from_fn_with_state(
                 state.clone(),
                 auth_middleware,
             )),
         )
+        .route(
+            "/api/v1/actor",
+            get(v1::activitypub::profile::get_instance_actor),
+        )
         .route(
             "/api/v1/user/:username/outbox",
             get(v1::activitypub::boxes::get_outbox),
@@ -185,6 +563,11 @@ pub async fn get_server() -> Router {
             "/api/v1/user/:username/inbox",
             post(v1::activitypub::boxes::post_user_inbox),
         )
+        .route(
+            "/api/v1/inbox",
+            post(v1::activitypub::boxes::post_shared_inbox),
+        )
+        .route("/inbox", post(v1::activitypub::boxes::post_shared_inbox))
         .route(
             "/api/v1/printers/create",
             post(v1::printers::create_printer).route_layer(middleware::from_fn_with_state(
@@ -210,6 +593,10 @@ pub async fn get_server() -> Router {
             "/api/v1/storage/upload",
             post(v1::storage::upload_file)
                 .layer(DefaultBodyLimit::max(52_428_800))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    verified_middleware,
+                ))
                 .route_layer(middleware::from_fn_with_state(
                     state.clone(),
                     auth_middleware,
@@ -243,10 +630,15 @@ pub async fn get_server() -> Router {
         )
         .route(
             "/api/v1/model/create",
-            post(v1::model::create_model).route_layer(middleware::from_fn_with_state(
-                state.clone(),
-                auth_middleware,
-            )),
+            post(v1::model::create_model)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    verified_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
         )
         .route(
             "/api/v1/model/list",
@@ -262,11 +654,31 @@ pub async fn get_server() -> Router {
                 auth_middleware,
             )),
         )
+        .route(
+            "/api/v1/model/update",
+            put(v1::model::update_model)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    verified_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    auth_middleware,
+                )),
+        )
+        .route(
+            "/api/v1/model/delete",
+            delete(v1::model::delete_model).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            )),
+        )
         .route(
             "/api/v1/model/public/newest",
             get(v1::model::get_newest_models),
         )
         .route("/api/v1/model/:id", get(v1::model::get_model))
+        .route("/api/v1/model/:id/thread", get(v1::model::get_thread))
         .route("/api/v1/search/model", get(v1::model::search_models))
         .route(
             "/api/v1/links/printables",
@@ -287,6 +699,10 @@ pub async fn get_server() -> Router {
             ),
         )
         .route("/api/v1/statuses/:id", get(v1::statuses::get_status))
+        .route(
+            "/api/v1/statuses/:id/replies",
+            get(v1::statuses::get_replies),
+        )
         .route("/api/v1/nodeinfo/2.0", get(v1::nodeinfo::get_nodeinfo))
         .route(
             "/api/v1/search/profiles",
@@ -339,5 +755,12 @@ async fn main() {
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
-    axum::serve(listener, get_server().await).await.unwrap();
+    axum::serve(
+        listener,
+        get_server()
+            .await
+            .into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -1,70 +1,82 @@
-use crate::helpers::activitypub::inbox_activities::{
-    handle_accept, handle_add, handle_announce, handle_create, handle_delete, handle_follow,
-    handle_like, handle_reject, handle_remove, handle_undo, handle_update,
-};
+use crate::helpers::activitypub::inbox_activities::is_same_host;
+use crate::helpers::sign::verify_request;
+use crate::helpers::viewer::get_authorized_viewer;
 use crate::helpers::{ensure_ap_header, AppResult};
-use axum::body::Body;
+use axum::body::{Body, Bytes};
 use axum::extract::{Path, Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
-use axum::Json;
+use axum_extra::extract::cookie::CookieJar;
 use serde_derive::Deserialize;
 use serde_json::json;
+use shared::db::blocks::FullBlock;
+use shared::db::instances::FullInstance;
+use shared::db::jobs;
+use shared::db::mentions::FullMention;
+use shared::db::processed_activities::ProcessedActivity;
 use shared::db::profile::FullProfile;
+use shared::db::InstanceModeration;
+use shared::helpers::keys::KeyService;
 use shared::models::activitypub::{
-    FocalPoint, NoteBoxItemFirst, NoteBoxItemObject, NoteBoxItemReplies, NoteJoinedModel,
-    OrderedCollection, OrderedItem, OutboxContext, OutboxDataPage, Tag,
+    get_addressing, Attachment, FocalPoint, NoteBoxItemFirst, NoteBoxItemObject,
+    NoteBoxItemReplies, NoteJoinedModel, OrderedCollection, OrderedItem, OutboxContext,
+    OutboxDataPage, Tag,
 };
 use shared::models::inbox::InboxEvent;
 use shared::AppState;
 use std::sync::Arc;
 use tracing::{debug, error};
+use url::Url;
 use uuid::{uuid, Uuid};
 
 #[derive(Deserialize)]
 pub struct GetBox {
     pub page: Option<bool>,
     pub min_id: Option<Uuid>,
+    pub max_id: Option<Uuid>,
 }
 
+const OUTBOX_PAGE_SIZE: i64 = 20;
+
 pub async fn get_outbox(
     Path(username): Path<String>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
     headers: HeaderMap,
     query: Query<GetBox>,
     State(state): State<Arc<AppState>>,
 ) -> AppResult<impl IntoResponse> {
     match ensure_ap_header(&headers) {
         Ok(_) => (),
+        Err(e) => return Ok(*e),
+    };
+    let viewer = match get_authorized_viewer(&cookie_jar, &method, &uri, &headers, &state).await {
+        Ok(d) => d,
         Err(e) => return Ok(e),
     };
 
-    // TODO get the count right
-    let count: i64 = 12;
-
     let user = FullProfile::get_by_username_and_instance(
         &username,
         &uuid!("00000000-0000-0000-0000-000000000000"),
         state.pool.clone(),
     )
     .await?;
+    let outbox_url = format!(
+        "{}/api/v1/user/{}/outbox",
+        state.env.public_url, &user.username
+    );
 
-    if query.page.is_none() {
+    if query.page.is_none() && query.min_id.is_none() && query.max_id.is_none() {
+        let count =
+            NoteJoinedModel::count_by_profile_id(&user.id, &viewer, state.pool.clone()).await?;
         let return_data = OrderedCollection {
             context: "https://www.w3.org/ns/activitystreams".to_string(),
-            first: format!(
-                "{}/api/v1/user/{}/outbox?page=true",
-                state.env.public_url, &user.username
-            ),
-            id: format!(
-                "{}/api/v1/user/{}/outbox",
-                state.env.public_url, &user.username
-            ),
+            first: format!("{outbox_url}?page=true"),
+            id: outbox_url.clone(),
             total_items: count,
             type_field: "OrderedCollection".to_string(),
-            last: Some(format!(
-                "{}/api/v1/user/{}/outbox?min_id=0",
-                state.env.public_url, &user.username
-            )),
+            last: Some(format!("{outbox_url}?min_id={}&page=true", Uuid::nil())),
         };
         return Ok(Response::builder()
             .status(StatusCode::OK)
@@ -72,80 +84,88 @@ pub async fn get_outbox(
             .body(Body::from(serde_json::to_string(&return_data).unwrap()))
             .unwrap());
     }
-    let data = NoteJoinedModel::get_by_profile_id(&user.id, state.pool.clone()).await?;
+    let data = NoteJoinedModel::get_page_by_profile_id(
+        &user.id,
+        &viewer,
+        query.max_id,
+        query.min_id,
+        OUTBOX_PAGE_SIZE,
+        state.pool.clone(),
+    )
+    .await?;
     let mut ordered_items: Vec<OrderedItem> = vec![];
-    for item in data {
-        let to = vec!["https://www.w3.org/ns/activitystreams#Public".to_string()];
-        let cc = vec![format!(
-            "{}/api/v1/user/{}/followers",
-            state.env.public_url, &user.username
-        )];
+    for item in &data {
+        let Some(object_id) = item.server_id.clone() else {
+            continue;
+        };
+        let id = item.note_id.or(item.model_id).unwrap();
+        let attachment = match item.model_id {
+            Some(model_id) => {
+                Attachment::get_by_model_id(&model_id, &state.env.public_url, state.pool.clone())
+                    .await?
+            }
+            None => vec![],
+        };
+        let mentioned = match item.note_id {
+            Some(note_id) => {
+                FullMention::get_mentioned_profiles(&note_id, state.pool.clone()).await?
+            }
+            None => vec![],
+        };
+        let (to, cc) = get_addressing(&user.server_id, &item.audience, &mentioned);
+        let url = match item.model_id {
+            Some(_) => format!("{}/api/v1/model/{}", state.env.public_url, id),
+            None => object_id.clone(),
+        };
         ordered_items.push(OrderedItem {
             type_field: "Create".to_string(),
-            id: format!(
-                "{}/api/v1/user/{}/statuses/{}/activity",
-                state.env.public_url,
-                &user.username,
-                &item.note_id.or(item.model_id).unwrap()
-            ),
+            id: format!("{object_id}/activity"),
             actor: user.server_id.to_string(),
-            published: item.created_at.to_string(),
+            published: item.created_at.to_rfc3339(),
             to: to.clone(),
             cc: cc.clone(),
             object: json!(NoteBoxItemObject {
-                id: format!(
-                    "{}/api/v1/user/{}/statuses/{}",
-                    state.env.public_url,
-                    &user.username,
-                    &item.note_id.or(item.model_id).unwrap()
-                ),
+                id: object_id.clone(),
                 type_field: "Note".to_string(),
-                to,
-                cc,
-                content: item.content,
-                summary: item.summary,
-                tag: Tag::from_strs(item.hashtags, &state.env.public_url),
+                to: to.clone(),
+                cc: cc.clone(),
+                content: item.content.clone(),
+                summary: item.summary.clone(),
+                tag: [
+                    Tag::from_strs(item.hashtags.clone(), &state.env.public_url),
+                    mentioned.iter().map(Tag::from_profile).collect()
+                ]
+                .concat(),
                 replies: NoteBoxItemReplies {
-                    id: format!(
-                        "{}/api/v1/user/{}/statuses/{}/replies",
-                        state.env.public_url,
-                        &user.username,
-                        &item.note_id.or(item.model_id).unwrap()
-                    ),
+                    id: format!("{object_id}/replies"),
                     type_field: "Collection".to_string(),
                     first: NoteBoxItemFirst {
                         type_field: "CollectionPage".to_string(),
-                        next: format!(
-                            "{}/api/v1/user/{}/statuses/{}/replies?page=true",
-                            state.env.public_url,
-                            &user.username,
-                            &item.note_id.or(item.model_id).unwrap()
-                        ),
-                        part_of: format!(
-                            "{}/api/v1/user/{}/statuses/{}/replies",
-                            state.env.public_url,
-                            &user.username,
-                            &item.note_id.or(item.model_id).unwrap()
-                        ),
-                        items: item.first_reply_server_id.map_or(Vec::new(), |s| vec![s])
+                        next: format!("{object_id}/replies?page=true"),
+                        part_of: format!("{object_id}/replies"),
+                        items: item
+                            .first_reply_server_id
+                            .clone()
+                            .map_or(Vec::new(), |s| vec![s])
                     }
                 },
-                attachment: vec![],
+                attachment,
                 attributed_to: user.server_id.to_string(),
-                updated: None,
-                published: item.created_at.to_string(),
-                url: format!(
-                    "{}/@{}/statuses/{}",
-                    state.env.public_url,
-                    &user.username,
-                    &item.note_id.or(item.model_id).unwrap()
-                ),
-                // in_reply_to: matchitem.in_reply_to_comment_id
-                in_reply_to: serde_json::Value::String("PLACEHOLDER".to_string())
+                updated: (item.updated_at != item.created_at).then(|| item.updated_at.to_rfc3339()),
+                published: item.created_at.to_rfc3339(),
+                url,
+                in_reply_to: json!(item.in_reply_to),
             }),
         })
     }
 
+    let page_id = match (query.max_id, query.min_id) {
+        (Some(max_id), _) => format!("{outbox_url}?max_id={max_id}&page=true"),
+        (None, Some(min_id)) => format!("{outbox_url}?min_id={min_id}&page=true"),
+        (None, None) => format!("{outbox_url}?page=true"),
+    };
+    let newest = data.first().and_then(|v| v.note_id.or(v.model_id));
+    let oldest = data.last().and_then(|v| v.note_id.or(v.model_id));
     let data = OutboxDataPage {
         context: (
             "https://www.w3.org/ns/activitystreams".to_string(),
@@ -161,19 +181,12 @@ pub async fn get_outbox(
                 },
             },
         ),
-        id: format!(
-            "{}/api/v1/user/{}/outbox?page=true",
-            state.env.public_url, &user.username
-        ),
+        id: page_id,
         type_field: "OrderedCollectionPage".to_string(),
-        next: "TO_BE_IMPLEMENTED".to_string(), // TODO
-        // Example: https://mastodon.online/users/Mawoka/outbox?max_id=111828830388463327&page=true
-        prev: "TO_BE_IMPLEMENTED".to_string(), // TODO
-        // Example: https://mastodon.online/users/Mawoka/outbox?min_id=111953759725965499&page=true
-        part_of: format!(
-            "{}/api/v1/user/{}/outbox",
-            state.env.public_url, &user.username
-        ),
+        // Same scheme as Mastodon, e.g. https://mastodon.online/users/Mawoka/outbox?max_id=111828830388463327&page=true
+        next: oldest.map(|v| format!("{outbox_url}?max_id={v}&page=true")),
+        prev: newest.map(|v| format!("{outbox_url}?min_id={v}&page=true")),
+        part_of: outbox_url,
         ordered_items,
     };
 
@@ -184,39 +197,155 @@ pub async fn get_outbox(
         .unwrap())
 }
 
+async fn is_blocked_by_recipient(username: &str, actor_id: &Uuid, state: &AppState) -> bool {
+    let Ok(recipient) = FullProfile::get_by_username_and_instance(
+        username,
+        &uuid!("00000000-0000-0000-0000-000000000000"),
+        state.pool.clone(),
+    )
+    .await
+    else {
+        return false;
+    };
+    FullBlock::get_by_profile_id_and_target_id(&recipient.id, actor_id, state.pool.clone())
+        .await
+        .is_ok()
+}
+
+async fn receive_activity(
+    recipient: Option<&str>,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &Bytes,
+    state: Arc<AppState>,
+) -> Response {
+    //! Verifies an incoming activity and queues it, shared by the user inboxes and the shared inbox.
+    //! Activities sent to a user who blocked the actor are dropped.
+    debug!("Req Content Type: {:?}", headers.get("accept"));
+    let event: InboxEvent = match serde_json::from_slice(body) {
+        Ok(d) => d,
+        Err(e) => {
+            debug!("Failed to parse inbox event: {}", e);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+    // Suspended instances are refused before their key gets fetched
+    let actor_host = Url::parse(&event.actor)
+        .ok()
+        .and_then(|d| d.host_str().map(|d| d.to_string()))
+        .unwrap_or_default();
+    if FullInstance::get_moderation_by_host(&actor_host, state.pool.clone()).await
+        == InstanceModeration::Suspended
+    {
+        debug!(
+            "Refusing inbox event from suspended instance {}",
+            actor_host
+        );
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    let signer = match verify_request(
+        method,
+        uri,
+        headers,
+        Some(body),
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            debug!("Rejecting inbox event: {}", e);
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    };
+    if signer.server_id != event.actor {
+        debug!(
+            "Rejecting inbox event: signed by {} but actor is {}",
+            signer.server_id, event.actor
+        );
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    // The id is the key of the replay check, so actors can't claim ids of other servers
+    if !is_same_host(&event.id, &event.actor) {
+        debug!(
+            "Rejecting inbox event: {} can't send {}",
+            event.actor, event.id
+        );
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let blocked = match recipient {
+        Some(username) => Ok(is_blocked_by_recipient(username, &signer.id, &state).await),
+        // The shared inbox doesn't name the recipients, they are taken from the addressing
+        None => {
+            FullBlock::is_blocked_by_any(&event.actor, &event.get_addressed(), state.pool.clone())
+                .await
+        }
+    };
+    match blocked {
+        Ok(false) => (),
+        Ok(true) => {
+            debug!(
+                "Dropping inbox event of {} blocked by a recipient",
+                event.actor
+            );
+            return StatusCode::ACCEPTED.into_response();
+        }
+        Err(e) => {
+            error!("Failed to check blocks of {}: {}", event.actor, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    // Retried deliveries and activities sent to several inboxes are only handled once
+    match ProcessedActivity::mark_received(&event.id, state.pool.clone()).await {
+        Ok(true) => (),
+        Ok(false) => {
+            debug!("Skipping already received activity {}", event.id);
+            return StatusCode::ACCEPTED.into_response();
+        }
+        Err(e) => {
+            error!("Failed to check activity {}: {}", event.id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    // The handling itself happens in the background so the sender isn't kept waiting
+    let body = String::from_utf8_lossy(body);
+    if let Err(e) = jobs::process_inbox_activity(&body, state.pool.clone()).await {
+        error!("Failed to queue activity {}: {}", event.id, e);
+        let _ = ProcessedActivity::unmark(&event.id, state.pool.clone()).await;
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/activity+json; charset=utf-8")
+        .body(Body::from(""))
+        .unwrap()
+}
+
 pub async fn post_user_inbox(
-    // Path(username): Path<String>,
+    Path(username): Path<String>,
+    method: Method,
+    uri: Uri,
     headers: HeaderMap,
-    // State(state): State<Arc<AppState>>,
-    Json(event): Json<InboxEvent>,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
 ) -> AppResult<impl IntoResponse> {
     // match ensure_ap_header(&headers) {
     //     Ok(_) => (),
     //     Err(e) => return Ok(e),
     // };
-    debug!("Req Content Type: {:?}", headers.get("accept"));
-    let event_type = event.event_type.as_str();
-    let _ = match event_type {
-        "Announce" => handle_announce(event).await,
-        "Create" => handle_create(event).await,
-        "Update" => handle_update(event).await,
-        "Delete" => handle_delete(event).await,
-        "Follow" => handle_follow(event).await,
-        "Accept" => handle_accept(event).await,
-        "Reject" => handle_reject(event).await,
-        "Remove" => handle_remove(event).await,
-        "Like" => handle_like(event).await,
-        "Undo" => handle_undo(event).await,
-        "Add" => handle_add(event).await,
-        _ => {
-            error!("Unknown event: {}", event_type);
-            Ok(())
-        }
-    };
+    Ok(receive_activity(Some(&username), &method, &uri, &headers, &body, state).await)
+}
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/activity+json; charset=utf-8")
-        .body(Body::from(""))
-        .unwrap())
+pub async fn post_shared_inbox(
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> AppResult<impl IntoResponse> {
+    // Activities for several local users arrive here once, the handlers resolve the recipients themselves
+    Ok(receive_activity(None, &method, &uri, &headers, &body, state).await)
 }
@@ -1,15 +1,17 @@
+use crate::helpers::viewer::get_authorized_viewer;
 use crate::helpers::{ensure_ap_header, AppResult};
 use axum::body::Body;
 use axum::debug_handler;
 use axum::extract::{Path, Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::CookieJar;
 use serde_derive::Deserialize;
+use shared::db::instances::InstanceActor;
 use shared::db::profile::{FullProfile, FullProfileWithFollower, FullProfileWithFollowing};
 use shared::models::activitypub::Profile;
 use shared::models::activitypub::{
-    AlsoKnownAs, Claim, Context, Endpoints, FingerprintKey, IdentityKey, OrderedCollection,
-    PeopleDataPage, PublicKey,
+    Context, Endpoints, OrderedCollection, PeopleDataPage, PublicKey,
 };
 use shared::AppState;
 use std::sync::Arc;
@@ -18,13 +20,19 @@ use uuid::uuid;
 #[debug_handler]
 pub async fn get_user_profile(
     Path(username): Path<String>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
     headers: HeaderMap,
     State(state): State<Arc<AppState>>,
 ) -> AppResult<impl IntoResponse> {
     match ensure_ap_header(&headers) {
         Ok(_) => (),
-        Err(e) => return Ok(e),
+        Err(e) => return Ok(*e),
     };
+    if let Err(e) = get_authorized_viewer(&cookie_jar, &method, &uri, &headers, &state).await {
+        return Ok(e);
+    }
 
     let user = FullProfile::get_by_username_and_instance(
         &username,
@@ -32,102 +40,45 @@ pub async fn get_user_profile(
         state.pool.clone(),
     )
     .await?;
+    let data = Profile::from_local(user, &state.env.public_url);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/activity+json; charset=utf-8")
+        .body(Body::from(serde_json::to_string(&data).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn get_instance_actor(
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    // Has to stay fetchable without a signature, remote servers need its key to verify ours
+    let actor = InstanceActor::get_local(state.pool.clone()).await?;
     let data = Profile {
         context: (
             "https://www.w3.org/ns/activitystreams".to_string(),
             "https://w3id.org/security/v1".to_string(),
             Context {
-                curve25519key: Some("toot:Curve25519Key".to_string()),
-                // device: "toot:Device".to_string(),
-                ed25519key: Some("toot:Ed25519Key".to_string()),
-                ed25519signature: Some("toot:Ed25519Signature".to_string()),
-                encrypted_message: Some("toot:EncryptedMessage".to_string()),
-                hashtag: Some("as:Hashtag".to_string()),
-                property_value: Some("schema:PropertyValue".to_string()),
-                also_known_as: AlsoKnownAs {
-                    id: "as:alsoKnownAs".to_string(),
-                    type_field: "@id".to_string(),
-                },
-                cipher_text: Some("toot:cipherText".to_string()),
-                claim: Some(Claim {
-                    id: "toot:claim".to_string(),
-                    type_field: "@id".to_string(),
-                }),
-                // device_id: "toot:deviceId".to_string(),
-                // devices: Devices{
-                //     id: "toot:devices".to_string(),
-                //     type_field: "@id".to_string()
-                // },
-                // discoverable: "toot:discoverable".to_string(),
-                // featured: Featured {
-                //     id: "toot:featured".to_string(),
-                //     type_field: "@id".to_string()
-                // },
-                // featured_tags: FeaturedTags {
-                //     id: "toot:featuredTags".to_string(),
-                //     type_field: "@id".to_string()
-                // },
-                fingerprint_key: Some(FingerprintKey {
-                    id: "toot:fingerprintKey".to_string(),
-                    type_field: "@id".to_string(),
-                }),
-                // focal_point: FocalPoint {
-                //     container: "@list".to_string(),
-                //     id: "toot:focalPoint".to_string(),
-                // },
-                identity_key: Some(IdentityKey {
-                    id: "toot:identityKey".to_string(),
-                    type_field: "@id".to_string(),
-                }),
-                // indexable: "toot:indexable".to_string(),
-                // manually_approves_followers: "as:manuallyApprovesFollowers".to_string(),
-                // memorial: "toot:memorial".to_string(),
-                message_franking: Some("toot:messageFranking".to_string()),
-                message_type: Some("toot:messageType".to_string()),
-                // moved_to: MovedTo {
-                //     id: "as:movedTo".to_string(),
-                //     type_field: "@id".to_string()
-                // },
-                public_key_base64: Some("toot:publicKeyBase64".to_string()),
-                schema: Some("http://schema.org#".to_string()),
-                // suspended: "toot:suspended".to_string(),
+                manually_approves_followers: Some("as:manuallyApprovesFollowers".to_string()),
                 toot: "http://joinmastodon.org/ns#".to_string(),
-                value: Some("schema:value".to_string()),
+                ..Default::default()
             },
         ),
         endpoints: Endpoints {
             shared_inbox: format!("{}/api/v1/inbox", state.env.public_url),
         },
-        followers: format!(
-            "{}/api/v1/user/{}/followers",
-            state.env.public_url, user.username
-        ),
-        following: format!(
-            "{}/api/v1/user/{}/following",
-            state.env.public_url, user.username
-        ),
-        id: format!("{}/api/v1/user/{}", state.env.public_url, user.username),
-        inbox: format!(
-            "{}/api/v1/user/{}/inbox",
-            state.env.public_url, user.username
-        ),
-        name: user.username.clone(),
-        outbox: format!(
-            "{}/api/v1/user/{}/outbox",
-            state.env.public_url, user.username
-        ),
-        preferred_username: user.display_name,
+        id: actor.actor_id(),
+        inbox: format!("{}/api/v1/inbox", state.env.public_url),
+        manually_approves_followers: true,
+        preferred_username: state.env.base_domain.clone(),
         public_key: PublicKey {
-            id: format!(
-                "{}/api/v1/user/{}#main-key",
-                state.env.public_url, user.username
-            ),
-            owner: format!("{}/api/v1/user/{}", state.env.public_url, user.username),
-            public_key_pem: user.public_key,
+            id: actor.key_id(),
+            owner: actor.actor_id(),
+            public_key_pem: actor.public_key.clone(),
         },
-        published: user.registered_at.to_rfc3339(),
-        type_field: "Person".to_string(),
-        url: format!("{}/@{}", state.env.public_url, user.username),
+        type_field: "Application".to_string(),
+        url: state.env.public_url.clone(),
+        ..Default::default()
     };
     Ok(Response::builder()
         .status(StatusCode::OK)
@@ -144,14 +95,20 @@ pub struct GetFollowersQuery {
 #[debug_handler]
 pub async fn get_followers(
     Path(username): Path<String>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
     headers: HeaderMap,
     query: Query<GetFollowersQuery>,
     State(state): State<Arc<AppState>>,
 ) -> AppResult<impl IntoResponse> {
     match ensure_ap_header(&headers) {
         Ok(_) => (),
-        Err(e) => return Ok(e),
+        Err(e) => return Ok(*e),
     };
+    if let Err(e) = get_authorized_viewer(&cookie_jar, &method, &uri, &headers, &state).await {
+        return Ok(e);
+    }
     let page = query.page;
 
     let user = FullProfile::get_by_username_and_instance(
@@ -211,14 +168,20 @@ pub async fn get_followers(
 #[debug_handler]
 pub async fn get_following(
     Path(username): Path<String>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
     headers: HeaderMap,
     query: Query<GetFollowersQuery>,
     State(state): State<Arc<AppState>>,
 ) -> AppResult<impl IntoResponse> {
     match ensure_ap_header(&headers) {
         Ok(_) => (),
-        Err(e) => return Ok(e),
+        Err(e) => return Ok(*e),
     };
+    if let Err(e) = get_authorized_viewer(&cookie_jar, &method, &uri, &headers, &state).await {
+        return Ok(e);
+    }
     let page = query.page;
 
     let user = FullProfile::get_by_username_and_instance(
@@ -0,0 +1,509 @@
+use crate::helpers::accounts::delete_profile;
+use crate::helpers::activitypub::outbox_activities::send_status_delete;
+use crate::helpers::auth::UserState;
+use crate::helpers::search::remove_from_index;
+use crate::helpers::{internal_app_error, AppResult};
+use crate::routes::api::v1::storage::PaginationQuery;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, Extension, Json};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use shared::db::account::FullAccount;
+use shared::db::audit_log::{CreateAuditLogEntry, FullAuditLogEntry};
+use shared::db::instances::FullInstance;
+use shared::db::jobs::queue_account_deletion;
+use shared::db::model::{FullModel, FullModelWithRelationsIds};
+use shared::db::profile::FullProfile;
+use shared::db::reports::FullReport;
+use shared::db::sessions::FullSession;
+use shared::db::stats::InstanceStats;
+use shared::db::{AccountRole, AuditAction, EventAudience, InstanceModeration, ReportStatus};
+use shared::AppState;
+use std::sync::Arc;
+use tracing::{debug, error};
+use url::Url;
+use uuid::{uuid, Uuid};
+
+async fn audit(
+    claims: &UserState,
+    action: AuditAction,
+    target: String,
+    details: Option<String>,
+    state: &AppState,
+) -> Result<(), sqlx::Error> {
+    CreateAuditLogEntry {
+        actor_id: claims.profile_id,
+        action,
+        target,
+        details,
+    }
+    .create(state.pool.clone())
+    .await?;
+    Ok(())
+}
+
+fn get_base_url(domain: &str, state: &AppState) -> Option<String> {
+    //! Accepts plain domains as well as URLs, the own instance can't be moderated
+    let domain = domain.trim().to_lowercase();
+    let host = match Url::parse(&domain) {
+        Ok(d) => d.host_str()?.to_string(),
+        Err(_) => domain,
+    };
+    if host.is_empty() || Url::parse(&state.env.public_url).ok()?.host_str() == Some(&host) {
+        return None;
+    }
+    Some(format!("https://{host}"))
+}
+
+fn get_domain(instance: &FullInstance) -> String {
+    Url::parse(&instance.base_url)
+        .ok()
+        .and_then(|d| d.host_str().map(|d| d.to_string()))
+        .unwrap_or_else(|| instance.base_url.clone())
+}
+
+#[debug_handler]
+pub async fn get_moderated_instances(
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let instances = FullInstance::get_moderated(state.pool.clone()).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&instances).unwrap()))
+        .unwrap())
+}
+
+#[derive(Deserialize)]
+pub struct SetInstanceModeration {
+    pub domain: String,
+    pub moderation: InstanceModeration,
+    pub comment: Option<String>,
+}
+
+#[debug_handler]
+pub async fn set_instance_moderation(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<SetInstanceModeration>,
+) -> AppResult<impl IntoResponse> {
+    let Some(base_url) = get_base_url(&input.domain, &state) else {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+    let instance = FullInstance::set_moderation(
+        &base_url,
+        input.moderation,
+        input.comment,
+        state.pool.clone(),
+    )
+    .await?;
+    audit(
+        &claims,
+        AuditAction::SetInstanceModeration,
+        base_url,
+        Some(format!("{:?}", input.moderation)),
+        &state,
+    )
+    .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&instance).unwrap()))
+        .unwrap())
+}
+
+// Columns of the domain block CSV Mastodon imports and exports
+#[derive(Serialize, Deserialize)]
+pub struct DomainBlockRecord {
+    #[serde(rename = "#domain", alias = "domain")]
+    pub domain: String,
+    #[serde(rename = "#severity", alias = "severity", default)]
+    pub severity: String,
+    #[serde(rename = "#reject_media", alias = "reject_media", default)]
+    pub reject_media: bool,
+    #[serde(rename = "#reject_reports", alias = "reject_reports", default)]
+    pub reject_reports: bool,
+    #[serde(rename = "#public_comment", alias = "public_comment", default)]
+    pub public_comment: String,
+    #[serde(rename = "#obfuscate", alias = "obfuscate", default)]
+    pub obfuscate: bool,
+}
+
+impl DomainBlockRecord {
+    fn from_instance(instance: &FullInstance) -> DomainBlockRecord {
+        let severity = match instance.moderation {
+            InstanceModeration::Suspended => "suspend",
+            InstanceModeration::Silenced => "silence",
+            _ => "noop",
+        };
+        DomainBlockRecord {
+            domain: get_domain(instance),
+            severity: severity.to_string(),
+            reject_media: instance.moderation == InstanceModeration::RejectMedia,
+            reject_reports: false,
+            public_comment: instance.moderation_comment.clone().unwrap_or_default(),
+            obfuscate: false,
+        }
+    }
+
+    fn get_moderation(&self) -> InstanceModeration {
+        //! Only one state is kept per instance, so the severity wins over rejecting media
+        match (self.severity.as_str(), self.reject_media) {
+            ("suspend", _) => InstanceModeration::Suspended,
+            ("silence", _) => InstanceModeration::Silenced,
+            (_, true) => InstanceModeration::RejectMedia,
+            _ => InstanceModeration::None,
+        }
+    }
+}
+
+#[debug_handler]
+pub async fn export_instance_moderation(
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let instances = FullInstance::get_moderated(state.pool.clone()).await?;
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for instance in &instances {
+        writer
+            .serialize(DomainBlockRecord::from_instance(instance))
+            .map_err(internal_app_error)?;
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/csv")
+        .header(
+            "Content-Disposition",
+            "attachment; filename=\"domain_blocks.csv\"",
+        )
+        .body(Body::from(writer.into_inner().map_err(internal_app_error)?))
+        .unwrap())
+}
+
+#[derive(Serialize)]
+pub struct ImportResult {
+    pub imported: usize,
+    pub skipped: Vec<String>,
+}
+
+#[debug_handler]
+pub async fn import_instance_moderation(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> AppResult<impl IntoResponse> {
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let mut result = ImportResult {
+        imported: 0,
+        skipped: vec![],
+    };
+    for record in reader.deserialize::<DomainBlockRecord>() {
+        let record = match record {
+            Ok(d) => d,
+            Err(e) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from(e.to_string()))
+                    .unwrap());
+            }
+        };
+        let Some(base_url) = get_base_url(&record.domain, &state) else {
+            result.skipped.push(record.domain);
+            continue;
+        };
+        let comment = Some(record.public_comment.clone()).filter(|d| !d.is_empty());
+        FullInstance::set_moderation(
+            &base_url,
+            record.get_moderation(),
+            comment,
+            state.pool.clone(),
+        )
+        .await?;
+        result.imported += 1;
+    }
+    debug!(
+        "Imported {} domain blocks, skipped {:?}",
+        result.imported, result.skipped
+    );
+    audit(
+        &claims,
+        AuditAction::ImportInstanceModeration,
+        format!("{} domains", result.imported),
+        None,
+        &state,
+    )
+    .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&result).unwrap()))
+        .unwrap())
+}
+
+#[derive(Deserialize)]
+pub struct GetReportsQuery {
+    #[serde(default)]
+    pub status: ReportStatus,
+}
+
+#[debug_handler]
+pub async fn get_reports(
+    State(state): State<Arc<AppState>>,
+    query: Query<GetReportsQuery>,
+) -> AppResult<impl IntoResponse> {
+    //! The review queue, open reports unless another status is asked for
+    let reports = FullReport::get_by_status(query.status, state.pool.clone()).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&reports).unwrap()))
+        .unwrap())
+}
+
+#[derive(Deserialize)]
+pub struct SetReportStatus {
+    pub status: ReportStatus,
+}
+
+#[debug_handler]
+pub async fn set_report_status(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<SetReportStatus>,
+) -> AppResult<impl IntoResponse> {
+    let report = FullReport::get_by_id(&id, state.pool.clone())
+        .await?
+        .set_status(input.status, &claims.profile_id, state.pool.clone())
+        .await?;
+    audit(
+        &claims,
+        AuditAction::SetReportStatus,
+        id.to_string(),
+        Some(format!("{:?}", input.status)),
+        &state,
+    )
+    .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&report).unwrap()))
+        .unwrap())
+}
+
+async fn get_local_account(
+    profile_id: &Uuid,
+    claims: &UserState,
+    state: &AppState,
+) -> Result<FullAccount, Response> {
+    //! Staff can't act on their own account or on accounts with the same or a higher role
+    if *profile_id == claims.profile_id {
+        return Err(StatusCode::BAD_REQUEST.into_response());
+    }
+    let account = match FullAccount::get_by_profile_id(profile_id, state.pool.clone()).await {
+        Ok(d) => d,
+        Err(_) => return Err(StatusCode::NOT_FOUND.into_response()),
+    };
+    if account.role >= claims.role {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+    Ok(account)
+}
+
+#[derive(Deserialize)]
+pub struct SetAccountRole {
+    pub role: AccountRole,
+}
+
+#[debug_handler]
+pub async fn set_account_role(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Path(profile_id): Path<Uuid>,
+    Json(input): Json<SetAccountRole>,
+) -> AppResult<impl IntoResponse> {
+    let account = match get_local_account(&profile_id, &claims, &state).await {
+        Ok(d) => d,
+        Err(e) => return Ok(e),
+    };
+    let account = FullAccount::set_role(&account.id, input.role, state.pool.clone()).await?;
+    audit(
+        &claims,
+        AuditAction::SetRole,
+        profile_id.to_string(),
+        Some(format!("{:?}", input.role)),
+        &state,
+    )
+    .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&json!({"role": account.role})).unwrap(),
+        ))
+        .unwrap())
+}
+
+#[derive(Deserialize)]
+pub struct SetAccountSuspended {
+    pub suspended: bool,
+    pub reason: Option<String>,
+}
+
+#[debug_handler]
+pub async fn set_account_suspended(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Path(profile_id): Path<Uuid>,
+    Json(input): Json<SetAccountSuspended>,
+) -> AppResult<impl IntoResponse> {
+    //! Suspended accounts can't log in and their sessions end once the token has to be renewed
+    let account = match get_local_account(&profile_id, &claims, &state).await {
+        Ok(d) => d,
+        Err(e) => return Ok(e),
+    };
+    FullAccount::set_suspended(&account.id, input.suspended, state.pool.clone()).await?;
+    if input.suspended {
+        FullSession::revoke_all(&account.id, state.pool.clone()).await?;
+    }
+    let action = match input.suspended {
+        true => AuditAction::SuspendAccount,
+        false => AuditAction::UnsuspendAccount,
+    };
+    audit(
+        &claims,
+        action,
+        profile_id.to_string(),
+        input.reason,
+        &state,
+    )
+    .await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+#[debug_handler]
+pub async fn delete_account(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Path(profile_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    //! Suspends the account right away and queues its deletion
+    if let Err(e) = get_local_account(&profile_id, &claims, &state).await {
+        return Ok(e);
+    }
+    let profile = FullProfile::get_by_id(&profile_id, state.pool.clone()).await?;
+    let account = FullAccount::get_by_profile_id(&profile.id, state.pool.clone()).await?;
+    FullAccount::set_suspended(&account.id, true, state.pool.clone()).await?;
+    FullSession::revoke_all(&account.id, state.pool.clone()).await?;
+    queue_account_deletion(&profile.id, state.pool.clone()).await?;
+    audit(
+        &claims,
+        AuditAction::DeleteAccount,
+        profile_id.to_string(),
+        Some(profile.username),
+        &state,
+    )
+    .await?;
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+#[debug_handler]
+pub async fn unpublish_model(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    //! Hides a model from everyone but its owner. Local models are removed from the followers' servers too.
+    let model = FullModelWithRelationsIds::get_by_id(&id, false, state.pool.clone()).await?;
+    FullModel::unpublish_by_id(&model.id, state.pool.clone()).await?;
+    remove_from_index(&model.id, &state.ms).await?;
+    let profile = FullProfile::get_by_id(&model.profile_id, state.pool.clone()).await?;
+    if let (true, true, Some(server_id)) = (
+        model.published,
+        profile.instance == uuid!("00000000-0000-0000-0000-000000000000"),
+        &model.server_id,
+    ) {
+        if let Err(e) = send_status_delete(
+            server_id,
+            &profile.id,
+            &EventAudience::Public,
+            vec![],
+            state.clone(),
+        )
+        .await
+        {
+            error!("Failed to federate model removal: {e}");
+        }
+    }
+    audit(
+        &claims,
+        AuditAction::UnpublishModel,
+        model.id.to_string(),
+        Some(model.title),
+        &state,
+    )
+    .await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+#[debug_handler]
+pub async fn purge_remote_profile(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Path(profile_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    //! Drops a remote profile with its cached models, notes and files. It comes back if it's fetched again.
+    let profile = FullProfile::get_by_id(&profile_id, state.pool.clone()).await?;
+    if profile.instance == uuid!("00000000-0000-0000-0000-000000000000") {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+    if let Err(e) = delete_profile(&profile, state.clone()).await {
+        error!("Failed to purge {}: {e}", profile.server_id);
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+    audit(
+        &claims,
+        AuditAction::PurgeProfile,
+        profile_id.to_string(),
+        Some(profile.server_id),
+        &state,
+    )
+    .await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+#[debug_handler]
+pub async fn get_stats(State(state): State<Arc<AppState>>) -> AppResult<impl IntoResponse> {
+    let stats = InstanceStats::get(state.pool.clone()).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&stats).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn get_audit_log(
+    State(state): State<Arc<AppState>>,
+    query: Query<PaginationQuery>,
+) -> AppResult<impl IntoResponse> {
+    if query.page < 0 {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("page can't be less than 0"))
+            .unwrap());
+    }
+    let entries = FullAuditLogEntry::get_newest_paginated(
+        &50i64,
+        &((query.page * 50) as i64),
+        state.pool.clone(),
+    )
+    .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&entries).unwrap()))
+        .unwrap())
+}
@@ -1,21 +1,19 @@
 use axum::body::Body;
-use axum::extract::State;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::HeaderMap;
 use axum::response::{IntoResponse, Response};
 use axum::{debug_handler, Extension};
 use axum::{extract::Json, http::StatusCode, Form};
 use axum_extra::extract::cookie::Cookie;
 use axum_extra::extract::CookieJar;
-use base64::{engine::general_purpose, Engine as _};
+use chrono::{TimeDelta, Utc};
 use email_address::EmailAddress;
-use openssl::pkey::PKey;
-use openssl::rsa::Rsa;
-use openssl::symm::Cipher;
 use serde::Deserialize;
-use std::str;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use uuid::{uuid, Uuid};
 
-use crate::helpers::auth::UserState;
+use crate::helpers::auth::{get_client_ip, get_user_agent, UserState};
 use crate::{
     helpers::auth::{generate_jwt, get_password_hash},
     helpers::{
@@ -25,7 +23,17 @@ use crate::{
     AppState,
 };
 use shared::db::account::{CreateAccount, FullAccount};
+use shared::db::jobs::{
+    queue_account_deletion, send_password_reset_email, send_register_confirm_email,
+};
+use shared::db::password_resets::FullPasswordResetToken;
 use shared::db::profile::{CreateProfile, FullProfile};
+use shared::db::sessions::{CreateSession, FullSession};
+use shared::db::two_factor::{CreateTwoFactorChallenge, FullTwoFactor, FullTwoFactorChallenge};
+use shared::helpers::config::EmailVerification;
+use shared::helpers::keys::KeyService;
+use shared::helpers::tokens::{generate_one_time_token, hash_token, read_verification_token};
+use shared::helpers::totp::verify_second_factor;
 use shared::models::users::CreateUserInput;
 
 #[debug_handler]
@@ -46,13 +54,9 @@ pub async fn create_user(
             .unwrap());
     }
     let pw_hash = get_password_hash(input.password);
-    let rsa = Rsa::generate(2048).unwrap();
-    let public_key = str::from_utf8(&rsa.public_key_to_pem().unwrap())
-        .unwrap()
-        .to_string();
-    let private_key = str::from_utf8(&rsa.private_key_to_pem().unwrap())
-        .unwrap()
-        .to_string();
+    let Ok((public_key, private_key)) = KeyService::new(&state.env).generate_key_pair() else {
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
     let profile = CreateProfile {
         id: Uuid::now_v7(),
         username: input.username.clone(),
@@ -72,7 +76,7 @@ pub async fn create_user(
     }
     .create(state.pool.clone())
     .await?;
-    CreateAccount {
+    let account = CreateAccount {
         password: &pw_hash,
         email: &input.email,
         private_key: &private_key,
@@ -80,6 +84,7 @@ pub async fn create_user(
     }
     .create(state.pool.clone())
     .await?;
+    send_register_confirm_email(&account.id, state.pool.clone()).await?;
 
     Ok(Response::builder()
         .status(StatusCode::CREATED)
@@ -96,6 +101,8 @@ pub struct LogIn {
 #[debug_handler]
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     jar: CookieJar,
     Form(data): Form<LogIn>,
 ) -> AppResult<(CookieJar, StatusCode)> {
@@ -111,11 +118,112 @@ pub async fn login(
     if !check_password_hash(data.password, &acct.password) {
         return Ok((jar, StatusCode::UNAUTHORIZED));
     }
-    let rsa_key = Rsa::private_key_from_pem(acct.private_key.as_ref()).unwrap();
-    let pkey = PKey::from_rsa(rsa_key).unwrap();
-    let encrypted_key = pkey
-        .private_key_to_pem_pkcs8_passphrase(Cipher::aes_128_cbc(), state.env.jwt_secret.as_ref())
-        .unwrap();
+    if acct.suspended
+        || (acct.verified.is_some() && state.env.email_verification == EmailVerification::Login)
+    {
+        return Ok((jar, StatusCode::FORBIDDEN));
+    }
+    // With 2FA the password only gets a short-lived challenge for the second step
+    if let Some(two_factor) = FullTwoFactor::get_enabled(&acct.id, state.pool.clone()).await? {
+        if two_factor.is_locked() {
+            return Ok((jar, StatusCode::TOO_MANY_REQUESTS));
+        }
+        let Ok((challenge, token_hash)) = generate_one_time_token() else {
+            return Ok((jar, StatusCode::INTERNAL_SERVER_ERROR));
+        };
+        CreateTwoFactorChallenge {
+            account_id: acct.id,
+            token_hash,
+            expires_at: Utc::now() + TimeDelta::try_minutes(5).unwrap(),
+        }
+        .create(state.pool.clone())
+        .await?;
+        let challenge_cookie = Cookie::build(("two_factor_challenge", challenge))
+            .secure(true)
+            .path("/api/v1/auth/login")
+            .http_only(true);
+        return Ok((jar.add(challenge_cookie), StatusCode::ACCEPTED));
+    }
+    let auth_cookie = start_session(acct, prof, &headers, &addr, &state).await?;
+
+    Ok((jar.add(auth_cookie), StatusCode::OK))
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorLogIn {
+    pub code: String,
+}
+
+#[debug_handler]
+pub async fn login_two_factor(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Form(data): Form<TwoFactorLogIn>,
+) -> AppResult<(CookieJar, StatusCode)> {
+    //! The second login step, takes a TOTP or a recovery code. The challenge is used up by the first
+    //! try, after a wrong code the password has to be entered again.
+    let Some(challenge) = jar
+        .get("two_factor_challenge")
+        .map(|c| c.value().to_string())
+    else {
+        return Ok((jar, StatusCode::UNAUTHORIZED));
+    };
+    let jar = jar.remove(Cookie::build("two_factor_challenge").path("/api/v1/auth/login"));
+    let Ok(token_hash) = hash_token(&challenge) else {
+        return Ok((jar, StatusCode::INTERNAL_SERVER_ERROR));
+    };
+    let Some(account_id) = FullTwoFactorChallenge::consume(&token_hash, state.pool.clone()).await?
+    else {
+        return Ok((jar, StatusCode::UNAUTHORIZED));
+    };
+    let Some(two_factor) = FullTwoFactor::get_enabled(&account_id, state.pool.clone()).await?
+    else {
+        return Ok((jar, StatusCode::UNAUTHORIZED));
+    };
+    if two_factor.is_locked() {
+        return Ok((jar, StatusCode::TOO_MANY_REQUESTS));
+    }
+    let key_service = KeyService::new(&state.env);
+    match verify_second_factor(
+        &key_service,
+        &two_factor,
+        &data.code,
+        true,
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(true) => (),
+        Ok(false) => return Ok((jar, StatusCode::UNAUTHORIZED)),
+        Err(_) => return Ok((jar, StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+    let acct = FullAccount::get_by_id(&account_id, state.pool.clone()).await?;
+    // The account might have been suspended in between
+    if acct.suspended {
+        return Ok((jar, StatusCode::FORBIDDEN));
+    }
+    let prof = FullProfile::get_by_id(&acct.profile_id, state.pool.clone()).await?;
+    let auth_cookie = start_session(acct, prof, &headers, &addr, &state).await?;
+
+    Ok((jar.add(auth_cookie), StatusCode::OK))
+}
+
+async fn start_session(
+    acct: FullAccount,
+    prof: FullProfile,
+    headers: &HeaderMap,
+    addr: &SocketAddr,
+    state: &AppState,
+) -> Result<Cookie<'static>, sqlx::Error> {
+    let session = CreateSession {
+        account_id: acct.id,
+        user_agent: get_user_agent(headers),
+        ip: Some(get_client_ip(headers, addr)),
+    }
+    .create(state.pool.clone())
+    .await?;
     let claims = InputClaims {
         sub: acct.id,
         profile_id: prof.id,
@@ -123,15 +231,119 @@ pub async fn login(
         email: acct.email,
         username: prof.username,
         server_id: prof.server_id,
-        private_key: general_purpose::STANDARD.encode(encrypted_key),
+        role: acct.role,
+        sid: session.id,
     };
     let jwt = generate_jwt(claims, state.env.jwt_secret.clone());
-    let auth_cookie = Cookie::build(("authorization_key", jwt))
+    Ok(Cookie::build(("authorization_key", jwt))
         .secure(true)
         .path("/")
-        .http_only(true);
+        .http_only(true)
+        .build())
+}
 
-    Ok((jar.add(auth_cookie), StatusCode::OK))
+#[derive(Deserialize)]
+pub struct ForgotPassword {
+    pub email: String,
+}
+
+#[debug_handler]
+pub async fn forgot_password(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ForgotPassword>,
+) -> AppResult<impl IntoResponse> {
+    //! Emails a link to reset the password. Always answers the same, so it doesn't tell which
+    //! emails have an account.
+    if let Ok(acct) = FullAccount::get_by_email(&input.email, state.pool.clone()).await {
+        if !acct.suspended {
+            send_password_reset_email(&acct.id, state.pool.clone()).await?;
+        }
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+pub struct ResetPassword {
+    pub token: String,
+    pub password: String,
+}
+
+#[debug_handler]
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ResetPassword>,
+) -> AppResult<impl IntoResponse> {
+    //! Sets a new password with the token from the email. All sessions of the account are logged out.
+    if input.password.is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("The password can't be empty"))
+            .unwrap());
+    }
+    let Ok(token_hash) = hash_token(&input.token) else {
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    let pw_hash = get_password_hash(input.password);
+    if FullPasswordResetToken::redeem(&token_hash, &pw_hash, state.pool.clone())
+        .await?
+        .is_none()
+    {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("The link is invalid or expired"))
+            .unwrap());
+    }
+    Ok(StatusCode::OK.into_response())
+}
+
+#[derive(Deserialize)]
+pub struct VerifyQuery {
+    pub token: String,
+}
+
+#[debug_handler]
+pub async fn verify_email(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<VerifyQuery>,
+) -> AppResult<impl IntoResponse> {
+    //! Opened from the link in the registration email
+    let Some((account_id, code)) = read_verification_token(&query.token, &state.env.jwt_secret)
+    else {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("The link is invalid or expired"))
+            .unwrap());
+    };
+    if !FullAccount::verify_email(&account_id, &code, state.pool.clone()).await? {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("The email is already verified"))
+            .unwrap());
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("Your email is verified"))
+        .unwrap())
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerification {
+    pub email: String,
+}
+
+#[debug_handler]
+pub async fn resend_verification_email(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ResendVerification>,
+) -> AppResult<impl IntoResponse> {
+    //! Works without a session, as unverified accounts may not be able to log in.
+    //! Always answers the same, so it doesn't tell which emails have an account.
+    if let Ok(acct) = FullAccount::get_by_email(&input.email, state.pool.clone()).await {
+        if acct.verified.is_some() {
+            send_register_confirm_email(&acct.id, state.pool.clone()).await?;
+        }
+    }
+    Ok(StatusCode::ACCEPTED)
 }
 
 #[debug_handler]
@@ -148,6 +360,33 @@ pub async fn get_me_handler(
     Ok((StatusCode::OK, Json(json_response)))
 }
 
+#[derive(Deserialize)]
+pub struct DeleteAccountInput {
+    pub password: String,
+}
+
+#[debug_handler]
+pub async fn delete_own_account(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Json(input): Json<DeleteAccountInput>,
+) -> AppResult<(CookieJar, StatusCode)> {
+    //! Deletes the account of the user. The account is suspended right away, so it can't log in again,
+    //! the deletion itself and its federation run as a job.
+    let acct = FullAccount::get_by_id(&claims.sub, state.pool.clone()).await?;
+    if !check_password_hash(input.password, &acct.password) {
+        return Ok((jar, StatusCode::UNAUTHORIZED));
+    }
+    FullAccount::set_suspended(&acct.id, true, state.pool.clone()).await?;
+    FullSession::revoke_all(&acct.id, state.pool.clone()).await?;
+    queue_account_deletion(&acct.profile_id, state.pool.clone()).await?;
+    Ok((
+        jar.remove(Cookie::build("authorization_key").path("/")),
+        StatusCode::ACCEPTED,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,6 +463,8 @@ mod tests {
         let cookie_jar = CookieJar::new();
         let res = login(
             State(state.clone()),
+            ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))),
+            HeaderMap::new(),
             cookie_jar,
             Form(LogIn {
                 email: "test@mawoka.eu".to_string(),
@@ -245,6 +486,8 @@ mod tests {
         let cookie_jar = CookieJar::new();
         let res = login(
             State(state.clone()),
+            ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))),
+            HeaderMap::new(),
             cookie_jar,
             Form(LogIn {
                 email: "test@mawoka".to_string(),
@@ -260,6 +503,8 @@ mod tests {
         let cookie_jar = CookieJar::new();
         let res = login(
             State(state.clone()),
+            ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))),
+            HeaderMap::new(),
             cookie_jar,
             Form(LogIn {
                 email: "test@mawoka.eu".to_string(),
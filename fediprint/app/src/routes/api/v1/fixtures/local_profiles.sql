@@ -0,0 +1,9 @@
+-- Two more local profiles without accounts, next to the basic user
+
+INSERT INTO profile (id, username, server_id, display_name, summary, inbox, outbox, public_key, instance)
+VALUES ('018e7b20-51bd-703a-96c6-9c70cc723c68', 'alice', 'http://localhost:3000/api/v1/user/alice', 'alice', '',
+        'http://localhost:3000/api/v1/user/alice/inbox', 'http://localhost:3000/api/v1/user/alice/outbox', '',
+        '00000000-0000-0000-0000-000000000000'),
+       ('018e7b20-51bd-703a-96c6-9c70cc723c69', 'bob', 'http://localhost:3000/api/v1/user/bob', 'bob', '',
+        'http://localhost:3000/api/v1/user/bob/inbox', 'http://localhost:3000/api/v1/user/bob/outbox', '',
+        '00000000-0000-0000-0000-000000000000');
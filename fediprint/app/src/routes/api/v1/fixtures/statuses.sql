@@ -0,0 +1,22 @@
+-- Notes and models of the basic user with every audience, alice is mentioned in two of the notes
+
+INSERT INTO note (id, content, audience, actor_id)
+VALUES ('20000000-0000-0000-0000-000000000001', 'public', 'PUBLIC', '018e7b20-51bd-703a-96c6-9c70cc723c67'),
+       ('20000000-0000-0000-0000-000000000002', 'followers', 'FOLLOWERS', '018e7b20-51bd-703a-96c6-9c70cc723c67'),
+       ('20000000-0000-0000-0000-000000000003', 'mentioned', 'MENTIONED', '018e7b20-51bd-703a-96c6-9c70cc723c67'),
+       ('20000000-0000-0000-0000-000000000004', 'nobody', 'NOBODY', '018e7b20-51bd-703a-96c6-9c70cc723c67');
+
+INSERT INTO _mentions (profile_id, note_id)
+VALUES ('018e7b20-51bd-703a-96c6-9c70cc723c68', '20000000-0000-0000-0000-000000000003'),
+       ('018e7b20-51bd-703a-96c6-9c70cc723c68', '20000000-0000-0000-0000-000000000004');
+
+INSERT INTO model (id, server, profile_id, published, title, summary, description, license)
+VALUES ('30000000-0000-0000-0000-000000000001', 'http://localhost:3000', '018e7b20-51bd-703a-96c6-9c70cc723c67',
+        true, 'published', '', '', 'CC_PD'),
+       ('30000000-0000-0000-0000-000000000002', 'http://localhost:3000', '018e7b20-51bd-703a-96c6-9c70cc723c67',
+        false, 'draft', '', '', 'CC_PD');
+
+-- Models are listed with their files and images
+INSERT INTO file (mime_type, size, file_name, profile_id, file_for_model_id, image_for_model_id)
+VALUES ('model/stl', 1, 'published.stl', '018e7b20-51bd-703a-96c6-9c70cc723c67', '30000000-0000-0000-0000-000000000001', null),
+       ('image/png', 1, 'published.png', '018e7b20-51bd-703a-96c6-9c70cc723c67', null, '30000000-0000-0000-0000-000000000001');
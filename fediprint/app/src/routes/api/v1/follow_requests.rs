@@ -0,0 +1,107 @@
+use crate::helpers::auth::UserState;
+use crate::helpers::interactions::respond_to_follow;
+use crate::helpers::AppResult;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, Extension, Json};
+use serde::Deserialize;
+use shared::db::followers::{FollowRequestWithProfile, FullFollower};
+use shared::db::profile::FullProfile;
+use shared::AppState;
+use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct FollowRequestId {
+    pub id: Uuid,
+    #[serde(default)]
+    pub notify: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ManualApproval {
+    pub enabled: bool,
+}
+
+#[debug_handler]
+pub async fn get_follow_requests(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let requests =
+        FollowRequestWithProfile::get_pending_by_profile_id(&claims.profile_id, state.pool.clone())
+            .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&requests).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn accept_follow_request(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<FollowRequestId>,
+) -> AppResult<impl IntoResponse> {
+    let follow =
+        FullFollower::get_by_id_and_profile_id(&input.id, &claims.profile_id, state.pool.clone())
+            .await?;
+    if !follow.pending {
+        return Ok(StatusCode::CONFLICT.into_response());
+    }
+    let follow = follow.accept(state.pool.clone()).await?;
+    FullProfile::refresh_follower_count(&claims.profile_id, state.pool.clone()).await?;
+    if let Err(e) = respond_to_follow(&follow, "Accept", state.pool.clone()).await {
+        error!("Failed to send Accept: {}", e);
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&follow).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn reject_follow_request(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<FollowRequestId>,
+) -> AppResult<impl IntoResponse> {
+    let follow =
+        FullFollower::get_by_id_and_profile_id(&input.id, &claims.profile_id, state.pool.clone())
+            .await?;
+    if !follow.pending {
+        return Ok(StatusCode::CONFLICT.into_response());
+    }
+    // A rejection is only federated on request, otherwise the request just stays unanswered
+    if input.notify {
+        if let Err(e) = respond_to_follow(&follow, "Reject", state.pool.clone()).await {
+            error!("Failed to send Reject: {}", e);
+        }
+    }
+    follow.delete(state.pool.clone()).await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+#[debug_handler]
+pub async fn set_manual_approval(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ManualApproval>,
+) -> AppResult<impl IntoResponse> {
+    let profile = FullProfile::set_manually_approves_followers(
+        &claims.profile_id,
+        input.enabled,
+        state.pool.clone(),
+    )
+    .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&profile).unwrap()))
+        .unwrap())
+}
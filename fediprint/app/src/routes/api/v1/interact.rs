@@ -1,15 +1,29 @@
+use crate::helpers::activitypub::outbox_activities::send_status_activity;
 use crate::helpers::auth::UserState;
+use crate::helpers::interactions::{boost_status, like_status, unboost_status, unlike_status};
 use crate::helpers::AppResult;
+use anyhow::anyhow;
 use axum::body::Body;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::{debug_handler, Extension, Json};
 use serde_derive::Deserialize;
-use shared::db::note::{CreateNote, UserFacingNote};
+use shared::db::boosts::FullBoost;
+use shared::db::likes::FullLike;
+use shared::db::mentions::CreateMention;
+use shared::db::note::{CreateNote, ReplyTarget, UserFacingNote};
+use shared::db::profile::FullProfile;
+use shared::db::visibility::Viewer;
 use shared::db::EventAudience;
+use shared::helpers::activities::{get_local_copy, get_remote_activity, ModelOrNote};
+use shared::helpers::keys::KeyService;
+use shared::helpers::profile::find_mentions;
+use shared::models::activitypub::NoteJoinedModel;
 use shared::AppState;
 use std::sync::Arc;
+use tracing::{debug, error};
+use uuid::Uuid;
 
 #[derive(Deserialize)]
 pub struct PostNoteInput {
@@ -20,6 +34,72 @@ pub struct PostNoteInput {
     pub in_reply_to: Option<String>,
 }
 
+async fn resolve_reply_target(
+    in_reply_to: &str,
+    viewer: &Viewer,
+    state: &Arc<AppState>,
+) -> anyhow::Result<ReplyTarget> {
+    //! Finds the parent among the stored notes and models or fetches it from the remote server.
+    //! Stored parents the user can't see are treated as unknown.
+    let parent = match get_local_copy(in_reply_to, state.pool.clone()).await {
+        Some(d) => {
+            let visible = match &d {
+                ModelOrNote::Note(n) => viewer.can_view_note(&n.id, state.pool.clone()).await?,
+                ModelOrNote::Model(m) => viewer.can_view_model(&m.id, state.pool.clone()).await?,
+            };
+            if !visible {
+                anyhow::bail!("Failed to resolve {}", in_reply_to);
+            }
+            d
+        }
+        None => get_remote_activity(in_reply_to.to_string(), state.clone())
+            .await
+            .map_err(|e| anyhow!("Failed to resolve {}: {:?}", in_reply_to, e))?,
+    };
+    Ok(match parent {
+        ModelOrNote::Note(d) => ReplyTarget::from_note(&d),
+        ModelOrNote::Model(d) => ReplyTarget::from_model(&d.id),
+    })
+}
+
+async fn resolve_mentions(
+    mentions: &[String],
+    content: &str,
+    state: &AppState,
+) -> anyhow::Result<Vec<FullProfile>> {
+    //! Resolves the explicitly passed handles and the ones written in the content.
+    //! Only explicit handles have to be resolvable.
+    let mut profiles: Vec<FullProfile> = vec![];
+    for handle in mentions {
+        let profile = FullProfile::get_by_handle(
+            handle,
+            &state.env.public_url,
+            &KeyService::new(&state.env),
+            state.pool.clone(),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", handle, e))?;
+        if !profiles.iter().any(|v| v.id == profile.id) {
+            profiles.push(profile);
+        }
+    }
+    for handle in find_mentions(content) {
+        match FullProfile::get_by_handle(
+            &handle,
+            &state.env.public_url,
+            &KeyService::new(&state.env),
+            state.pool.clone(),
+        )
+        .await
+        {
+            Ok(d) if !profiles.iter().any(|v| v.id == d.id) => profiles.push(d),
+            Ok(_) => {}
+            Err(e) => debug!("Ignoring mention {}: {}", handle, e),
+        }
+    }
+    Ok(profiles)
+}
+
 #[debug_handler]
 pub async fn post_note(
     Extension(claims): Extension<UserState>,
@@ -31,26 +111,194 @@ pub async fn post_note(
     //     mentions_vec.push(profile::server_id::equals(mention));
     // }
 
+    let reply_to = match &input.in_reply_to {
+        Some(in_reply_to) => match resolve_reply_target(
+            in_reply_to,
+            &Viewer::from_profile_id(&claims.profile_id),
+            &state,
+        )
+        .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from(e.to_string()))
+                    .unwrap());
+            }
+        },
+        None => ReplyTarget::default(),
+    };
+    let mentioned = match resolve_mentions(&input.mentions, &input.content, &state).await {
+        Ok(d) => d,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap());
+        }
+    };
     let unfinished_note = CreateNote {
         server_id: None,
         content: input.content,
         hashtags: input.hashtags,
         audience: input.audience,
-        comment_of_model_id: None,
-        in_reply_to_note_id: None,
+        comment_of_model_id: reply_to.comment_of_model_id,
+        in_reply_to_note_id: reply_to.in_reply_to_note_id,
+        in_reply_to_model_id: reply_to.in_reply_to_model_id,
         actor_id: claims.profile_id,
-        in_reply_to_comment_id: None,
+        in_reply_to_comment_id: reply_to.in_reply_to_comment_id,
     }
     .create(state.pool.clone())
     .await?;
+    for profile in &mentioned {
+        CreateMention {
+            profile_id: profile.id,
+            note_id: unfinished_note.id,
+        }
+        .create(state.pool.clone())
+        .await?;
+    }
     let s_id = format!(
-        "{}api/v1/statuses/{}",
+        "{}/api/v1/statuses/{}",
         state.env.public_url, &unfinished_note.id
     );
     let note =
         UserFacingNote::set_server_id(&unfinished_note.id, &s_id, state.pool.clone()).await?;
+    if let Err(e) = send_status_activity(
+        "Create",
+        &note.id,
+        &claims.profile_id,
+        &note.audience,
+        state.clone(),
+    )
+    .await
+    {
+        error!("Failed to federate note: {e}");
+    }
     Ok(Response::builder()
         .status(StatusCode::OK)
         .body(Body::from(serde_json::to_string(&note).unwrap()))
         .unwrap())
 }
+
+#[derive(Deserialize)]
+pub struct StatusId {
+    pub id: Uuid,
+}
+
+async fn get_status(
+    id: &Uuid,
+    claims: &UserState,
+    state: &AppState,
+) -> Result<NoteJoinedModel, sqlx::Error> {
+    //! Loads the note or model to interact with, statuses hidden from the user are reported as missing
+    let viewer = Viewer::from_profile_id(&claims.profile_id);
+    NoteJoinedModel::get_by_id_for_viewer(id, &viewer, state.pool.clone()).await
+}
+
+#[debug_handler]
+pub async fn like(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<StatusId>,
+) -> AppResult<impl IntoResponse> {
+    let status = get_status(&input.id, &claims, &state).await?;
+    if FullLike::get_by_profile_id_and_object(
+        &claims.profile_id,
+        status.note_id,
+        status.model_id,
+        state.pool.clone(),
+    )
+    .await
+    .is_ok()
+    {
+        return Ok(StatusCode::CONFLICT.into_response());
+    }
+    let like = match like_status(&status, &claims, state.pool.clone()).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Like failed: {e}");
+            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&like).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn unlike(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<StatusId>,
+) -> AppResult<impl IntoResponse> {
+    let status = get_status(&input.id, &claims, &state).await?;
+    FullLike::get_by_profile_id_and_object(
+        &claims.profile_id,
+        status.note_id,
+        status.model_id,
+        state.pool.clone(),
+    )
+    .await?;
+    if let Err(e) = unlike_status(&status, &claims, state.pool.clone()).await {
+        error!("Unlike failed: {e}");
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+    Ok(StatusCode::OK.into_response())
+}
+
+#[debug_handler]
+pub async fn boost(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<StatusId>,
+) -> AppResult<impl IntoResponse> {
+    let status = get_status(&input.id, &claims, &state).await?;
+    if FullBoost::get_by_profile_id_and_object(
+        &claims.profile_id,
+        status.note_id,
+        status.model_id,
+        state.pool.clone(),
+    )
+    .await
+    .is_ok()
+    {
+        return Ok(StatusCode::CONFLICT.into_response());
+    }
+    let boost = match boost_status(&status, &claims, state.pool.clone()).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Boost failed: {e}");
+            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&boost).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn unboost(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<StatusId>,
+) -> AppResult<impl IntoResponse> {
+    let status = get_status(&input.id, &claims, &state).await?;
+    FullBoost::get_by_profile_id_and_object(
+        &claims.profile_id,
+        status.note_id,
+        status.model_id,
+        state.pool.clone(),
+    )
+    .await?;
+    if let Err(e) = unboost_status(&status, &claims, state.pool.clone()).await {
+        error!("Unboost failed: {e}");
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+    Ok(StatusCode::OK.into_response())
+}
@@ -0,0 +1,54 @@
+use crate::helpers::activitypub::outbox_activities::send_actor_update;
+use crate::helpers::auth::UserState;
+use crate::helpers::AppResult;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, Extension};
+use serde::Serialize;
+use shared::helpers::keys::KeyService;
+use shared::AppState;
+use std::sync::Arc;
+use tracing::error;
+
+#[derive(Serialize)]
+pub struct PublicKeyOutput {
+    pub id: String,
+    pub public_key_pem: String,
+}
+
+#[debug_handler]
+pub async fn rotate_key(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    //! Replaces the key pair of the account and announces the new public key with an `Update`.
+    //! Deliveries still queued are signed with the new key.
+    let profile = match KeyService::new(&state.env)
+        .rotate_key(&claims.profile_id, state.pool.clone())
+        .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Failed to rotate the key of {}: {e}", claims.server_id);
+            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+    let output = PublicKeyOutput {
+        id: format!("{}#main-key", profile.server_id),
+        public_key_pem: profile.public_key.clone(),
+    };
+    // Remote servers also refetch the actor once a signature doesn't verify anymore
+    if let Err(e) = send_actor_update(profile, state.clone()).await {
+        error!(
+            "Failed to federate the new key of {}: {e}",
+            claims.server_id
+        );
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&output).unwrap()))
+        .unwrap())
+}
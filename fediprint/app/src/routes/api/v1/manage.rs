@@ -1,16 +1,19 @@
 use crate::helpers::auth::UserState;
-use crate::helpers::interactions::follow_user;
+use crate::helpers::interactions::{block_user, follow_user, unblock_user, unfollow_user};
 use crate::helpers::AppResult;
-use shared::AppState;
 use axum::body::Body;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::{debug_handler, Extension, Json};
-use regex::Regex;
 use serde::Deserialize;
+use shared::db::blocks::{CreateMute, FullBlock, FullMute, HiddenProfile};
+use shared::db::followers::FullFollower;
 use shared::db::profile::FullProfile;
+use shared::helpers::keys::KeyService;
+use shared::AppState;
 use std::sync::Arc;
+use tracing::error;
 
 #[derive(Deserialize)]
 pub struct FollowUser {
@@ -23,62 +26,251 @@ pub async fn follow_user_route(
     State(state): State<Arc<AppState>>,
     Json(input): Json<FollowUser>,
 ) -> AppResult<impl IntoResponse> {
-    let user_regex = Regex::new(r"@?(.*)@(.*\..{2,})").unwrap();
-    let bad_request = Ok(Response::builder()
-        .status(StatusCode::BAD_REQUEST)
-        .body(Body::from(""))
-        .unwrap());
-    let caps = match user_regex.captures(&input.user) {
-        Some(d) => d,
-        None => {
-            return bad_request;
+    let to_follow = match FullProfile::get_by_handle(
+        &input.user,
+        &state.env.public_url,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap());
         }
     };
-    let username = match caps.get(1) {
-        Some(d) => d.as_str(),
-        None => {
-            return bad_request;
+    if to_follow.id == claims.profile_id {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+    if let Ok(d) = FullFollower::get_by_profile_id_and_follower_id(
+        &to_follow.id,
+        &claims.profile_id,
+        state.pool.clone(),
+    )
+    .await
+    {
+        if !d.pending {
+            return Ok(StatusCode::CONFLICT.into_response());
+        }
+    }
+    let profile = FullProfile::get_by_id(&claims.profile_id, state.pool.clone()).await?;
+    let follow = match follow_user(&to_follow, &profile, state.pool.clone()).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Follow failed: {e}");
+            return Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(e.to_string()))
+                .unwrap());
         }
     };
-    let domain = match caps.get(2) {
-        Some(d) => d.as_str(),
-        None => {
-            return bad_request;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&follow).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn unfollow_user_route(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<FollowUser>,
+) -> AppResult<impl IntoResponse> {
+    let to_unfollow = match FullProfile::get_by_handle(
+        &input.user,
+        &state.env.public_url,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap());
+        }
+    };
+    // Make sure there is something to undo before talking to the remote server
+    FullFollower::get_by_profile_id_and_follower_id(
+        &to_unfollow.id,
+        &claims.profile_id,
+        state.pool.clone(),
+    )
+    .await?;
+    let profile = FullProfile::get_by_id(&claims.profile_id, state.pool.clone()).await?;
+    if let Err(e) = unfollow_user(&to_unfollow, &profile, state.pool.clone()).await {
+        error!("Unfollow failed: {e}");
+        return Ok(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string()))
+            .unwrap());
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn get_target_profile(
+    input: &FollowUser,
+    claims: &UserState,
+    state: &AppState,
+) -> Result<FullProfile, Response> {
+    //! Resolves the handle of a profile to block or mute, which can't be the own profile
+    let profile = match FullProfile::get_by_handle(
+        &input.user,
+        &state.env.public_url,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            return Err(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap());
         }
     };
-    let to_follow: FullProfile =
-        match FullProfile::get_by_username_and_server(username, domain, state.pool.clone()).await {
-            Ok(d) => d,
-            Err(_) => {
-                match create_remote_profile(
-                    username.to_string(),
-                    domain.to_string(),
-                    state.pool.clone(),
-                )
-                .await
-                {
-                    Ok(d) => d,
-                    Err(e) => {
-                        return Ok(Response::builder()
-                            .status(StatusCode::BAD_REQUEST)
-                            .body(Body::from(e.to_string()))
-                            .unwrap());
-                    }
-                }
-            }
-        };
-    match follow_user(&to_follow, &claims, &state.env).await {
-        Ok(_) => (),
+    if profile.id == claims.profile_id {
+        return Err(StatusCode::BAD_REQUEST.into_response());
+    }
+    Ok(profile)
+}
+
+#[debug_handler]
+pub async fn block_user_route(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<FollowUser>,
+) -> AppResult<impl IntoResponse> {
+    let to_block = match get_target_profile(&input, &claims, &state).await {
+        Ok(d) => d,
+        Err(e) => return Ok(e),
+    };
+    let block = match block_user(&to_block, &claims, state.pool.clone()).await {
+        Ok(d) => d,
         Err(e) => {
+            error!("Block failed: {e}");
             return Ok(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
                 .body(Body::from(e.to_string()))
                 .unwrap());
         }
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&block).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn unblock_user_route(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<FollowUser>,
+) -> AppResult<impl IntoResponse> {
+    let to_unblock = match get_target_profile(&input, &claims, &state).await {
+        Ok(d) => d,
+        Err(e) => return Ok(e),
+    };
+    // Make sure there is something to undo before talking to the remote server
+    FullBlock::get_by_profile_id_and_target_id(
+        &claims.profile_id,
+        &to_unblock.id,
+        state.pool.clone(),
+    )
+    .await?;
+    if let Err(e) = unblock_user(&to_unblock, &claims, state.pool.clone()).await {
+        error!("Unblock failed: {e}");
+        return Ok(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string()))
+            .unwrap());
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
+
+#[debug_handler]
+pub async fn get_blocks(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let blocks =
+        HiddenProfile::get_blocked_by_profile_id(&claims.profile_id, state.pool.clone()).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&blocks).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn mute_user_route(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<FollowUser>,
+) -> AppResult<impl IntoResponse> {
+    //! Mutes are never federated, they only hide the profile for the own listings
+    let to_mute = match get_target_profile(&input, &claims, &state).await {
+        Ok(d) => d,
+        Err(e) => return Ok(e),
+    };
+    let mute = CreateMute {
+        profile_id: claims.profile_id,
+        target_id: to_mute.id,
     }
+    .create(state.pool.clone())
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&mute).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn unmute_user_route(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<FollowUser>,
+) -> AppResult<impl IntoResponse> {
+    let to_unmute = match get_target_profile(&input, &claims, &state).await {
+        Ok(d) => d,
+        Err(e) => return Ok(e),
+    };
+    FullMute::get_by_profile_id_and_target_id(
+        &claims.profile_id,
+        &to_unmute.id,
+        state.pool.clone(),
+    )
+    .await?
+    .delete(state.pool.clone())
+    .await?;
 
+    Ok(StatusCode::OK.into_response())
+}
+
+#[debug_handler]
+pub async fn get_mutes(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let mutes =
+        HiddenProfile::get_muted_by_profile_id(&claims.profile_id, state.pool.clone()).await?;
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(Body::from(""))
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&mutes).unwrap()))
         .unwrap())
 }
@@ -0,0 +1,172 @@
+use crate::helpers::activitypub::outbox_activities::send_move;
+use crate::helpers::auth::UserState;
+use crate::helpers::interactions::move_followers;
+use crate::helpers::AppResult;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, Extension, Json};
+use serde::{Deserialize, Serialize};
+use shared::db::profile::FullProfile;
+use shared::helpers::keys::KeyService;
+use shared::AppState;
+use std::sync::Arc;
+use tracing::error;
+
+#[derive(Serialize)]
+pub struct Aliases {
+    pub also_known_as: Vec<String>,
+    pub moved_to: Option<String>,
+}
+
+impl From<FullProfile> for Aliases {
+    fn from(profile: FullProfile) -> Self {
+        Aliases {
+            also_known_as: profile.also_known_as,
+            moved_to: profile.moved_to,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SetAliases {
+    pub aliases: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MoveAccount {
+    pub user: String,
+}
+
+#[debug_handler]
+pub async fn get_aliases(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let profile = FullProfile::get_by_id(&claims.profile_id, state.pool.clone()).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&Aliases::from(profile)).unwrap(),
+        ))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn set_aliases(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<SetAliases>,
+) -> AppResult<impl IntoResponse> {
+    //! Replaces the accounts listed in `alsoKnownAs`, which are allowed to move to this account
+    let mut also_known_as: Vec<String> = vec![];
+    for handle in input.aliases {
+        let alias = match FullProfile::get_by_handle(
+            &handle,
+            &state.env.public_url,
+            &KeyService::new(&state.env),
+            state.pool.clone(),
+        )
+        .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from(format!("{handle}: {e}")))
+                    .unwrap());
+            }
+        };
+        if alias.id == claims.profile_id {
+            return Ok(StatusCode::BAD_REQUEST.into_response());
+        }
+        if !also_known_as.contains(&alias.server_id) {
+            also_known_as.push(alias.server_id);
+        }
+    }
+    let profile =
+        FullProfile::set_also_known_as(&claims.profile_id, &also_known_as, state.pool.clone())
+            .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&Aliases::from(profile)).unwrap(),
+        ))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn move_account(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<MoveAccount>,
+) -> AppResult<impl IntoResponse> {
+    //! Moves the followers to another account, which has to list this account in `alsoKnownAs`.
+    //! Remote followers get a `Move`, local ones are re-pointed right away.
+    let target = match FullProfile::get_by_handle(
+        &input.user,
+        &state.env.public_url,
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap());
+        }
+    };
+    if target.id == claims.profile_id {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+    let target = match target
+        .refresh_aliases(&KeyService::new(&state.env), state.pool.clone())
+        .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Failed to fetch the aliases of the move target: {e}");
+            return Ok(StatusCode::BAD_GATEWAY.into_response());
+        }
+    };
+    if !target.also_known_as.contains(&claims.server_id) {
+        return Ok(Response::builder()
+            .status(StatusCode::UNPROCESSABLE_ENTITY)
+            .body(Body::from(
+                "The target account has to list this account as alias first",
+            ))
+            .unwrap());
+    }
+    let profile = FullProfile::get_by_id(&claims.profile_id, state.pool.clone()).await?;
+    if let Err(e) = send_move(&profile, &target, state.clone()).await {
+        error!("Failed to federate move of {}: {e}", profile.server_id);
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+    if let Err(e) = move_followers(&profile, &target, state.pool.clone()).await {
+        error!(
+            "Failed to move local followers of {}: {e}",
+            profile.server_id
+        );
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+    // Only marked as moved once the followers were told, so a failed move can be retried
+    let profile = FullProfile::set_moved_to(
+        &claims.profile_id,
+        Some(&target.server_id),
+        state.pool.clone(),
+    )
+    .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&Aliases::from(profile)).unwrap(),
+        ))
+        .unwrap())
+}
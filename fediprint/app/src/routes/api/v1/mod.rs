@@ -1,11 +1,18 @@
 pub mod activitypub;
+pub mod admin;
 pub mod auth;
+pub mod follow_requests;
 pub mod interact;
+pub mod keys;
 pub mod links;
-// pub mod manage;
+pub mod manage;
+pub mod migration;
 pub mod model;
 pub mod printers;
+pub mod reports;
+pub mod sessions;
 pub mod storage;
+pub mod two_factor;
 
 pub mod nodeinfo;
 pub mod payments;
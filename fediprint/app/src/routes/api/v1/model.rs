@@ -1,17 +1,29 @@
+use crate::helpers::activitypub::outbox_activities::{send_status_activity, send_status_delete};
 use crate::helpers::auth::UserState;
-use crate::helpers::search::{index_model, search};
+use crate::helpers::search::{index_model, remove_from_index, search};
+use crate::helpers::viewer::get_viewer;
 use crate::helpers::AppResult;
 use crate::routes::api::v1::storage::PaginationQuery;
 use axum::body::Body;
 use axum::extract::{Path, Query, State};
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
 use axum::{debug_handler, Extension, Json};
+use axum_extra::extract::cookie::CookieJar;
 use serde_derive::{Deserialize, Serialize};
+use shared::db::blocks::HiddenProfile;
+use shared::db::boosts::FullBoost;
+use shared::db::instances::FullInstance;
+use shared::db::likes::FullLike;
 use shared::db::model::{CreateModel as DbCreateModel, FullModel, FullModelWithRelationsIds};
-use shared::models::model::CreateModel;
+use shared::db::note::FullNote;
+use shared::db::profile::FullProfile;
+use shared::db::EventAudience;
+use shared::models::model::{CreateModel, UpdateModel};
 use shared::AppState;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use tracing::error;
 use uuid::Uuid;
 
 #[debug_handler]
@@ -53,6 +65,19 @@ pub async fn create_model(
     .await?;
     let s_id = format!("{}/api/v1/statuses/{}", state.env.public_url, &res.id);
     let model = FullModel::update_server_id_and_return(&res.id, &s_id, state.pool.clone()).await?;
+    if model.published {
+        if let Err(e) = send_status_activity(
+            "Create",
+            &model.id,
+            &claims.profile_id,
+            &EventAudience::Public,
+            state.clone(),
+        )
+        .await
+        {
+            error!("Failed to federate model: {e}");
+        }
+    }
     Ok(Response::builder()
         .status(StatusCode::CREATED)
         .header("Content-Type", "application/json")
@@ -99,6 +124,11 @@ pub async fn change_model_visibility(
     State(state): State<Arc<AppState>>,
     Json(input): Json<ChangeModelVisibilityInput>,
 ) -> AppResult<impl IntoResponse> {
+    let old_model =
+        FullModelWithRelationsIds::get_by_id(&input.model_id, true, state.pool.clone()).await?;
+    if old_model.profile_id != claims.profile_id {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
     let model = FullModelWithRelationsIds::change_visibility_with_id_and_profile_id(
         &input.public,
         &input.model_id,
@@ -107,7 +137,66 @@ pub async fn change_model_visibility(
         state.pool.clone(),
     )
     .await?;
-    index_model(&model, &claims.profile_id, &state.ms).await?;
+    let profile = FullProfile::get_by_id(&claims.profile_id, state.pool.clone()).await?;
+    index_model(&model, &profile.id, &profile.instance, &state.ms).await?;
+    let res = match (old_model.published, model.published, &model.server_id) {
+        (false, true, _) => {
+            send_status_activity(
+                "Create",
+                &model.id,
+                &claims.profile_id,
+                &EventAudience::Public,
+                state.clone(),
+            )
+            .await
+        }
+        (true, false, Some(server_id)) => {
+            send_status_delete(
+                server_id,
+                &claims.profile_id,
+                &EventAudience::Public,
+                vec![],
+                state.clone(),
+            )
+            .await
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = res {
+        error!("Failed to federate visibility change: {e}");
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&model).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn update_model(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<UpdateModel>,
+) -> AppResult<impl IntoResponse> {
+    let model =
+        FullModel::update_by_id_and_profile_id(input, &claims.profile_id, state.pool.clone())
+            .await?;
+    let model = FullModelWithRelationsIds::get_by_id(&model.id, true, state.pool.clone()).await?;
+    let profile = FullProfile::get_by_id(&claims.profile_id, state.pool.clone()).await?;
+    index_model(&model, &profile.id, &profile.instance, &state.ms).await?;
+    if model.published {
+        if let Err(e) = send_status_activity(
+            "Update",
+            &model.id,
+            &claims.profile_id,
+            &EventAudience::Public,
+            state.clone(),
+        )
+        .await
+        {
+            error!("Failed to federate model update: {e}");
+        }
+    }
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
@@ -115,10 +204,40 @@ pub async fn change_model_visibility(
         .unwrap())
 }
 
+#[debug_handler]
+pub async fn delete_model(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    query: Query<GetModelQuery>,
+) -> AppResult<impl IntoResponse> {
+    let model =
+        FullModel::delete_by_id_and_profile_id(&query.id, &claims.profile_id, state.pool.clone())
+            .await?;
+    remove_from_index(&model.id, &state.ms).await?;
+    if let (true, Some(server_id)) = (model.published, &model.server_id) {
+        if let Err(e) = send_status_delete(
+            server_id,
+            &claims.profile_id,
+            &EventAudience::Public,
+            vec![],
+            state.clone(),
+        )
+        .await
+        {
+            error!("Failed to federate model deletion: {e}");
+        }
+    }
+    Ok(StatusCode::OK.into_response())
+}
+
 #[debug_handler]
 pub async fn get_newest_models(
     State(state): State<Arc<AppState>>,
     query: Query<PaginationQuery>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
 ) -> AppResult<impl IntoResponse> {
     if query.page < 0 {
         return Ok(Response::builder()
@@ -126,10 +245,12 @@ pub async fn get_newest_models(
             .body(Body::from("page can't be less than 0"))
             .unwrap());
     }
+    let viewer = get_viewer(&cookie_jar, &method, &uri, &headers, &state).await;
     let models = FullModelWithRelationsIds::get_newest_published_models_paginated(
         &20i64,
         &((&query.page * 20) as i64),
         false,
+        &viewer,
         state.pool.clone(),
     )
     .await?;
@@ -149,8 +270,23 @@ pub struct GetModelQuery {
 pub async fn get_model(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
 ) -> AppResult<impl IntoResponse> {
+    let viewer = get_viewer(&cookie_jar, &method, &uri, &headers, &state).await;
+    if !viewer.can_view_model(&id, state.pool.clone()).await? {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
     let model = FullModelWithRelationsIds::get_by_id(&id, false, state.pool.clone()).await?;
+    let like_count = FullLike::count_by_object(None, Some(model.id), state.pool.clone()).await?;
+    let boost_count = FullBoost::count_by_object(None, Some(model.id), state.pool.clone()).await?;
+    let model = ModelWithInteractions {
+        model,
+        like_count,
+        boost_count,
+    };
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
@@ -158,6 +294,107 @@ pub async fn get_model(
         .unwrap())
 }
 
+#[derive(Serialize)]
+pub struct ModelWithInteractions {
+    #[serde(flatten)]
+    pub model: FullModelWithRelationsIds,
+    pub like_count: i64,
+    pub boost_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct ThreadNode {
+    #[serde(flatten)]
+    pub note: FullNote,
+    pub replies: Vec<ThreadNode>,
+}
+
+// Deeper replies are shown on the last level, so remote chains can't make the response arbitrarily deep
+const MAX_THREAD_DEPTH: usize = 32;
+
+fn build_thread(root: &Uuid, comments: Vec<FullNote>) -> Vec<ThreadNode> {
+    //! Nests the comments under the comments they reply to. Remote data can contain cycles, so every
+    //! comment is placed once, breadth first from the model. Comments that can't be reached from the
+    //! model, like replies to deleted comments or cycles, are shown directly under the model.
+    let order: Vec<Uuid> = comments.iter().map(|v| v.id).collect();
+    let mut children: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for comment in &comments {
+        let parent = comment.in_reply_to_comment_id.unwrap_or(*root);
+        children.entry(parent).or_default().push(comment.id);
+    }
+    let mut notes: HashMap<Uuid, FullNote> = comments.into_iter().map(|v| (v.id, v)).collect();
+    // The comment and the one it's shown under, parents always come first
+    let mut placed: Vec<(Uuid, Uuid)> = vec![];
+    let mut depths: HashMap<Uuid, usize> = HashMap::from([(*root, 0)]);
+    let mut shown_under: HashMap<Uuid, Uuid> = HashMap::new();
+    let mut queue: VecDeque<Uuid> = VecDeque::from([*root]);
+    let mut unreached = order.iter();
+    loop {
+        while let Some(id) = queue.pop_front() {
+            let depth = depths[&id];
+            let (parent, child_depth) = match shown_under.get(&id) {
+                Some(d) if depth >= MAX_THREAD_DEPTH => (*d, depth),
+                _ => (id, depth + 1),
+            };
+            for child in children.remove(&id).unwrap_or_default() {
+                if depths.contains_key(&child) {
+                    continue;
+                }
+                depths.insert(child, child_depth);
+                shown_under.insert(child, parent);
+                placed.push((child, parent));
+                queue.push_back(child);
+            }
+        }
+        let Some(next) = unreached.find(|v| !depths.contains_key(v)) else {
+            break;
+        };
+        depths.insert(*next, 1);
+        shown_under.insert(*next, *root);
+        placed.push((*next, *root));
+        queue.push_back(*next);
+    }
+    // Built from the deepest comments up, so no recursion is needed
+    let mut replies: HashMap<Uuid, Vec<ThreadNode>> = HashMap::new();
+    for (id, parent) in placed.into_iter().rev() {
+        let Some(note) = notes.remove(&id) else {
+            continue;
+        };
+        let mut node_replies = replies.remove(&id).unwrap_or_default();
+        node_replies.reverse();
+        replies.entry(parent).or_default().push(ThreadNode {
+            note,
+            replies: node_replies,
+        });
+    }
+    let mut thread = replies.remove(root).unwrap_or_default();
+    thread.reverse();
+    thread
+}
+
+#[debug_handler]
+pub async fn get_thread(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> AppResult<impl IntoResponse> {
+    let viewer = get_viewer(&cookie_jar, &method, &uri, &headers, &state).await;
+    if !viewer.can_view_model(&id, state.pool.clone()).await? {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let model = FullModelWithRelationsIds::get_by_id(&id, false, state.pool.clone()).await?;
+    let comments = FullNote::get_comments_of_model(&model.id, &viewer, state.pool.clone()).await?;
+    let thread = build_thread(&model.id, comments);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&thread).unwrap()))
+        .unwrap())
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct SearchModelsQuery {
     pub q: String,
@@ -168,8 +405,28 @@ pub struct SearchModelsQuery {
 pub async fn search_models(
     State(state): State<Arc<AppState>>,
     query: Query<SearchModelsQuery>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
 ) -> AppResult<impl IntoResponse> {
-    let data = search(&query.q, query.page, 20i64, &state.ms).await?;
+    let viewer = get_viewer(&cookie_jar, &method, &uri, &headers, &state).await;
+    let hidden_instances = FullInstance::get_hidden_ids(state.pool.clone()).await?;
+    let hidden_profiles = match viewer.profile_id {
+        Some(profile_id) => {
+            HiddenProfile::get_hidden_profile_ids(&profile_id, state.pool.clone()).await?
+        }
+        None => vec![],
+    };
+    let data = search(
+        &query.q,
+        query.page,
+        20i64,
+        &hidden_instances,
+        &hidden_profiles,
+        &state.ms,
+    )
+    .await?;
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
@@ -177,11 +434,85 @@ pub async fn search_models(
         .unwrap())
 }
 
-/*#[cfg(test)]
+#[cfg(test)]
 mod tests {
-    use axum::http::{self, Request, StatusCode};
+    use super::*;
+    use chrono::Utc;
+
+    fn comment(id: u128, in_reply_to: Option<u128>) -> FullNote {
+        FullNote {
+            id: Uuid::from_u128(id),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            server_id: None,
+            content: "".to_string(),
+            hashtags: vec![],
+            audience: EventAudience::Public,
+            in_reply_to_comment_id: in_reply_to.map(Uuid::from_u128),
+            in_reply_to_note_id: None,
+            in_reply_to_model_id: None,
+            actor_id: Uuid::from_u128(0),
+            comment_of_model_id: None,
+        }
+    }
 
-    #[tokio::test]
-    async fn create_models() {}
+    fn ids(thread: &[ThreadNode]) -> Vec<u128> {
+        thread.iter().map(|v| v.note.id.as_u128()).collect()
+    }
+
+    #[test]
+    fn test_build_thread() {
+        let root = Uuid::from_u128(100);
+        let thread = build_thread(
+            &root,
+            vec![
+                comment(1, None),
+                comment(2, Some(1)),
+                comment(3, None),
+                comment(4, Some(2)),
+                // The comment it replies to is gone
+                comment(5, Some(99)),
+            ],
+        );
+        assert_eq!(ids(&thread), vec![1, 3, 5]);
+        assert_eq!(ids(&thread[0].replies), vec![2]);
+        assert_eq!(ids(&thread[0].replies[0].replies), vec![4]);
+        assert!(thread[1].replies.is_empty());
+    }
 
-}*/
+    #[test]
+    fn test_build_thread_with_cycle() {
+        let root = Uuid::from_u128(100);
+        let thread = build_thread(
+            &root,
+            vec![
+                comment(1, Some(2)),
+                comment(2, Some(1)),
+                comment(3, Some(3)),
+            ],
+        );
+        assert_eq!(ids(&thread), vec![1, 3]);
+        assert_eq!(ids(&thread[0].replies), vec![2]);
+        assert!(thread[0].replies[0].replies.is_empty());
+        assert!(thread[1].replies.is_empty());
+    }
+
+    #[test]
+    fn test_build_thread_depth_cap() {
+        let root = Uuid::from_u128(0);
+        let comments = (1..=10_000)
+            .map(|v| comment(v, (v > 1).then(|| v - 1)))
+            .collect();
+        let thread = build_thread(&root, comments);
+        let mut level = &thread;
+        let mut depth = 0;
+        let mut count = 0;
+        while !level.is_empty() {
+            depth += 1;
+            count += level.len();
+            level = &level.last().unwrap().replies;
+        }
+        assert_eq!(depth, MAX_THREAD_DEPTH);
+        assert_eq!(count, 10_000);
+    }
+}
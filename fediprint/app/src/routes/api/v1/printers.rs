@@ -122,8 +122,7 @@ mod tests {
         // Test slicer_config too big
         let mut too_big = printer.clone();
         too_big.slicer_config = Some(
-            std::iter::repeat("x")
-                .take(70 * 1000)
+            std::iter::repeat_n("x", 70 * 1000)
                 .map(|c| c.parse::<char>().unwrap())
                 .collect(),
         );
@@ -142,7 +141,7 @@ mod tests {
             .into_response();
         assert_eq!(res.status(), StatusCode::OK);
         let b = res.into_body();
-        let j: Value = serde_json::from_slice(&*b.collect().await.unwrap().to_bytes()).unwrap();
+        let j: Value = serde_json::from_slice(&b.collect().await.unwrap().to_bytes()).unwrap();
         let j_s = j.to_string();
         assert!(j_s.contains("Printer") && j_s.contains("Printer2"))
     }
@@ -166,7 +165,7 @@ mod tests {
             .into_response();
         assert_eq!(res.status(), StatusCode::OK);
         let j: Value =
-            serde_json::from_slice(&*res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+            serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
         assert_eq!(
             j.get("name"),
             Some(Value::from(update_data.name.clone())).as_ref()
@@ -178,8 +177,7 @@ mod tests {
         // Test Payload too large
         let mut too_big = update_data.clone();
         too_big.slicer_config = Some(
-            std::iter::repeat("x")
-                .take(70 * 1000)
+            std::iter::repeat_n("x", 70 * 1000)
                 .map(|c| c.parse::<char>().unwrap())
                 .collect(),
         );
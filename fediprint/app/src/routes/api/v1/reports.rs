@@ -0,0 +1,121 @@
+use crate::helpers::activitypub::outbox_activities::send_report_flag;
+use crate::helpers::auth::UserState;
+use crate::helpers::AppResult;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, Extension, Json};
+use serde::Deserialize;
+use shared::db::model::FullModelWithRelationsIds;
+use shared::db::note::FullNote;
+use shared::db::profile::FullProfile;
+use shared::db::reports::CreateReport;
+use shared::db::visibility::Viewer;
+use shared::helpers::keys::KeyService;
+use shared::AppState;
+use std::sync::Arc;
+use tracing::error;
+use uuid::{uuid, Uuid};
+
+#[derive(Deserialize)]
+pub struct ReportInput {
+    pub user: Option<String>,
+    pub note_id: Option<Uuid>,
+    pub model_id: Option<Uuid>,
+    pub reason: String,
+    #[serde(default)]
+    pub forward: bool,
+}
+
+#[debug_handler]
+pub async fn create_report(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ReportInput>,
+) -> AppResult<impl IntoResponse> {
+    //! Reports a note, a model or a profile. Notes and models can only be reported if they are visible
+    //! to the reporter. Reports of remote content can be forwarded to its server.
+    if input.reason.trim().is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("A reason is required"))
+            .unwrap());
+    }
+    let viewer = Viewer::from_profile_id(&claims.profile_id);
+    let (profile_id, object_id) = match (input.note_id, input.model_id, &input.user) {
+        (Some(note_id), None, None) => {
+            if !viewer.can_view_note(&note_id, state.pool.clone()).await? {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            }
+            let note = FullNote::get_by_id(&note_id, state.pool.clone()).await?;
+            (note.actor_id, note.server_id)
+        }
+        (None, Some(model_id), None) => {
+            if !viewer.can_view_model(&model_id, state.pool.clone()).await? {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            }
+            let model =
+                FullModelWithRelationsIds::get_by_id(&model_id, false, state.pool.clone()).await?;
+            (model.profile_id, model.server_id)
+        }
+        (None, None, Some(user)) => {
+            match FullProfile::get_by_handle(
+                user,
+                &state.env.public_url,
+                &KeyService::new(&state.env),
+                state.pool.clone(),
+            )
+            .await
+            {
+                Ok(d) => (d.id, None),
+                Err(e) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from(e.to_string()))
+                        .unwrap());
+                }
+            }
+        }
+        _ => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(
+                    "Exactly one of user, note_id and model_id is required",
+                ))
+                .unwrap());
+        }
+    };
+    if profile_id == claims.profile_id {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+    let mut report = CreateReport {
+        reporter_id: claims.profile_id,
+        profile_id,
+        note_id: input.note_id,
+        model_id: input.model_id,
+        reason: input.reason,
+        activity_id: None,
+    }
+    .create(state.pool.clone())
+    .await?;
+    let profile = FullProfile::get_by_id(&profile_id, state.pool.clone()).await?;
+    if input.forward && profile.instance != uuid!("00000000-0000-0000-0000-000000000000") {
+        let object_ids = object_id.into_iter().collect();
+        match send_report_flag(&report, &profile, object_ids, state.clone()).await {
+            Ok(activity_id) => {
+                report = report
+                    .mark_forwarded(&activity_id, state.pool.clone())
+                    .await?
+            }
+            // The report is kept for the local moderators anyway
+            Err(e) => error!("Failed to forward report {}: {e}", report.id),
+        }
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&report).unwrap()))
+        .unwrap())
+}
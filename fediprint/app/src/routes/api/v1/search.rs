@@ -10,6 +10,7 @@ use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use shared::db::profile::FullProfile;
 use shared::helpers::instances::get_instance_by_base_url;
+use shared::helpers::keys::KeyService;
 use shared::AppState;
 use std::sync::Arc;
 use tracing::{debug, error};
@@ -41,23 +42,29 @@ pub async fn search_profiles(
         return Ok(StatusCode::BAD_REQUEST.into_response());
     }
     debug!("Parsed User handle: User: {}, Server: {}", &name, &server);
-    let instance =
-        match get_instance_by_base_url(&format!("https://{server}"), state.pool.clone()).await {
-            Ok(d) => d,
-            Err(e) => {
-                error!("Instance fetch failed: {e}");
-                return Ok((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "failed to fetch instance",
-                )
-                    .into_response());
-            }
-        };
+    let instance = match get_instance_by_base_url(
+        &format!("https://{server}"),
+        &KeyService::new(&state.env),
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Instance fetch failed: {e}");
+            return Ok((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to fetch instance",
+            )
+                .into_response());
+        }
+    };
 
     let profile = match FullProfile::get_by_name_and_instance_remote(
         name,
         &query.q,
         instance,
+        &KeyService::new(&state.env),
         state.pool.clone(),
     )
     .await
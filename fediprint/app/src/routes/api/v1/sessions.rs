@@ -0,0 +1,97 @@
+use crate::helpers::auth::UserState;
+use crate::helpers::AppResult;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, Extension};
+use axum_extra::extract::cookie::Cookie;
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use shared::db::sessions::FullSession;
+use shared::AppState;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct Session {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub last_seen_at: DateTime<Utc>,
+    pub current: bool,
+}
+
+#[debug_handler]
+pub async fn get_sessions(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    //! The last seen time and IP are updated whenever the token of a session is renewed
+    let sessions: Vec<Session> =
+        FullSession::get_active_by_account_id(&claims.sub, state.pool.clone())
+            .await?
+            .into_iter()
+            .map(|d| Session {
+                current: d.id == claims.sid,
+                id: d.id,
+                created_at: d.created_at,
+                user_agent: d.user_agent,
+                ip: d.ip,
+                last_seen_at: d.last_seen_at,
+            })
+            .collect();
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&sessions).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn revoke_session(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Path(id): Path<Uuid>,
+) -> AppResult<(CookieJar, StatusCode)> {
+    if !FullSession::revoke(&id, &claims.sub, state.pool.clone()).await? {
+        return Ok((jar, StatusCode::NOT_FOUND));
+    }
+    if id == claims.sid {
+        return Ok((
+            jar.remove(Cookie::build("authorization_key").path("/")),
+            StatusCode::OK,
+        ));
+    }
+    Ok((jar, StatusCode::OK))
+}
+
+#[debug_handler]
+pub async fn logout(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> AppResult<(CookieJar, StatusCode)> {
+    FullSession::revoke(&claims.sid, &claims.sub, state.pool.clone()).await?;
+    Ok((
+        jar.remove(Cookie::build("authorization_key").path("/")),
+        StatusCode::OK,
+    ))
+}
+
+#[debug_handler]
+pub async fn logout_everywhere(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> AppResult<(CookieJar, StatusCode)> {
+    //! Revokes all sessions of the account, including the current one
+    FullSession::revoke_all(&claims.sub, state.pool.clone()).await?;
+    Ok((
+        jar.remove(Cookie::build("authorization_key").path("/")),
+        StatusCode::OK,
+    ))
+}
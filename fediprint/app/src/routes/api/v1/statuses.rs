@@ -1,12 +1,17 @@
+use crate::helpers::viewer::get_authorized_viewer;
 use crate::helpers::{ensure_ap_header, AppResult};
 use axum::body::Body;
 use axum::debug_handler;
 use axum::extract::{Path, Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
+use shared::db::note::FullNote;
 use shared::helpers::activities::{get_remote_activity, ModelOrNote};
-use shared::models::activitypub::ActivityPubModel;
+use shared::models::activitypub::{
+    ActivityPubModel, CollectionPage, NoteJoinedModel, OrderedCollection,
+};
 use shared::AppState;
 use std::sync::Arc;
 use tracing::{debug, error};
@@ -16,13 +21,21 @@ use uuid::Uuid;
 pub async fn get_status(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
     headers: HeaderMap,
 ) -> AppResult<impl IntoResponse> {
     debug!("Working...");
     match ensure_ap_header(&headers) {
         Ok(_) => (),
+        Err(e) => return Ok(*e),
+    };
+    let viewer = match get_authorized_viewer(&cookie_jar, &method, &uri, &headers, &state).await {
+        Ok(d) => d,
         Err(e) => return Ok(e),
     };
+    NoteJoinedModel::get_by_id_for_viewer(&id, &viewer, state.pool.clone()).await?;
     let model =
         ActivityPubModel::get_by_id(&id, state.pool.clone(), state.env.public_url.clone()).await?;
     Ok(Response::builder()
@@ -62,3 +75,80 @@ pub async fn get_remote_status(
         }
     }
 }
+
+#[derive(Deserialize)]
+pub struct GetRepliesQuery {
+    pub page: Option<bool>,
+    pub min_id: Option<Uuid>,
+}
+
+const REPLIES_PAGE_SIZE: i64 = 20;
+
+#[debug_handler]
+pub async fn get_replies(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    query: Query<GetRepliesQuery>,
+    cookie_jar: CookieJar,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> AppResult<impl IntoResponse> {
+    match ensure_ap_header(&headers) {
+        Ok(_) => (),
+        Err(e) => return Ok(*e),
+    };
+    let viewer = match get_authorized_viewer(&cookie_jar, &method, &uri, &headers, &state).await {
+        Ok(d) => d,
+        Err(e) => return Ok(e),
+    };
+    let status = NoteJoinedModel::get_by_id_for_viewer(&id, &viewer, state.pool.clone()).await?;
+    let Some(server_id) = status.server_id else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let replies_url = format!("{server_id}/replies");
+    let body = if query.page.is_none() && query.min_id.is_none() {
+        serde_json::to_string(&OrderedCollection {
+            context: "https://www.w3.org/ns/activitystreams".to_string(),
+            first: format!("{replies_url}?page=true"),
+            id: replies_url,
+            last: None,
+            total_items: FullNote::count_replies(&id, &viewer, state.pool.clone()).await?,
+            type_field: "Collection".to_string(),
+        })
+        .unwrap()
+    } else {
+        let replies = FullNote::get_replies_paginated(
+            &id,
+            &viewer,
+            query.min_id,
+            REPLIES_PAGE_SIZE,
+            state.pool.clone(),
+        )
+        .await?;
+        let next = match replies.len() as i64 == REPLIES_PAGE_SIZE {
+            true => replies
+                .last()
+                .map(|v| format!("{replies_url}?min_id={}&page=true", v.id)),
+            false => None,
+        };
+        let page_id = match query.min_id {
+            Some(min_id) => format!("{replies_url}?min_id={min_id}&page=true"),
+            None => format!("{replies_url}?page=true"),
+        };
+        serde_json::to_string(&CollectionPage {
+            context: "https://www.w3.org/ns/activitystreams".to_string(),
+            id: page_id,
+            next,
+            items: replies.into_iter().filter_map(|v| v.server_id).collect(),
+            part_of: replies_url,
+            type_field: "CollectionPage".to_string(),
+        })
+        .unwrap()
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/activity+json; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap())
+}
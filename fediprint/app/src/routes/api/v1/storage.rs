@@ -46,7 +46,7 @@ pub async fn upload_file(
         };
         debug!("Filename: {}", &filename);
         debug!("Content-Type: {}", &content_type);
-        let body_with_io_error = field.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+        let body_with_io_error = field.map_err(io::Error::other);
         let body_reader = StreamReader::new(body_with_io_error);
         futures::pin_mut!(body_reader);
 
@@ -164,20 +164,20 @@ pub async fn delete_file(
 fn get_file_headers(file: FullFile) -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert("Content-Type", file.mime_type.parse().unwrap());
-    if file.file_name.is_some() {
+    if let Some(file_name) = file.file_name {
         headers.insert(
             "Content-Disposition",
-            format!("attachment; filename={}", file.file_name.unwrap())
+            format!("attachment; filename={}", file_name)
                 .parse()
                 .unwrap(),
         );
     }
 
-    if file.thumbhash.is_some() {
-        headers.insert("X-Blurhash", file.thumbhash.unwrap().parse().unwrap());
+    if let Some(thumbhash) = file.thumbhash {
+        headers.insert("X-Blurhash", thumbhash.parse().unwrap());
     }
-    if file.alt_text.is_some() {
-        headers.insert("X-Alttext", file.alt_text.unwrap().parse().unwrap());
+    if let Some(alt_text) = file.alt_text {
+        headers.insert("X-Alttext", alt_text.parse().unwrap());
     }
     headers.insert("Content-Length", format!("{}", file.size).parse().unwrap());
     headers.insert(
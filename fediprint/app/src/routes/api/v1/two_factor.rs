@@ -0,0 +1,209 @@
+use crate::helpers::auth::{check_password_hash, UserState};
+use crate::helpers::AppResult;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{debug_handler, Extension, Json};
+use serde::{Deserialize, Serialize};
+use shared::db::account::FullAccount;
+use shared::db::two_factor::{FullRecoveryCode, FullTwoFactor};
+use shared::helpers::keys::KeyService;
+use shared::helpers::totp::{
+    check_code, generate_recovery_codes, generate_secret, get_provisioning_uri,
+    verify_second_factor,
+};
+use shared::AppState;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorSetup {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorPassword {
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableTwoFactor {
+    pub password: String,
+    pub code: String,
+}
+
+async fn check_current_password(
+    account_id: &Uuid,
+    password: String,
+    state: &AppState,
+) -> Result<bool, sqlx::Error> {
+    let acct = FullAccount::get_by_id(account_id, state.pool.clone()).await?;
+    Ok(check_password_hash(password, &acct.password))
+}
+
+#[debug_handler]
+pub async fn get_two_factor(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let enabled = FullTwoFactor::get_enabled(&claims.sub, state.pool.clone())
+        .await?
+        .is_some();
+    let recovery_codes_left =
+        FullRecoveryCode::count_unused(&claims.sub, state.pool.clone()).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&TwoFactorStatus {
+                enabled,
+                recovery_codes_left,
+            })
+            .unwrap(),
+        ))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn setup_two_factor(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<TwoFactorPassword>,
+) -> AppResult<impl IntoResponse> {
+    //! Starts the enrolment with a new secret. The provisioning URI is meant to be shown as QR code,
+    //! 2FA is only enabled once a code from the app was confirmed. Needs the current password.
+    if !check_current_password(&claims.sub, input.password, &state).await? {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("The password is wrong"))
+            .unwrap());
+    }
+    let Ok(secret) = generate_secret() else {
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    let Ok(encrypted_secret) = KeyService::new(&state.env).encrypt_secret(&secret) else {
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    if FullTwoFactor::start(&claims.sub, &encrypted_secret, state.pool.clone())
+        .await?
+        .is_none()
+    {
+        return Ok(Response::builder()
+            .status(StatusCode::CONFLICT)
+            .body(Body::from("2FA is already enabled"))
+            .unwrap());
+    }
+    let setup = TwoFactorSetup {
+        provisioning_uri: get_provisioning_uri(&secret, &claims.username, &state.env.base_domain),
+        secret,
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&setup).unwrap()))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn confirm_two_factor(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<TwoFactorCode>,
+) -> AppResult<impl IntoResponse> {
+    //! Enables 2FA with the first code of the app. Returns the recovery codes, which are only stored
+    //! hashed and can't be shown again.
+    let Some(two_factor) = FullTwoFactor::get_by_account_id(&claims.sub, state.pool.clone())
+        .await?
+        .filter(|d| d.confirmed_at.is_none())
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Ok(secret) = KeyService::new(&state.env).decrypt_secret(&two_factor.encrypted_secret)
+    else {
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    let Some(step) = check_code(&secret, &input.code, None) else {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("The code is invalid"))
+            .unwrap());
+    };
+    let Ok((recovery_codes, hashes)) = generate_recovery_codes() else {
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    if !FullTwoFactor::confirm(&claims.sub, step, &hashes, state.pool.clone()).await? {
+        return Ok(StatusCode::CONFLICT.into_response());
+    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&RecoveryCodes { recovery_codes }).unwrap(),
+        ))
+        .unwrap())
+}
+
+#[debug_handler]
+pub async fn disable_two_factor(
+    Extension(claims): Extension<UserState>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<DisableTwoFactor>,
+) -> AppResult<impl IntoResponse> {
+    //! Needs the current password and a fresh code from the app, recovery codes and a stolen
+    //! session aren't enough
+    if !check_current_password(&claims.sub, input.password, &state).await? {
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("The password is wrong"))
+            .unwrap());
+    }
+    let Some(two_factor) = FullTwoFactor::get_enabled(&claims.sub, state.pool.clone()).await?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if two_factor.is_locked() {
+        return Ok(Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .body(Body::from("Too many wrong codes, try again later"))
+            .unwrap());
+    }
+    let key_service = KeyService::new(&state.env);
+    match verify_second_factor(
+        &key_service,
+        &two_factor,
+        &input.code,
+        false,
+        state.pool.clone(),
+    )
+    .await
+    {
+        Ok(true) => (),
+        Ok(false) => {
+            return Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from("The code is invalid"))
+                .unwrap())
+        }
+        Err(_) => return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    }
+    FullTwoFactor::disable(&claims.sub, state.pool.clone()).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
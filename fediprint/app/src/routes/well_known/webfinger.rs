@@ -135,7 +135,7 @@ mod tests {
             "application/jrd+json; charset=utf-8"
         );
         let j: Webfinger =
-            serde_json::from_slice(&*res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+            serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
         assert_eq!(j.subject, "acct:testuser@localhost.local");
         // Check correctness of server
         let res = handler(
@@ -3,6 +3,8 @@ use serde_derive::Serialize;
 use sqlx::{Error, PgPool};
 use uuid::Uuid;
 
+use super::AccountRole;
+
 #[derive(Serialize, Debug, PartialEq)]
 pub struct CreateAccount<'a> {
     pub password: &'a str,
@@ -14,7 +16,7 @@ pub struct CreateAccount<'a> {
 impl CreateAccount<'_> {
     pub async fn create(self, pool: PgPool) -> Result<FullAccount, Error> {
         sqlx::query_as!(FullAccount,
-            "INSERT INTO account (password, email, profile_id, private_key) VALUES ($1, $2, $3, $4) RETURNING *",
+            r#"INSERT INTO account (password, email, profile_id, private_key) VALUES ($1, $2, $3, $4) RETURNING id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id, role AS "role!: AccountRole", suspended"#,
             self.password, self.email, self.profile_id, self.private_key
         ).fetch_one(&pool).await
     }
@@ -31,22 +33,24 @@ pub struct FullAccount {
     pub profile_id: Uuid,
     pub private_key: String,
     pub stripe_id: Option<String>,
+    pub role: AccountRole,
+    pub suspended: bool,
 }
 
 impl FullAccount {
     pub async fn get_by_id(id: &Uuid, pool: PgPool) -> Result<FullAccount, Error> {
         sqlx::query_as!(FullAccount,
-            r#"SELECT id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id FROM account where id = $1"#,
+            r#"SELECT id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id, role AS "role!: AccountRole", suspended FROM account where id = $1"#,
             id).fetch_one(&pool).await
     }
     pub async fn get_by_email(email: &str, pool: PgPool) -> Result<FullAccount, Error> {
         sqlx::query_as!(FullAccount,
-            "SELECT id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id FROM account where email = $1",
+            r#"SELECT id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id, role AS "role!: AccountRole", suspended FROM account where email = $1"#,
             email).fetch_one(&pool).await
     }
     pub async fn get_by_profile_id(id: &Uuid, pool: PgPool) -> Result<FullAccount, Error> {
         sqlx::query_as!(FullAccount,
-            r#"SELECT id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id FROM account WHERE profile_id = $1"#, id
+            r#"SELECT id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id, role AS "role!: AccountRole", suspended FROM account WHERE profile_id = $1"#, id
             ).fetch_one(&pool).await
     }
     pub async fn link_stripe_id(
@@ -56,8 +60,85 @@ impl FullAccount {
     ) -> Result<FullAccount, Error> {
         sqlx::query_as!(FullAccount,
             r#"UPDATE account SET stripe_id = $1 WHERE id = $2 RETURNING
-            id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id
+            id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id, role AS "role!: AccountRole", suspended
             "#, stripe_id, account_id
         ).fetch_one(&pool).await
     }
+    pub async fn set_role(
+        id: &Uuid,
+        role: AccountRole,
+        pool: PgPool,
+    ) -> Result<FullAccount, Error> {
+        sqlx::query_as!(FullAccount,
+            r#"UPDATE account SET role = $2, updated_at = NOW() WHERE id = $1 RETURNING
+            id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id, role AS "role!: AccountRole", suspended
+            "#, id, role as _
+        ).fetch_one(&pool).await
+    }
+    pub async fn set_suspended(
+        id: &Uuid,
+        suspended: bool,
+        pool: PgPool,
+    ) -> Result<FullAccount, Error> {
+        sqlx::query_as!(FullAccount,
+            r#"UPDATE account SET suspended = $2, updated_at = NOW() WHERE id = $1 RETURNING
+            id, registered_at, updated_at, password, email, verified, profile_id, private_key, stripe_id, role AS "role!: AccountRole", suspended
+            "#, id, suspended
+        ).fetch_one(&pool).await
+    }
+    pub async fn verify_email(id: &Uuid, code: &str, pool: PgPool) -> Result<bool, Error> {
+        //! Clears the verification code if it matches, false if the code is wrong or already used
+        let res = sqlx::query!(
+            r#"UPDATE account SET verified = NULL, updated_at = NOW() WHERE id = $1 AND verified = $2"#,
+            id,
+            code
+        )
+        .execute(&pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+    pub async fn promote_admins(usernames: &[String], pool: PgPool) -> Result<(), Error> {
+        //! Gives the local profiles with these usernames the admin role, so a fresh instance has an admin
+        sqlx::query!(
+            r#"UPDATE account SET role = 'ADMIN' FROM profile p
+            WHERE p.id = account.profile_id AND p.instance = '00000000-0000-0000-0000-000000000000' AND p.username = ANY($1)"#,
+            usernames
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::uuid;
+
+    const ACCOUNT: Uuid = uuid!("018e7b20-51e5-79c2-878e-02d01f941165");
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_verify_email(pool: PgPool) {
+        assert!(!FullAccount::verify_email(&ACCOUNT, "wrong", pool.clone())
+            .await
+            .unwrap());
+        let code = "018e7b20-51e5-7fd9-b45c-b770542c1976";
+        assert!(FullAccount::verify_email(&ACCOUNT, code, pool.clone())
+            .await
+            .unwrap());
+        let account = FullAccount::get_by_id(&ACCOUNT, pool.clone())
+            .await
+            .unwrap();
+        assert_eq!(account.verified, None);
+        // The code can't be used twice
+        assert!(!FullAccount::verify_email(&ACCOUNT, code, pool)
+            .await
+            .unwrap());
+    }
 }
@@ -0,0 +1,57 @@
+use super::AuditAction;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateAuditLogEntry {
+    pub actor_id: Uuid,
+    pub action: AuditAction,
+    pub target: String,
+    pub details: Option<String>,
+}
+
+impl CreateAuditLogEntry {
+    pub async fn create(self, pool: PgPool) -> Result<FullAuditLogEntry, Error> {
+        sqlx::query_as!(
+            FullAuditLogEntry,
+            r#"INSERT INTO audit_log (actor_id, action, target, details) VALUES ($1, $2, $3, $4)
+            RETURNING id, created_at, actor_id, action AS "action!: AuditAction", target, details"#,
+            self.actor_id,
+            self.action as _,
+            self.target,
+            self.details
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct FullAuditLogEntry {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub actor_id: Option<Uuid>,
+    pub action: AuditAction,
+    pub target: String,
+    pub details: Option<String>,
+}
+
+impl FullAuditLogEntry {
+    pub async fn get_newest_paginated(
+        limit: &i64,
+        offset: &i64,
+        pool: PgPool,
+    ) -> Result<Vec<FullAuditLogEntry>, Error> {
+        sqlx::query_as!(
+            FullAuditLogEntry,
+            r#"SELECT id, created_at, actor_id, action AS "action!: AuditAction", target, details
+            FROM audit_log ORDER BY created_at DESC OFFSET $1 LIMIT $2"#,
+            offset,
+            limit
+        )
+        .fetch_all(&pool)
+        .await
+    }
+}
@@ -0,0 +1,319 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateBlock {
+    pub profile_id: Uuid,
+    pub target_id: Uuid,
+    pub activity_id: Option<String>,
+}
+
+impl CreateBlock {
+    pub async fn create(self, pool: PgPool) -> Result<FullBlock, Error> {
+        //! Returns the existing block if the profile already blocked the target
+        let block = sqlx::query_as!(
+            FullBlock,
+            r#"INSERT INTO blocks (profile_id, target_id, activity_id) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            RETURNING id, created_at, profile_id, target_id, activity_id"#,
+            self.profile_id,
+            self.target_id,
+            self.activity_id
+        )
+        .fetch_optional(&pool)
+        .await?;
+        match block {
+            Some(d) => Ok(d),
+            None => {
+                FullBlock::get_by_profile_id_and_target_id(&self.profile_id, &self.target_id, pool)
+                    .await
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullBlock {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub profile_id: Uuid,
+    pub target_id: Uuid,
+    pub activity_id: Option<String>,
+}
+
+impl FullBlock {
+    pub async fn get_by_profile_id_and_target_id(
+        profile_id: &Uuid,
+        target_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<FullBlock, Error> {
+        sqlx::query_as!(
+            FullBlock,
+            r#"SELECT id, created_at, profile_id, target_id, activity_id
+            FROM blocks WHERE profile_id = $1 AND target_id = $2"#,
+            profile_id,
+            target_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn is_blocked_by_any(
+        actor_server_id: &str,
+        profile_server_ids: &[String],
+        pool: PgPool,
+    ) -> Result<bool, Error> {
+        //! Whether any of the given profiles blocked the actor
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM blocks b
+                JOIN profile p ON p.id = b.profile_id
+                JOIN profile t ON t.id = b.target_id
+                WHERE t.server_id = $1 AND p.server_id = ANY($2)) AS "blocked!""#,
+            actor_server_id,
+            profile_server_ids
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn delete(self, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(r#"DELETE FROM blocks WHERE id = $1"#, self.id)
+            .execute(&pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateMute {
+    pub profile_id: Uuid,
+    pub target_id: Uuid,
+}
+
+impl CreateMute {
+    pub async fn create(self, pool: PgPool) -> Result<FullMute, Error> {
+        //! Returns the existing mute if the profile already muted the target
+        let mute = sqlx::query_as!(
+            FullMute,
+            r#"INSERT INTO mutes (profile_id, target_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            RETURNING id, created_at, profile_id, target_id"#,
+            self.profile_id,
+            self.target_id
+        )
+        .fetch_optional(&pool)
+        .await?;
+        match mute {
+            Some(d) => Ok(d),
+            None => {
+                FullMute::get_by_profile_id_and_target_id(&self.profile_id, &self.target_id, pool)
+                    .await
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullMute {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub profile_id: Uuid,
+    pub target_id: Uuid,
+}
+
+impl FullMute {
+    pub async fn get_by_profile_id_and_target_id(
+        profile_id: &Uuid,
+        target_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<FullMute, Error> {
+        sqlx::query_as!(
+            FullMute,
+            r#"SELECT id, created_at, profile_id, target_id
+            FROM mutes WHERE profile_id = $1 AND target_id = $2"#,
+            profile_id,
+            target_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn delete(self, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(r#"DELETE FROM mutes WHERE id = $1"#, self.id)
+            .execute(&pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct HiddenProfile {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub target_id: Uuid,
+    pub username: String,
+    pub server_id: String,
+    pub display_name: String,
+    pub instance: Uuid,
+}
+
+impl HiddenProfile {
+    pub async fn get_blocked_by_profile_id(
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<HiddenProfile>, Error> {
+        sqlx::query_as!(
+            HiddenProfile,
+            r#"SELECT b.id, b.created_at, b.target_id, p.username, p.server_id, p.display_name, p.instance
+            FROM blocks b
+            JOIN profile p ON p.id = b.target_id
+            WHERE b.profile_id = $1
+            ORDER BY b.created_at"#,
+            profile_id
+        )
+        .fetch_all(&pool)
+        .await
+    }
+
+    pub async fn get_muted_by_profile_id(
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<HiddenProfile>, Error> {
+        sqlx::query_as!(
+            HiddenProfile,
+            r#"SELECT m.id, m.created_at, m.target_id, p.username, p.server_id, p.display_name, p.instance
+            FROM mutes m
+            JOIN profile p ON p.id = m.target_id
+            WHERE m.profile_id = $1
+            ORDER BY m.created_at"#,
+            profile_id
+        )
+        .fetch_all(&pool)
+        .await
+    }
+
+    pub async fn get_hidden_profile_ids(
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<Uuid>, Error> {
+        //! Every profile whose content is left out of the listings of the profile, see `is_profile_hidden`
+        sqlx::query_scalar!(
+            r#"SELECT target_id AS "id!" FROM blocks WHERE profile_id = $1
+            UNION SELECT target_id FROM mutes WHERE profile_id = $1
+            UNION SELECT profile_id FROM blocks WHERE target_id = $1"#,
+            profile_id
+        )
+        .fetch_all(&pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::visibility::Viewer;
+    use uuid::uuid;
+
+    const AUTHOR: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c67");
+    const ALICE: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c68");
+    const BOB: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c69");
+
+    async fn is_hidden(profile_id: &Uuid, viewer: Option<Uuid>, pool: &PgPool) -> bool {
+        sqlx::query_scalar("SELECT is_profile_hidden($1, $2)")
+            .bind(profile_id)
+            .bind(viewer)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles")
+        )
+    )]
+    async fn test_is_profile_hidden(pool: PgPool) {
+        assert!(!is_hidden(&AUTHOR, Some(ALICE), &pool).await);
+        assert!(!is_hidden(&AUTHOR, None, &pool).await);
+        let block = CreateBlock {
+            profile_id: ALICE,
+            target_id: AUTHOR,
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        // Blocking twice returns the existing block
+        let again = CreateBlock {
+            profile_id: ALICE,
+            target_id: AUTHOR,
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert_eq!(block, again);
+        // Hidden in both directions
+        assert!(is_hidden(&AUTHOR, Some(ALICE), &pool).await);
+        assert!(is_hidden(&ALICE, Some(AUTHOR), &pool).await);
+        assert!(!is_hidden(&AUTHOR, Some(BOB), &pool).await);
+        assert!(!is_hidden(&AUTHOR, None, &pool).await);
+        block.delete(pool.clone()).await.unwrap();
+        assert!(!is_hidden(&AUTHOR, Some(ALICE), &pool).await);
+
+        // Mutes only hide the muted profile from the one who muted it
+        let mute = CreateMute {
+            profile_id: BOB,
+            target_id: AUTHOR,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert!(is_hidden(&AUTHOR, Some(BOB), &pool).await);
+        assert!(!is_hidden(&BOB, Some(AUTHOR), &pool).await);
+        assert_eq!(
+            HiddenProfile::get_hidden_profile_ids(&BOB, pool.clone())
+                .await
+                .unwrap(),
+            vec![AUTHOR]
+        );
+        mute.delete(pool.clone()).await.unwrap();
+        assert!(!is_hidden(&AUTHOR, Some(BOB), &pool).await);
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles", "statuses")
+        )
+    )]
+    async fn test_blocked_profile_cant_view_content(pool: PgPool) {
+        let note = uuid!("20000000-0000-0000-0000-000000000003");
+        let model = uuid!("30000000-0000-0000-0000-000000000001");
+        let alice = Viewer::from_profile_id(&ALICE);
+        assert!(alice.can_view_note(&note, pool.clone()).await.unwrap());
+        assert!(alice.can_view_model(&model, pool.clone()).await.unwrap());
+        CreateBlock {
+            profile_id: AUTHOR,
+            target_id: ALICE,
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert!(!alice.can_view_note(&note, pool.clone()).await.unwrap());
+        assert!(!alice.can_view_model(&model, pool.clone()).await.unwrap());
+        assert!(FullBlock::is_blocked_by_any(
+            "http://localhost:3000/api/v1/user/alice",
+            &["http://localhost:3000/api/v1/user/testuser".to_string()],
+            pool.clone()
+        )
+        .await
+        .unwrap());
+    }
+}
@@ -139,6 +139,15 @@ impl FullFile {
                 LEFT JOIN model m on f.file_for_model_id = m.id OR f.image_for_model_id = m.id
             WHERE m.id = $1"#, ids).fetch_all(&pool).await
     }
+    pub async fn get_all_by_profile_id(
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<FullFile>, Error> {
+        sqlx::query_as!(FullFile, r#"SELECT id, created_at, updated_at, mime_type, size, file_name, description, alt_text, thumbhash, preview_file_id, to_be_deleted_at, profile_id, file_for_model_id, image_for_model_id FROM file
+        WHERE profile_id = $1"#,
+            profile_id
+        ).fetch_all(&pool).await
+    }
     pub async fn get_by_id(id: &Uuid, pool: PgPool) -> Result<FullFile, Error> {
         sqlx::query_as!(FullFile, r#"SELECT id, created_at, updated_at, mime_type, size, file_name, description, alt_text, thumbhash, preview_file_id, to_be_deleted_at, profile_id, file_for_model_id, image_for_model_id FROM file
         WHERE id = $1;"#,
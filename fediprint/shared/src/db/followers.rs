@@ -0,0 +1,211 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+use super::profile::FullProfile;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateFollower {
+    pub profile_id: Uuid,
+    pub follower_id: Uuid,
+    pub pending: bool,
+    pub activity_id: Option<String>,
+}
+
+impl CreateFollower {
+    pub async fn create(self, pool: PgPool) -> Result<FullFollower, Error> {
+        sqlx::query_as!(
+            FullFollower,
+            r#"INSERT INTO followers (profile_id, follower_id, pending, activity_id) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (profile_id, follower_id) DO UPDATE SET activity_id = $4, pending = EXCLUDED.pending
+            RETURNING id, profile_id, follower_id, created_at, pending, activity_id"#,
+            self.profile_id,
+            self.follower_id,
+            self.pending,
+            self.activity_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullFollower {
+    pub id: Uuid,
+    pub profile_id: Uuid,
+    pub follower_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub pending: bool,
+    pub activity_id: Option<String>,
+}
+
+impl FullFollower {
+    pub async fn get_by_id_and_profile_id(
+        id: &Uuid,
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<FullFollower, Error> {
+        sqlx::query_as!(
+            FullFollower,
+            r#"SELECT id, profile_id, follower_id, created_at, pending, activity_id
+            FROM followers WHERE id = $1 AND profile_id = $2"#,
+            id,
+            profile_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn get_by_profile_id_and_follower_id(
+        profile_id: &Uuid,
+        follower_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<FullFollower, Error> {
+        sqlx::query_as!(
+            FullFollower,
+            r#"SELECT id, profile_id, follower_id, created_at, pending, activity_id
+            FROM followers WHERE profile_id = $1 AND follower_id = $2"#,
+            profile_id,
+            follower_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn get_by_activity_id(
+        activity_id: &str,
+        pool: PgPool,
+    ) -> Result<FullFollower, Error> {
+        sqlx::query_as!(
+            FullFollower,
+            r#"SELECT id, profile_id, follower_id, created_at, pending, activity_id
+            FROM followers WHERE activity_id = $1"#,
+            activity_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn get_follower_profiles(
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<FullProfile>, Error> {
+        sqlx::query_as!(
+            FullProfile,
+            r#"SELECT p.id, p.username, p.server_id, p.display_name, p.summary, p.inbox, p.outbox, p.follower_count, p.following_count, p.message_count, p.public_key, p.registered_at, p.updated_at, p.linked_printables_profile, p.instance, p.manually_approves_followers, p.shared_inbox, p.also_known_as, p.moved_to
+            FROM followers f
+            JOIN profile p ON p.id = f.follower_id
+            WHERE f.profile_id = $1 AND NOT f.pending"#,
+            profile_id
+        )
+        .fetch_all(&pool)
+        .await
+    }
+
+    pub async fn accept(self, pool: PgPool) -> Result<FullFollower, Error> {
+        sqlx::query_as!(
+            FullFollower,
+            r#"UPDATE followers SET pending = false WHERE id = $1
+            RETURNING id, profile_id, follower_id, created_at, pending, activity_id"#,
+            self.id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn delete(self, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(r#"DELETE FROM followers WHERE id = $1"#, self.id)
+            .execute(&pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_between(
+        profile_id: &Uuid,
+        other_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<(), Error> {
+        //! Drops the follows and follow requests of the two profiles in both directions
+        sqlx::query!(
+            r#"DELETE FROM followers WHERE (profile_id = $1 AND follower_id = $2) OR (profile_id = $2 AND follower_id = $1)"#,
+            profile_id,
+            other_id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FollowRequestWithProfile {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub follower_id: Uuid,
+    pub username: String,
+    pub server_id: String,
+    pub display_name: String,
+    pub instance: Uuid,
+}
+
+impl FollowRequestWithProfile {
+    pub async fn get_pending_by_profile_id(
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<FollowRequestWithProfile>, Error> {
+        sqlx::query_as!(
+            FollowRequestWithProfile,
+            r#"SELECT f.id, f.created_at, f.follower_id, p.username, p.server_id, p.display_name, p.instance
+            FROM followers f
+            JOIN profile p ON p.id = f.follower_id
+            WHERE f.profile_id = $1 AND f.pending = true
+            ORDER BY f.created_at"#,
+            profile_id
+        )
+        .fetch_all(&pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::uuid;
+
+    const PROFILE: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c67");
+    const ALICE: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c68");
+
+    async fn follow(pending: bool, activity_id: &str, pool: &PgPool) -> FullFollower {
+        CreateFollower {
+            profile_id: PROFILE,
+            follower_id: ALICE,
+            pending,
+            activity_id: Some(activity_id.to_string()),
+        }
+        .create(pool.clone())
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles")
+        )
+    )]
+    async fn test_follow_again(pool: PgPool) {
+        let first = follow(false, "https://fedi.example/follows/1", &pool).await;
+        // A new request after the profile started approving followers manually
+        let second = follow(true, "https://fedi.example/follows/2", &pool).await;
+        assert_eq!(second.id, first.id);
+        assert!(second.pending);
+        assert_eq!(
+            second.activity_id.as_deref(),
+            Some("https://fedi.example/follows/2")
+        );
+        let third = follow(false, "https://fedi.example/follows/3", &pool).await;
+        assert!(!third.pending);
+    }
+}
@@ -1,8 +1,11 @@
+use crate::helpers::keys::KeyService;
 use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 use sqlx::{Error, PgPool};
 use uuid::{uuid, Uuid};
 
+use super::InstanceModeration;
+
 #[derive(Serialize, Debug, PartialEq, Deserialize)]
 pub struct CreateInstance {
     pub base_url: String,
@@ -17,7 +20,7 @@ impl CreateInstance {
         sqlx::query_as!(FullInstance,
             r#"INSERT INTO instances (base_url, instance_name, user_count, software, software_version)
             VALUES ($1, $2, $3, $4, $5)
-            RETURNING id, created_at, updated_at, base_url, instance_name, user_count, software, software_version
+            RETURNING id, created_at, updated_at, base_url, instance_name, user_count, software, software_version, moderation AS "moderation!: InstanceModeration", moderation_comment
             "#, self.base_url, self.instance_name, self.user_count, self.software, self.software_version
         ).fetch_one(&pool).await
     }
@@ -45,20 +48,204 @@ pub struct FullInstance {
     pub user_count: Option<i32>,
     pub software: String,
     pub software_version: Option<String>,
+    pub moderation: InstanceModeration,
+    pub moderation_comment: Option<String>,
 }
 
 impl FullInstance {
     pub async fn get_by_id(id: &Uuid, pool: PgPool) -> Result<FullInstance, Error> {
         sqlx::query_as!(FullInstance,
-        r#"SELECT id, created_at, updated_at, base_url, instance_name, user_count, software, software_version
+        r#"SELECT id, created_at, updated_at, base_url, instance_name, user_count, software, software_version, moderation AS "moderation!: InstanceModeration", moderation_comment
         FROM instances WHERE id = $1"#, id
     ).fetch_one(&pool).await
     }
 
     pub async fn get_by_base_url(base_url: &str, pool: PgPool) -> Result<FullInstance, Error> {
         sqlx::query_as!(FullInstance,
-        r#"SELECT id, created_at, updated_at, base_url, instance_name, user_count, software, software_version
+        r#"SELECT id, created_at, updated_at, base_url, instance_name, user_count, software, software_version, moderation AS "moderation!: InstanceModeration", moderation_comment
         FROM instances WHERE base_url = $1"#, base_url)
         .fetch_one(&pool).await
     }
+
+    pub async fn get_moderated(pool: PgPool) -> Result<Vec<FullInstance>, Error> {
+        sqlx::query_as!(FullInstance,
+        r#"SELECT id, created_at, updated_at, base_url, instance_name, user_count, software, software_version, moderation AS "moderation!: InstanceModeration", moderation_comment
+        FROM instances WHERE moderation != 'NONE' ORDER BY base_url"#)
+        .fetch_all(&pool).await
+    }
+
+    pub async fn set_moderation(
+        base_url: &str,
+        moderation: InstanceModeration,
+        comment: Option<String>,
+        pool: PgPool,
+    ) -> Result<FullInstance, Error> {
+        //! Instances that were never seen are stored right away, so discovery already knows about the state
+        sqlx::query_as!(FullInstance,
+        r#"INSERT INTO instances (base_url, software, moderation, moderation_comment)
+        VALUES ($1, 'unknown', $2, $3)
+        ON CONFLICT (base_url) DO UPDATE SET moderation = $2, moderation_comment = $3
+        RETURNING id, created_at, updated_at, base_url, instance_name, user_count, software, software_version, moderation AS "moderation!: InstanceModeration", moderation_comment"#,
+        base_url, moderation as _, comment)
+        .fetch_one(&pool).await
+    }
+
+    pub async fn get_hidden_ids(pool: PgPool) -> Result<Vec<Uuid>, Error> {
+        //! Content of silenced and suspended instances is left out of public listings
+        sqlx::query_scalar!(
+            r#"SELECT id FROM instances WHERE moderation IN ('SILENCED', 'SUSPENDED')"#
+        )
+        .fetch_all(&pool)
+        .await
+    }
+
+    pub async fn get_moderation_of_profile(
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<InstanceModeration, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT i.moderation AS "moderation!: InstanceModeration"
+            FROM profile p JOIN instances i ON i.id = p.instance WHERE p.id = $1"#,
+            profile_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn get_moderation_by_host(host: &str, pool: PgPool) -> InstanceModeration {
+        //! Moderating a domain covers its subdomains as well, the most specific moderated domain wins.
+        //! Unknown instances aren't moderated
+        let base_urls: Vec<String> = get_domains(&host.to_lowercase())
+            .iter()
+            .map(|v| format!("https://{v}"))
+            .collect();
+        sqlx::query_scalar!(
+            r#"SELECT moderation AS "moderation!: InstanceModeration" FROM instances
+            WHERE base_url = ANY($1) AND moderation != 'NONE'
+            ORDER BY length(base_url) DESC LIMIT 1"#,
+            &base_urls
+        )
+        .fetch_optional(&pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+    }
+}
+
+fn get_domains(host: &str) -> Vec<String> {
+    //! The host and all its parent domains, without the top level domain
+    let labels: Vec<&str> = host.split('.').collect();
+    (0..labels.len().saturating_sub(1).max(1))
+        .map(|i| labels[i..].join("."))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct InstanceActor {
+    pub base_url: String,
+    pub public_key: String,
+    pub private_key: String,
+}
+
+impl InstanceActor {
+    pub async fn get_local(pool: PgPool) -> Result<InstanceActor, Error> {
+        sqlx::query_as!(
+            InstanceActor,
+            r#"SELECT base_url, public_key AS "public_key!", private_key AS "private_key!"
+        FROM instances WHERE id = $1 AND private_key IS NOT NULL"#,
+            uuid!("00000000-0000-0000-0000-000000000000")
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn create_local_if_missing(
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<InstanceActor> {
+        //! Generates the keypair of the instance actor once, it is kept across restarts
+        if let Ok(d) = InstanceActor::get_local(pool.clone()).await {
+            return Ok(d);
+        }
+        let (public_key, private_key) = key_service.generate_key_pair()?;
+        Ok(sqlx::query_as!(
+            InstanceActor,
+            r#"UPDATE instances SET public_key = $2, private_key = $3 WHERE id = $1
+        RETURNING base_url, public_key AS "public_key!", private_key AS "private_key!""#,
+            uuid!("00000000-0000-0000-0000-000000000000"),
+            public_key,
+            private_key
+        )
+        .fetch_one(&pool)
+        .await?)
+    }
+
+    pub fn actor_id(&self) -> String {
+        format!("{}/api/v1/actor", self.base_url)
+    }
+
+    pub fn key_id(&self) -> String {
+        format!("{}#main-key", self.actor_id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_domains() {
+        assert_eq!(
+            get_domains("a.b.example.com"),
+            vec!["a.b.example.com", "b.example.com", "example.com"]
+        );
+        assert_eq!(get_domains("example.com"), vec!["example.com"]);
+        assert_eq!(get_domains("localhost"), vec!["localhost"]);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_get_moderation_by_host(pool: PgPool) {
+        FullInstance::set_moderation(
+            "https://evil.example",
+            InstanceModeration::Suspended,
+            None,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        FullInstance::set_moderation(
+            "https://media.evil.example",
+            InstanceModeration::RejectMedia,
+            None,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        // Known, but not moderated
+        FullInstance::set_moderation(
+            "https://sub.evil.example",
+            InstanceModeration::None,
+            None,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        for (host, moderation) in [
+            ("evil.example", InstanceModeration::Suspended),
+            ("EVIL.example", InstanceModeration::Suspended),
+            ("sub.evil.example", InstanceModeration::Suspended),
+            ("a.b.evil.example", InstanceModeration::Suspended),
+            ("media.evil.example", InstanceModeration::RejectMedia),
+            ("notevil.example", InstanceModeration::None),
+            ("evil.example.com", InstanceModeration::None),
+            ("example", InstanceModeration::None),
+        ] {
+            assert_eq!(
+                FullInstance::get_moderation_by_host(host, pool.clone()).await,
+                moderation,
+                "{host}"
+            );
+        }
+    }
 }
@@ -0,0 +1,479 @@
+use crate::db::profile::FullProfile;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use tracing::debug;
+use uuid::{uuid, Uuid};
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type, Clone)]
+#[sqlx(type_name = "job_status", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JobStatus {
+    Unprocessed,
+    Processing,
+    Finished,
+    WaitingForRetry,
+    Failed,
+}
+#[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type, Clone)]
+#[sqlx(type_name = "job_type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JobType {
+    SendRegisterEmail,
+    DeliverActivity,
+    ProcessInboxActivity, // run by the app, the handlers need search and storage
+    DeleteAccount,        // run by the app as well
+    SendPasswordResetEmail,
+}
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct FullJob {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub status: JobStatus,
+    pub retry_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub input_data: Option<String>,
+    pub return_data: Option<String>,
+    pub failure_log: Vec<String>,
+    pub tries: i32,
+    pub max_tries: i32,
+    pub processing_times: Vec<f64>,
+    pub updated_at: DateTime<Utc>,
+    pub job_type: JobType,
+}
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct DeliverActivityData {
+    pub inbox: String,
+    pub activity: String,
+    pub sender: Uuid, // profile id, its key signs the request. The local instance id signs as the instance actor
+}
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct JobResponseSuccess {
+    pub resp_data: Option<String>,
+}
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct JobResponseFailure {
+    pub try_in: Option<i32>, // in seconds
+    pub failure_message: String,
+}
+
+impl JobResponseFailure {
+    pub fn try_in_30(msg: &str) -> Self {
+        JobResponseFailure {
+            try_in: Some(30),
+            failure_message: msg.to_string(),
+        }
+    }
+    pub fn never_try(msg: &str) -> Self {
+        JobResponseFailure {
+            try_in: None,
+            failure_message: msg.to_string(),
+        }
+    }
+}
+
+pub fn get_backoff(tries: i32) -> i32 {
+    // 1min, 2min, 4min, ... capped at 6 hours
+    (60 << (tries.clamp(1, 10) - 1)).min(6 * 60 * 60)
+}
+
+struct CreateRawJob<'a> {
+    input_data: &'a str,
+    max_tries: i32, // Should be 3
+    job_type: JobType,
+}
+impl CreateRawJob<'_> {
+    async fn create(self, pool: PgPool) -> Result<i32, Error> {
+        sqlx::query_scalar!(r#"INSERT INTO jobs (input_data, max_tries, job_type) VALUES ($1, $2, $3) RETURNING id"#,
+        self.input_data, self.max_tries, self.job_type as _
+    ).fetch_one(&pool).await
+    }
+}
+
+pub async fn send_register_confirm_email(to_user: &Uuid, pool: PgPool) -> Result<i32, Error> {
+    let job = CreateRawJob {
+        job_type: JobType::SendRegisterEmail,
+        input_data: &to_user.to_string(),
+        max_tries: 3,
+    };
+    let job_id = job.create(pool.clone()).await?;
+
+    Ok(job_id)
+}
+
+pub async fn send_password_reset_email(to_user: &Uuid, pool: PgPool) -> Result<i32, Error> {
+    let job = CreateRawJob {
+        job_type: JobType::SendPasswordResetEmail,
+        input_data: &to_user.to_string(),
+        max_tries: 3,
+    };
+    job.create(pool).await
+}
+
+pub fn get_inboxes(recipients: &[FullProfile]) -> Vec<String> {
+    // Profiles on the same server usually share an inbox, which only needs the activity once
+    let mut inboxes: Vec<String> = vec![];
+    for recipient in recipients {
+        if recipient.instance == uuid!("00000000-0000-0000-0000-000000000000") {
+            continue;
+        }
+        let inbox = recipient
+            .shared_inbox
+            .clone()
+            .unwrap_or_else(|| recipient.inbox.clone());
+        if !inboxes.contains(&inbox) {
+            inboxes.push(inbox);
+        }
+    }
+    inboxes
+}
+
+pub async fn deliver_activity(
+    activity: &str,
+    sender: &Uuid,
+    recipients: &[FullProfile],
+    pool: PgPool,
+) -> Result<Vec<i32>, Error> {
+    let mut job_ids: Vec<i32> = vec![];
+    for inbox in get_inboxes(recipients) {
+        let input_data = serde_json::to_string(&DeliverActivityData {
+            inbox,
+            activity: activity.to_string(),
+            sender: *sender,
+        })
+        .unwrap();
+        let job = CreateRawJob {
+            job_type: JobType::DeliverActivity,
+            input_data: &input_data,
+            max_tries: 10,
+        };
+        job_ids.push(job.create(pool.clone()).await?);
+    }
+    Ok(job_ids)
+}
+
+pub async fn is_actor_delete_queued(
+    sender: &Uuid,
+    server_id: &str,
+    pool: PgPool,
+) -> Result<bool, Error> {
+    //! Whether the `Delete` of the actor itself was already queued for delivery
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM jobs
+            WHERE CASE WHEN job_type = 'DELIVER_ACTIVITY' THEN
+                input_data::jsonb->>'sender' = $1::text
+                AND (input_data::jsonb->>'activity')::jsonb @> jsonb_build_object('type', 'Delete', 'object', $2::text)
+            ELSE false END) AS "queued!""#,
+        sender.to_string(),
+        server_id
+    )
+    .fetch_one(&pool)
+    .await
+}
+
+pub async fn count_unfinished_deliveries(sender: &Uuid, pool: PgPool) -> Result<i64, Error> {
+    //! Deliveries signed by the profile that are still queued or waiting for a retry
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM jobs
+            WHERE status NOT IN ('FINISHED', 'FAILED')
+            AND CASE WHEN job_type = 'DELIVER_ACTIVITY' THEN input_data::jsonb->>'sender' = $1::text ELSE false END"#,
+        sender.to_string()
+    )
+    .fetch_one(&pool)
+    .await
+}
+
+pub async fn process_inbox_activity(activity: &str, pool: PgPool) -> Result<i32, Error> {
+    let job = CreateRawJob {
+        job_type: JobType::ProcessInboxActivity,
+        input_data: activity,
+        max_tries: 3,
+    };
+    job.create(pool).await
+}
+
+pub async fn queue_account_deletion(profile_id: &Uuid, pool: PgPool) -> Result<i32, Error> {
+    let job = CreateRawJob {
+        job_type: JobType::DeleteAccount,
+        input_data: &profile_id.to_string(),
+        max_tries: 20, // Waits for the deliveries of the actor Delete, which retry for about a day
+    };
+    job.create(pool).await
+}
+
+pub async fn claim_job(
+    task_id: i32,
+    app_jobs: bool,
+    pool: PgPool,
+) -> Result<Option<FullJob>, Error> {
+    //! Claiming the job in a single update makes sure only one worker processes it.
+    //! Jobs stuck in processing for 15 minutes belong to a crashed worker and are picked up again.
+    //! Inbox and account deletion jobs are only claimed by the app, all others by the worker.
+    sqlx::query_as!(
+        FullJob,
+        r#"UPDATE jobs SET status = 'PROCESSING', started_at = NOW(), updated_at = NOW(), tries = tries + 1
+    WHERE
+        id = $1
+        AND (job_type IN ('PROCESS_INBOX_ACTIVITY', 'DELETE_ACCOUNT')) = $2
+        AND (status = 'UNPROCESSED'
+            OR (status = 'WAITING_FOR_RETRY' AND retry_at <= NOW())
+            OR (status = 'PROCESSING' AND updated_at < NOW() - INTERVAL '15 minutes'))
+    RETURNING
+        id,
+        created_at,
+        started_at,
+        status AS "status!: JobStatus",
+        retry_at,
+        finished_at,
+        input_data,
+        return_data,
+        failure_log,
+        tries,
+        max_tries,
+        processing_times,
+        updated_at,
+        job_type AS "job_type!: JobType""#,
+        task_id,
+        app_jobs
+    )
+    .fetch_optional(&pool)
+    .await
+}
+
+pub async fn get_due_job_ids(app_jobs: bool, pool: PgPool) -> Result<Vec<i32>, Error> {
+    sqlx::query_scalar!(
+        r#"SELECT id
+    FROM
+        jobs
+    WHERE
+        (job_type IN ('PROCESS_INBOX_ACTIVITY', 'DELETE_ACCOUNT')) = $1
+        AND (status = 'UNPROCESSED'
+            OR (status = 'WAITING_FOR_RETRY' AND retry_at <= NOW())
+            OR (status = 'PROCESSING' AND updated_at < NOW() - INTERVAL '15 minutes'))
+    ORDER BY id
+    LIMIT 10"#, // Limit the number of jobs fetched to avoid overloading
+        app_jobs
+    )
+    .fetch_all(&pool)
+    .await
+}
+
+pub async fn save_failed_job(
+    data: JobResponseFailure,
+    t: u128,
+    job: &FullJob,
+    pool: PgPool,
+) -> Result<(), Error> {
+    let mut retry_at: Option<DateTime<Utc>> = None;
+    let mut status: JobStatus = JobStatus::Failed;
+    if let Some(try_in) = data.try_in {
+        if job.tries < job.max_tries {
+            retry_at = Some(Utc::now() + TimeDelta::seconds(try_in.into()));
+            status = JobStatus::WaitingForRetry;
+        } else {
+            debug!("Job {} failed {} times, giving up", job.id, job.tries);
+        }
+    }
+    sqlx::query!(
+        r#"UPDATE jobs SET retry_at = $1, failure_log = array_append(failure_log, $2), processing_times = array_append(processing_times, $3), status = $4, updated_at = NOW() WHERE id = $5"#,
+        retry_at, data.failure_message, t as i64, status as _, job.id as i32
+    ).execute(&pool).await?;
+    Ok(())
+}
+
+pub async fn save_success_job(
+    data: String,
+    t: u128,
+    job_id: i32,
+    pool: PgPool,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"UPDATE jobs SET return_data = $1, processing_times = array_append(processing_times, $2), status = $3, finished_at = NOW(), updated_at = NOW() WHERE id = $4"#,
+        data, t as i64, JobStatus::Finished as _, job_id
+    ).execute(&pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_backoff() {
+        let delays: Vec<i32> = (1..=5).map(get_backoff).collect();
+        assert_eq!(delays, vec![60, 120, 240, 480, 960]);
+        assert_eq!(get_backoff(0), 60);
+        assert_eq!(get_backoff(10), 6 * 60 * 60);
+        assert_eq!(get_backoff(1000), 6 * 60 * 60);
+        assert_eq!(JobResponseFailure::never_try("").try_in, None);
+    }
+
+    async fn get_status(job_id: i32, pool: &PgPool) -> (String, Option<DateTime<Utc>>) {
+        sqlx::query_as("SELECT status::text, retry_at FROM jobs WHERE id = $1")
+            .bind(job_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn create_job(max_tries: i32, pool: &PgPool) -> i32 {
+        CreateRawJob {
+            job_type: JobType::DeliverActivity,
+            input_data: "{}",
+            max_tries,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_failed_job_is_retried(pool: PgPool) {
+        let job_id = create_job(2, &pool).await;
+        assert_eq!(
+            get_due_job_ids(false, pool.clone()).await.unwrap(),
+            vec![job_id]
+        );
+        // App jobs are claimed by the app only
+        assert!(claim_job(job_id, true, pool.clone())
+            .await
+            .unwrap()
+            .is_none());
+        let job = claim_job(job_id, false, pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.tries, 1);
+        assert!(claim_job(job_id, false, pool.clone())
+            .await
+            .unwrap()
+            .is_none());
+        save_failed_job(
+            JobResponseFailure {
+                try_in: Some(get_backoff(job.tries)),
+                failure_message: "failed".to_string(),
+            },
+            1,
+            &job,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        let (status, retry_at) = get_status(job_id, &pool).await;
+        assert_eq!(status, "WAITING_FOR_RETRY");
+        assert!(retry_at.unwrap() > Utc::now() + TimeDelta::seconds(50));
+        assert!(get_due_job_ids(false, pool.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(claim_job(job_id, false, pool.clone())
+            .await
+            .unwrap()
+            .is_none());
+        // Due again once the backoff is over
+        sqlx::query("UPDATE jobs SET retry_at = NOW() WHERE id = $1")
+            .bind(job_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let job = claim_job(job_id, false, pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.tries, 2);
+        // The last try gives up
+        save_failed_job(
+            JobResponseFailure {
+                try_in: Some(get_backoff(job.tries)),
+                failure_message: "failed".to_string(),
+            },
+            1,
+            &job,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_status(job_id, &pool).await,
+            ("FAILED".to_string(), None)
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_failed_job_without_retry(pool: PgPool) {
+        let job_id = create_job(10, &pool).await;
+        let job = claim_job(job_id, false, pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        save_failed_job(
+            JobResponseFailure::never_try("invalid"),
+            1,
+            &job,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_status(job_id, &pool).await,
+            ("FAILED".to_string(), None)
+        );
+        assert!(get_due_job_ids(false, pool).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_unfinished_deliveries(pool: PgPool) {
+        let sender = Uuid::now_v7();
+        let server_id = "http://localhost:3000/api/v1/user/testuser";
+        // Jobs with other input data are skipped
+        send_register_confirm_email(&sender, pool.clone())
+            .await
+            .unwrap();
+        assert!(!is_actor_delete_queued(&sender, server_id, pool.clone())
+            .await
+            .unwrap());
+        assert_eq!(
+            count_unfinished_deliveries(&sender, pool.clone())
+                .await
+                .unwrap(),
+            0
+        );
+        let activity = serde_json::json!({
+            "id": format!("{server_id}#delete/{}", Uuid::now_v7()),
+            "type": "Delete",
+            "actor": server_id,
+            "object": server_id,
+        });
+        let input_data = serde_json::to_string(&DeliverActivityData {
+            inbox: "https://mastodon.online/inbox".to_string(),
+            activity: activity.to_string(),
+            sender,
+        })
+        .unwrap();
+        let job_id = CreateRawJob {
+            job_type: JobType::DeliverActivity,
+            input_data: &input_data,
+            max_tries: 10,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert!(is_actor_delete_queued(&sender, server_id, pool.clone())
+            .await
+            .unwrap());
+        assert!(
+            !is_actor_delete_queued(&Uuid::now_v7(), server_id, pool.clone())
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            count_unfinished_deliveries(&sender, pool.clone())
+                .await
+                .unwrap(),
+            1
+        );
+        save_success_job("200 OK".to_string(), 1, job_id, pool.clone())
+            .await
+            .unwrap();
+        assert_eq!(count_unfinished_deliveries(&sender, pool).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateLike {
+    pub profile_id: Uuid,
+    pub note_id: Option<Uuid>,
+    pub model_id: Option<Uuid>,
+    pub activity_id: Option<String>,
+}
+
+impl CreateLike {
+    pub async fn create(self, pool: PgPool) -> Result<FullLike, Error> {
+        //! Returns the existing like if the profile already liked the note or model
+        let like = sqlx::query_as!(
+            FullLike,
+            r#"INSERT INTO likes (profile_id, note_id, model_id, activity_id) VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            RETURNING id, created_at, profile_id, note_id, model_id, activity_id"#,
+            self.profile_id,
+            self.note_id,
+            self.model_id,
+            self.activity_id
+        )
+        .fetch_optional(&pool)
+        .await?;
+        match like {
+            Some(d) => Ok(d),
+            None => {
+                FullLike::get_by_profile_id_and_object(
+                    &self.profile_id,
+                    self.note_id,
+                    self.model_id,
+                    pool,
+                )
+                .await
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullLike {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub profile_id: Uuid,
+    pub note_id: Option<Uuid>,
+    pub model_id: Option<Uuid>,
+    pub activity_id: Option<String>,
+}
+
+impl FullLike {
+    pub async fn get_by_profile_id_and_object(
+        profile_id: &Uuid,
+        note_id: Option<Uuid>,
+        model_id: Option<Uuid>,
+        pool: PgPool,
+    ) -> Result<FullLike, Error> {
+        sqlx::query_as!(
+            FullLike,
+            r#"SELECT id, created_at, profile_id, note_id, model_id, activity_id
+            FROM likes WHERE profile_id = $1 AND note_id IS NOT DISTINCT FROM $2 AND model_id IS NOT DISTINCT FROM $3"#,
+            profile_id,
+            note_id,
+            model_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn get_by_activity_id(activity_id: &str, pool: PgPool) -> Result<FullLike, Error> {
+        sqlx::query_as!(
+            FullLike,
+            r#"SELECT id, created_at, profile_id, note_id, model_id, activity_id
+            FROM likes WHERE activity_id = $1"#,
+            activity_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn count_by_object(
+        note_id: Option<Uuid>,
+        model_id: Option<Uuid>,
+        pool: PgPool,
+    ) -> Result<i64, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM likes WHERE note_id IS NOT DISTINCT FROM $1 AND model_id IS NOT DISTINCT FROM $2"#,
+            note_id,
+            model_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn delete(self, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(r#"DELETE FROM likes WHERE id = $1"#, self.id)
+            .execute(&pool)
+            .await?;
+        Ok(())
+    }
+}
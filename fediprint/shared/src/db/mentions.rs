@@ -0,0 +1,50 @@
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+use super::profile::FullProfile;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateMention {
+    pub profile_id: Uuid,
+    pub note_id: Uuid,
+}
+
+impl CreateMention {
+    pub async fn create(self, pool: PgPool) -> Result<FullMention, Error> {
+        sqlx::query_as!(
+            FullMention,
+            r#"INSERT INTO _mentions (profile_id, note_id) VALUES ($1, $2)
+            ON CONFLICT (profile_id, note_id) DO UPDATE SET profile_id = $1
+            RETURNING profile_id AS "profile_id!", note_id AS "note_id!""#,
+            self.profile_id,
+            self.note_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullMention {
+    pub profile_id: Uuid,
+    pub note_id: Uuid,
+}
+
+impl FullMention {
+    pub async fn get_mentioned_profiles(
+        note_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<FullProfile>, Error> {
+        sqlx::query_as!(
+            FullProfile,
+            r#"SELECT p.id, p.username, p.server_id, p.display_name, p.summary, p.inbox, p.outbox, p.follower_count, p.following_count, p.message_count, p.public_key, p.registered_at, p.updated_at, p.linked_printables_profile, p.instance, p.manually_approves_followers, p.shared_inbox, p.also_known_as, p.moved_to
+            FROM _mentions m
+            JOIN profile p ON p.id = m.profile_id
+            WHERE m.note_id = $1"#,
+            note_id
+        )
+        .fetch_all(&pool)
+        .await
+    }
+}
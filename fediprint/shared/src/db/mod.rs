@@ -2,13 +2,28 @@ use serde_derive::{Deserialize, Serialize};
 use strum::EnumString;
 
 pub mod account;
+pub mod audit_log;
+pub mod blocks;
+pub mod boosts;
 pub mod file;
+pub mod followers;
 pub mod instances;
+pub mod jobs;
+pub mod likes;
+pub mod mentions;
 pub mod model;
 pub mod note;
+pub mod password_resets;
 pub mod printer;
+pub mod processed_activities;
 pub mod profile;
+pub mod reports;
+pub mod sessions;
+pub mod stats;
+pub mod tombstones;
 pub mod transactions;
+pub mod two_factor;
+pub mod visibility;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type, Clone)]
 #[sqlx(type_name = "modified_scale", rename_all = "SCREAMING_SNAKE_CASE")]
@@ -20,12 +35,13 @@ pub enum ModifiedScale {
     NewPrinter,
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type, Clone, Default)]
 #[sqlx(type_name = "event_audience", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum EventAudience {
     Public,
     Followers,
     Mentioned,
+    #[default]
     Nobody,
 }
 
@@ -46,3 +62,48 @@ pub enum ModelLicense {
     Bsd,
     Sdfl,
 }
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type, Clone, Copy, Default)]
+#[sqlx(type_name = "instance_moderation", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InstanceModeration {
+    #[default]
+    None,
+    Silenced,
+    Suspended,
+    RejectMedia,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type, Clone, Copy, Default)]
+#[sqlx(type_name = "report_status", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReportStatus {
+    #[default]
+    Open,
+    Resolved,
+    Dismissed,
+}
+
+// Ordered by privileges, so roles can be compared
+#[derive(
+    Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, sqlx::Type, Clone, Copy, Default,
+)]
+#[sqlx(type_name = "account_role", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AccountRole {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, sqlx::Type, Clone, Copy)]
+#[sqlx(type_name = "audit_action", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuditAction {
+    SetRole,
+    SuspendAccount,
+    UnsuspendAccount,
+    DeleteAccount,
+    UnpublishModel,
+    PurgeProfile,
+    SetInstanceModeration,
+    ImportInstanceModeration,
+    SetReportStatus,
+}
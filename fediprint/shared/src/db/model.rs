@@ -1,7 +1,10 @@
 use crate::{
-    db::ModelLicense, helpers::media::handle_media, models::activitypub::note::NoteResponse,
+    db::{visibility::Viewer, ModelLicense},
+    helpers::media::{delete_media_of_model, get_attachment_urls, handle_media},
+    models::{activitypub::note::NoteResponse, model::UpdateModel},
     AppState,
 };
+use anyhow::bail;
 use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 use sqlx::{Error, FromRow, PgPool};
@@ -93,15 +96,43 @@ impl FullModel {
             published, id, profile_id
         ).fetch_one(&pool).await
     }
+    pub async fn update_by_id_and_profile_id(
+        data: UpdateModel,
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<FullModel, Error> {
+        sqlx::query_as!(FullModel, r#"UPDATE model SET title = $1, summary = $2, description = $3, tags = $4, license = $5, updated_at = NOW()
+            WHERE id = $6 AND profile_id = $7
+            RETURNING id, server, server_id, profile_id, published, title, summary, description, tags, license AS "license!: ModelLicense", created_at, updated_at, printables_url, cost, currency"#,
+            data.title, data.summary, data.description, &data.tags, data.license as _, data.id, profile_id
+        ).fetch_one(&pool).await
+    }
+    pub async fn unpublish_by_id(id: &Uuid, pool: PgPool) -> Result<FullModel, Error> {
+        sqlx::query_as!(FullModel, r#"UPDATE model SET published = false WHERE id = $1
+            RETURNING id, server, server_id, profile_id, published, title, summary, description, tags, license AS "license!: ModelLicense", created_at, updated_at, printables_url, cost, currency"#,
+            id
+        ).fetch_one(&pool).await
+    }
+    pub async fn delete_by_id_and_profile_id(
+        id: &Uuid,
+        profile_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<FullModel, Error> {
+        sqlx::query_as!(FullModel, r#"DELETE FROM model WHERE id = $1 AND profile_id = $2
+            RETURNING id, server, server_id, profile_id, published, title, summary, description, tags, license AS "license!: ModelLicense", created_at, updated_at, printables_url, cost, currency"#,
+            id, profile_id
+        ).fetch_one(&pool).await
+    }
     pub async fn get_newest_published_models_paginated(
         limit: &i64,
         offset: &i64,
+        viewer: &Viewer,
         pool: PgPool,
     ) -> Result<Vec<FullModel>, Error> {
         sqlx::query_as!(FullModel, r#"SELECT id, server, server_id, profile_id, published, title, summary, description, tags, license AS "license!: ModelLicense", created_at, updated_at, printables_url, cost, currency FROM model
-            WHERE published = true ORDER BY created_at DESC OFFSET $1 LIMIT $2
+            WHERE published = true AND can_view_model(id, $3) ORDER BY created_at DESC OFFSET $1 LIMIT $2
             "#,
-            offset, limit
+            offset, limit, viewer.profile_id
         ).fetch_all(&pool).await
     }
     pub async fn create(self, pool: PgPool) -> Result<FullModel, Error> {
@@ -213,8 +244,10 @@ impl FullModelWithRelationsIds {
         limit: &i64,
         offset: &i64,
         include_files_if_paid: bool,
+        viewer: &Viewer,
         pool: PgPool,
     ) -> Result<Vec<FullModelWithRelationsIds>, Error> {
+        //! Models of profiles the viewer blocked or muted, or that blocked the viewer, are left out
         let mut res = sqlx::query_as!(FullModelWithRelationsIds, r#"SELECT m.id,m.server,m.server_id,m.profile_id,m.published,m.title,m.summary,m.description,m.tags,m.license AS "license!: ModelLicense",m.created_at,m.updated_at,m.cost,m.currency,
         CASE WHEN (m.cost = 0 OR m.cost IS NULL OR $3) THEN array_agg(f.id) ELSE '{}'::uuid[] END AS files,
         array_agg(i.id) AS images
@@ -225,12 +258,12 @@ impl FullModelWithRelationsIds {
         LEFT JOIN
             file AS i ON m.id = i.image_for_model_id
         WHERE
-            m.published = true
+            m.published = true AND can_view_model(m.id, $4) AND NOT is_profile_hidden(m.profile_id, $4)
         GROUP BY
             m.id
         ORDER BY created_at DESC OFFSET $1 LIMIT $2;
             "#,
-            offset, limit, include_files_if_paid
+            offset, limit, include_files_if_paid, viewer.profile_id
         ).fetch_all(&pool).await?;
         remove_duplicates_from_list_of_models(&mut res);
         Ok(res)
@@ -385,9 +418,11 @@ impl FullModelWithRelationsIds {
             "db res: {:?}",
             FullModelWithRelationsIds::get_by_server_id(&d.id, false, state.pool.clone()).await
         );
-        // if let Ok(d) = FullModelWithRelationsIds::get_by_server_id(&d.id, pool.clone()).await {
-        //     return Ok(d);
-        // }
+        if let Ok(d) =
+            FullModelWithRelationsIds::get_by_server_id(&d.id, false, state.pool.clone()).await
+        {
+            return Ok(d);
+        }
         let unfinished_model = FullModelWithRelationsIds::create_from_note_response(
             d.clone(),
             server,
@@ -396,7 +431,7 @@ impl FullModelWithRelationsIds {
         )
         .await?;
         handle_media(
-            d.attachment.iter().filter_map(|v| v.as_str()).collect(),
+            get_attachment_urls(&d.attachment),
             unfinished_model.id,
             profile_id,
             state.clone(),
@@ -407,4 +442,42 @@ impl FullModelWithRelationsIds {
                 .await?,
         )
     }
+
+    pub async fn update_from_note_response(
+        id: &Uuid,
+        d: NoteResponse,
+        state: Arc<AppState>,
+    ) -> anyhow::Result<FullModelWithRelationsIds> {
+        let date: DateTime<Utc> = match d.updated.as_deref().map(DateTime::parse_from_rfc3339) {
+            Some(Ok(d)) => d.into(),
+            _ => Utc::now(),
+        };
+        let license = match d.license.as_deref().map(ModelLicense::from_str) {
+            Some(Ok(d)) => d,
+            _ => bail!("Invalid license"),
+        };
+        sqlx::query!(
+            r#"UPDATE model SET title = $1, summary = $2, description = $3, tags = $4, license = $5, updated_at = $6 WHERE id = $7"#,
+            d.name.clone().unwrap_or_default(),
+            d.summary.clone().unwrap_or_default(),
+            d.content,
+            &d.tag.iter().map(|v| v.name.clone()).collect::<Vec<String>>(),
+            license as _,
+            date,
+            id
+        )
+        .execute(&state.pool)
+        .await?;
+        let model = FullModelWithRelationsIds::get_by_id(id, false, state.pool.clone()).await?;
+        // Attachments can't be matched to the existing files, so they are fetched again
+        delete_media_of_model(id, state.clone()).await?;
+        handle_media(
+            get_attachment_urls(&d.attachment),
+            model.id,
+            model.profile_id,
+            state.clone(),
+        )
+        .await?;
+        Ok(FullModelWithRelationsIds::get_by_id(id, false, state.pool.clone()).await?)
+    }
 }
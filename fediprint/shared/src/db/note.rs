@@ -1,4 +1,8 @@
-use crate::{db::EventAudience, models::activitypub::note::NoteResponse};
+use crate::{
+    db::{mentions::CreateMention, profile::FullProfile, visibility::Viewer, EventAudience},
+    helpers::activities::{get_local_copy, ModelOrNote},
+    models::activitypub::note::NoteResponse,
+};
 use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 use sqlx::{Error, FromRow, PgPool};
@@ -13,6 +17,7 @@ pub struct CreateNote {
     pub audience: EventAudience,
     pub in_reply_to_comment_id: Option<Uuid>,
     pub in_reply_to_note_id: Option<Uuid>,
+    pub in_reply_to_model_id: Option<Uuid>,
     pub actor_id: Uuid,
     pub comment_of_model_id: Option<Uuid>,
 }
@@ -20,11 +25,11 @@ pub struct CreateNote {
 impl CreateNote {
     pub async fn create(self, pool: PgPool) -> Result<FullNote, Error> {
         sqlx::query_as!(FullNote,
-            r#"INSERT INTO note (server_id, content, hashtags, audience, in_reply_to_comment_id, in_reply_to_note_id, actor_id,
+            r#"INSERT INTO note (server_id, content, hashtags, audience, in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id,
                 comment_of_model_id)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-                RETURNING id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, actor_id, comment_of_model_id"#,
-            self.server_id, self.content, &self.hashtags, self.audience as _, self.in_reply_to_comment_id, self.in_reply_to_note_id, self.actor_id, self.comment_of_model_id
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                RETURNING id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id, comment_of_model_id"#,
+            self.server_id, self.content, &self.hashtags, self.audience as _, self.in_reply_to_comment_id, self.in_reply_to_note_id, self.in_reply_to_model_id, self.actor_id, self.comment_of_model_id
         ).fetch_one(&pool).await
     }
 }
@@ -40,22 +45,79 @@ pub struct FullNote {
     pub audience: EventAudience,
     pub in_reply_to_comment_id: Option<Uuid>,
     pub in_reply_to_note_id: Option<Uuid>,
+    pub in_reply_to_model_id: Option<Uuid>,
     pub actor_id: Uuid,
     pub comment_of_model_id: Option<Uuid>,
 }
 
 impl FullNote {
     pub async fn create(self, pool: PgPool) -> Result<FullNote, Error> {
-        sqlx::query_as!(FullNote, r#"INSERT INTO note (id, created_at, updated_at, server_id, content, hashtags, audience, in_reply_to_comment_id, in_reply_to_note_id, actor_id,
+        sqlx::query_as!(FullNote, r#"INSERT INTO note (id, created_at, updated_at, server_id, content, hashtags, audience, in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id,
                 comment_of_model_id)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
-                RETURNING id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, actor_id, comment_of_model_id"#,
-                self.id, self.created_at, self.updated_at, self.server_id, self.content, &self.hashtags, self.audience as _, self.in_reply_to_comment_id, self.in_reply_to_note_id, self.actor_id, self.comment_of_model_id
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                RETURNING id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id, comment_of_model_id"#,
+                self.id, self.created_at, self.updated_at, self.server_id, self.content, &self.hashtags, self.audience as _, self.in_reply_to_comment_id, self.in_reply_to_note_id, self.in_reply_to_model_id, self.actor_id, self.comment_of_model_id
         ).fetch_one(&pool).await
     }
 
+    pub async fn get_by_id(id: &Uuid, pool: PgPool) -> Result<FullNote, Error> {
+        sqlx::query_as!(FullNote, r#"SELECT id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id, comment_of_model_id
+                FROM note WHERE id = $1"#,
+                id
+        ).fetch_one(&pool).await
+    }
+
+    pub async fn get_replies_paginated(
+        parent_id: &Uuid,
+        viewer: &Viewer,
+        min_id: Option<Uuid>,
+        limit: i64,
+        pool: PgPool,
+    ) -> Result<Vec<FullNote>, Error> {
+        //! Direct replies to a note or model that are visible to the viewer, oldest first.
+        //! Replies of blocked and muted profiles are left out.
+        sqlx::query_as!(FullNote, r#"SELECT id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id, comment_of_model_id
+                FROM note
+                WHERE (in_reply_to_note_id = $1 OR in_reply_to_comment_id = $1 OR in_reply_to_model_id = $1)
+                  AND can_view_note(id, $2)
+                  AND NOT is_profile_hidden(actor_id, $2)
+                  AND ($3::uuid IS NULL OR id > $3)
+                ORDER BY id LIMIT $4"#,
+                parent_id, viewer.profile_id, min_id, limit
+        ).fetch_all(&pool).await
+    }
+
+    pub async fn count_replies(
+        parent_id: &Uuid,
+        viewer: &Viewer,
+        pool: PgPool,
+    ) -> Result<i64, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM note
+            WHERE (in_reply_to_note_id = $1 OR in_reply_to_comment_id = $1 OR in_reply_to_model_id = $1)
+              AND can_view_note(id, $2) AND NOT is_profile_hidden(actor_id, $2)"#,
+            parent_id,
+            viewer.profile_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn get_comments_of_model(
+        model_id: &Uuid,
+        viewer: &Viewer,
+        pool: PgPool,
+    ) -> Result<Vec<FullNote>, Error> {
+        //! Every note in the conversation under a model that is visible to the viewer, oldest first
+        sqlx::query_as!(FullNote, r#"SELECT id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id, comment_of_model_id
+                FROM note WHERE comment_of_model_id = $1 AND can_view_note(id, $2) AND NOT is_profile_hidden(actor_id, $2)
+                ORDER BY id"#,
+                model_id, viewer.profile_id
+        ).fetch_all(&pool).await
+    }
+
     pub async fn get_by_server_id(server_id: &str, pool: PgPool) -> Result<FullNote, Error> {
-        sqlx::query_as!(FullNote, r#"SELECT id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, actor_id, comment_of_model_id
+        sqlx::query_as!(FullNote, r#"SELECT id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id, comment_of_model_id
                 FROM note WHERE server_id = $1 LIMIT 1"#,
                 Some(server_id)
         ).fetch_one(&pool).await
@@ -67,21 +129,81 @@ impl FullNote {
         pool: PgPool,
     ) -> Result<FullNote, Error> {
         let date: DateTime<Utc> = DateTime::parse_from_rfc3339(&d.published).unwrap().into();
+        // Only replies to already known notes and models are linked, the parent isn't fetched
+        let reply_to = match d.in_reply_to.as_ref().and_then(|v| v.as_str()) {
+            Some(d) => ReplyTarget::get_by_server_id(d, pool.clone()).await,
+            None => ReplyTarget::default(),
+        };
+        let is_addressed_to =
+            |suffix: &str| d.to.iter().chain(d.cc.iter()).any(|v| v.ends_with(suffix));
+        let audience = if is_addressed_to("#Public") {
+            EventAudience::Public
+        } else if is_addressed_to("/followers") {
+            EventAudience::Followers
+        } else {
+            EventAudience::Mentioned
+        };
+        let mentions: Vec<String> = d
+            .tag
+            .iter()
+            .filter(|v| v.type_field == "Mention")
+            .map(|v| v.href.clone())
+            .collect();
         let note = FullNote {
             id: Uuid::now_v7(),
             created_at: date,
             updated_at: date,
             server_id: Some(d.id),
             content: d.content,
-            hashtags: d.tag.into_iter().map(|v| v.name).collect(),
-            audience: EventAudience::Public,
-            in_reply_to_comment_id: None,
-            in_reply_to_note_id: None,
+            hashtags: d
+                .tag
+                .into_iter()
+                .filter(|v| v.type_field == "Hashtag")
+                .map(|v| v.name.trim_start_matches('#').to_string())
+                .collect(),
+            audience,
+            in_reply_to_comment_id: reply_to.in_reply_to_comment_id,
+            in_reply_to_note_id: reply_to.in_reply_to_note_id,
+            in_reply_to_model_id: reply_to.in_reply_to_model_id,
             actor_id: profile_id,
-            comment_of_model_id: None,
+            comment_of_model_id: reply_to.comment_of_model_id,
         };
         trace!("note: {:?}", &note);
-        note.create(pool).await
+        let note = note.create(pool.clone()).await?;
+        // Only already known profiles are linked, mostly the local ones
+        for server_id in mentions {
+            if let Ok(profile) = FullProfile::get_by_server_id(&server_id, pool.clone()).await {
+                CreateMention {
+                    profile_id: profile.id,
+                    note_id: note.id,
+                }
+                .create(pool.clone())
+                .await?;
+            }
+        }
+        Ok(note)
+    }
+
+    pub async fn update_from_note_response(
+        id: &Uuid,
+        d: NoteResponse,
+        pool: PgPool,
+    ) -> Result<FullNote, Error> {
+        let date: DateTime<Utc> = match d.updated.as_deref().map(DateTime::parse_from_rfc3339) {
+            Some(Ok(d)) => d.into(),
+            _ => Utc::now(),
+        };
+        sqlx::query_as!(FullNote, r#"UPDATE note SET content = $1, hashtags = $2, updated_at = $3 WHERE id = $4
+                RETURNING id, created_at, updated_at, server_id, content, hashtags, audience AS "audience!: EventAudience", in_reply_to_comment_id, in_reply_to_note_id, in_reply_to_model_id, actor_id, comment_of_model_id"#,
+                d.content, &d.tag.into_iter().filter(|v| v.type_field == "Hashtag").map(|v| v.name.trim_start_matches('#').to_string()).collect::<Vec<String>>(), date, id
+        ).fetch_one(&pool).await
+    }
+
+    pub async fn delete(self, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(r#"DELETE FROM note WHERE id = $1"#, self.id)
+            .execute(&pool)
+            .await?;
+        Ok(())
     }
 
     pub async fn create_or_get_from_note_response(
@@ -96,6 +218,48 @@ impl FullNote {
     }
 }
 
+#[derive(Debug, Default, PartialEq)]
+pub struct ReplyTarget {
+    pub in_reply_to_comment_id: Option<Uuid>,
+    pub in_reply_to_note_id: Option<Uuid>,
+    pub in_reply_to_model_id: Option<Uuid>,
+    pub comment_of_model_id: Option<Uuid>,
+}
+
+impl ReplyTarget {
+    pub fn from_note(note: &FullNote) -> ReplyTarget {
+        //! Replies to comments stay in the conversation of their model
+        match note.comment_of_model_id {
+            Some(model_id) => ReplyTarget {
+                in_reply_to_comment_id: Some(note.id),
+                comment_of_model_id: Some(model_id),
+                ..Default::default()
+            },
+            None => ReplyTarget {
+                in_reply_to_note_id: Some(note.id),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn from_model(model_id: &Uuid) -> ReplyTarget {
+        ReplyTarget {
+            in_reply_to_model_id: Some(*model_id),
+            comment_of_model_id: Some(*model_id),
+            ..Default::default()
+        }
+    }
+
+    pub async fn get_by_server_id(server_id: &str, pool: PgPool) -> ReplyTarget {
+        //! Resolves the parent from the stored notes and models, unknown parents are dropped
+        match get_local_copy(server_id, pool).await {
+            Some(ModelOrNote::Note(d)) => ReplyTarget::from_note(&d),
+            Some(ModelOrNote::Model(d)) => ReplyTarget::from_model(&d.id),
+            None => ReplyTarget::default(),
+        }
+    }
+}
+
 #[derive(Serialize, Debug, PartialEq, Deserialize)]
 pub struct UserFacingNote {
     pub id: Uuid,
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreatePasswordResetToken {
+    pub account_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl CreatePasswordResetToken {
+    pub async fn create(self, pool: PgPool) -> Result<FullPasswordResetToken, Error> {
+        sqlx::query_as!(
+            FullPasswordResetToken,
+            r#"INSERT INTO password_reset_tokens (account_id, token_hash, expires_at) VALUES ($1, $2, $3)
+            RETURNING id, created_at, account_id, token_hash, expires_at, used_at"#,
+            self.account_id,
+            self.token_hash,
+            self.expires_at
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullPasswordResetToken {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub account_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl FullPasswordResetToken {
+    pub async fn redeem(
+        token_hash: &str,
+        password: &str,
+        pool: PgPool,
+    ) -> Result<Option<Uuid>, Error> {
+        //! Marks an unused, unexpired token as used, sets the new password hash and logs out all
+        //! sessions of its account in one transaction. The other open tokens of the account are used
+        //! up as well, so older emails can't reset the password again. Returns the account.
+        let mut tx = pool.begin().await?;
+        let account_id = sqlx::query_scalar!(
+            r#"UPDATE password_reset_tokens SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING account_id"#,
+            token_hash
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(account_id) = account_id {
+            sqlx::query!(
+                r#"UPDATE password_reset_tokens SET used_at = NOW() WHERE account_id = $1 AND used_at IS NULL"#,
+                account_id
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                r#"UPDATE account SET password = $2, updated_at = NOW() WHERE id = $1"#,
+                account_id,
+                password
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                r#"UPDATE sessions SET revoked_at = NOW() WHERE account_id = $1 AND revoked_at IS NULL"#,
+                account_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(account_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::account::FullAccount;
+    use chrono::TimeDelta;
+    use uuid::uuid;
+
+    const ACCOUNT: Uuid = uuid!("018e7b20-51e5-79c2-878e-02d01f941165");
+
+    async fn create_token(token_hash: &str, expires_in: TimeDelta, pool: &PgPool) {
+        CreatePasswordResetToken {
+            account_id: ACCOUNT,
+            token_hash: token_hash.to_string(),
+            expires_at: Utc::now() + expires_in,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_redeem(pool: PgPool) {
+        let hour = TimeDelta::try_hours(1).unwrap();
+        create_token("expired", -hour, &pool).await;
+        create_token("older", hour, &pool).await;
+        create_token("newer", hour, &pool).await;
+        assert_eq!(
+            FullPasswordResetToken::redeem("expired", "new hash", pool.clone())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            FullPasswordResetToken::redeem("unknown", "new hash", pool.clone())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            FullPasswordResetToken::redeem("newer", "new hash", pool.clone())
+                .await
+                .unwrap(),
+            Some(ACCOUNT)
+        );
+        // Single use, and the older email is used up with it
+        for token_hash in ["newer", "older"] {
+            assert_eq!(
+                FullPasswordResetToken::redeem(token_hash, "other hash", pool.clone())
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+        let account = FullAccount::get_by_id(&ACCOUNT, pool).await.unwrap();
+        assert_eq!(account.password, "new hash");
+    }
+}
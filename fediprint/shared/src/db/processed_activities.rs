@@ -0,0 +1,41 @@
+use sqlx::{Error, PgPool};
+
+// Senders retry failed deliveries for a few days at most, older ids are not needed anymore
+const PROCESSED_ACTIVITY_TTL_DAYS: i32 = 7;
+
+pub struct ProcessedActivity;
+
+impl ProcessedActivity {
+    pub async fn mark_received(activity_id: &str, pool: PgPool) -> Result<bool, Error> {
+        //! Stores the activity id, returns false if it was already received before
+        let inserted = sqlx::query_scalar!(
+            r#"INSERT INTO processed_activities (activity_id) VALUES ($1)
+            ON CONFLICT (activity_id) DO NOTHING
+            RETURNING activity_id"#,
+            activity_id
+        )
+        .fetch_optional(&pool)
+        .await?;
+        Ok(inserted.is_some())
+    }
+
+    pub async fn unmark(activity_id: &str, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            r#"DELETE FROM processed_activities WHERE activity_id = $1"#,
+            activity_id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_expired(pool: PgPool) -> Result<u64, Error> {
+        let res = sqlx::query!(
+            r#"DELETE FROM processed_activities WHERE received_at < NOW() - make_interval(days => $1)"#,
+            PROCESSED_ACTIVITY_TTL_DAYS
+        )
+        .execute(&pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+}
@@ -5,6 +5,7 @@ use sqlx::{Error, PgPool};
 use tracing::debug;
 use uuid::{uuid, Uuid};
 
+use crate::helpers::keys::KeyService;
 use crate::helpers::webfinger::get_webfinger_details;
 
 use super::instances::FullInstance;
@@ -26,7 +27,7 @@ impl CreateProfile {
     pub async fn create(self, pool: PgPool) -> Result<FullProfile, Error> {
         sqlx::query_as!(FullProfile,
             r#"INSERT INTO profile (username, server_id, display_name, inbox, outbox, public_key, instance) VALUES ($1, $2, $3, $4, $5, $6, $7)
-            RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance"#,
+            RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to"#,
             self.username, self.server_id, self.display_name, self.inbox, self.outbox, self.public_key, uuid!("00000000-0000-0000-0000-000000000000")
         ).fetch_one(&pool).await
     }
@@ -47,14 +48,17 @@ pub struct ExtendedCreateProfile {
     pub public_key: String,
     pub registered_at: DateTime<Utc>,
     pub instance: Uuid,
+    pub shared_inbox: Option<String>,
+    pub also_known_as: Vec<String>,
+    pub moved_to: Option<String>,
 }
 
 impl ExtendedCreateProfile {
     pub async fn create(self, pool: PgPool) -> Result<FullProfile, Error> {
         sqlx::query_as!(FullProfile,
-            r#"INSERT INTO profile (username, server_id, display_name, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, instance) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
-            RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance"#,
-            self.username, self.server_id, self.display_name, self.inbox, self.outbox,self.follower_count, self.following_count, self.message_count, self.public_key, self.registered_at, self.instance
+            r#"INSERT INTO profile (username, server_id, display_name, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, instance, shared_inbox, also_known_as, moved_to) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to"#,
+            self.username, self.server_id, self.display_name, self.inbox, self.outbox,self.follower_count, self.following_count, self.message_count, self.public_key, self.registered_at, self.instance, self.shared_inbox, &self.also_known_as, self.moved_to
         ).fetch_one(&pool).await
     }
 }
@@ -76,12 +80,16 @@ pub struct FullProfile {
     pub updated_at: DateTime<Utc>,
     pub linked_printables_profile: Option<String>,
     pub instance: Uuid,
+    pub manually_approves_followers: bool,
+    pub shared_inbox: Option<String>,
+    pub also_known_as: Vec<String>,
+    pub moved_to: Option<String>,
 }
 
 impl FullProfile {
     pub async fn get_by_id(id: &Uuid, pool: PgPool) -> Result<FullProfile, Error> {
         sqlx::query_as!(FullProfile,
-            r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance
+            r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to
             FROM profile WHERE id = $1"#,
             id).fetch_one(&pool).await
     }
@@ -90,7 +98,7 @@ impl FullProfile {
         instance_id: &Uuid,
         pool: PgPool,
     ) -> Result<FullProfile, Error> {
-        sqlx::query_as!(FullProfile, r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance
+        sqlx::query_as!(FullProfile, r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to
         FROM profile WHERE username = $1 and instance = $2"#,
             username, instance_id).fetch_one(&pool).await
     }
@@ -100,7 +108,7 @@ impl FullProfile {
         instance: FullInstance,
         pool: PgPool,
     ) -> Result<FullProfile, Error> {
-        sqlx::query_as!(FullProfile, r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance
+        sqlx::query_as!(FullProfile, r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to
         FROM profile WHERE LOWER(username) = LOWER($1) and instance = $2"#,
             name, instance.id).fetch_one(&pool).await
     }
@@ -126,6 +134,7 @@ impl FullProfile {
         name: &str,
         handle: &str,
         instance: FullInstance,
+        key_service: &KeyService,
         pool: PgPool,
     ) -> anyhow::Result<FullProfile> {
         debug!("Username: {}, instance_id: {}", &name, &instance.id);
@@ -145,25 +154,142 @@ impl FullProfile {
         FullProfile::get_from_activitypub(
             &webfinger_data.ap_page.unwrap(),
             instance.id,
+            key_service,
             pool.clone(),
         )
         .await
     }
 
     pub async fn get_by_server_id(server_id: &str, pool: PgPool) -> Result<FullProfile, Error> {
-        sqlx::query_as!(FullProfile, r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance
+        sqlx::query_as!(FullProfile, r#"SELECT id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to
         FROM profile WHERE server_id = $1"#,
             server_id).fetch_one(&pool).await
     }
+    pub async fn update_public_key(
+        id: &Uuid,
+        public_key: &str,
+        pool: PgPool,
+    ) -> Result<FullProfile, Error> {
+        sqlx::query_as!(FullProfile, r#"UPDATE profile SET public_key = $1 WHERE id = $2
+        RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to"#,
+            public_key, id).fetch_one(&pool).await
+    }
+    pub async fn claim_key_refresh(id: &Uuid, pool: PgPool) -> Result<bool, Error> {
+        //! Whether the key of the remote profile may be refetched. It is refetched at most once
+        //! every 10 minutes, so forged signatures can't make us fetch the actor over and over.
+        let res = sqlx::query!(
+            r#"UPDATE profile SET public_key_refreshed_at = NOW()
+            WHERE id = $1 AND (public_key_refreshed_at IS NULL OR public_key_refreshed_at < NOW() - INTERVAL '10 minutes')"#,
+            id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+    pub async fn delete_with_content(id: &Uuid, pool: PgPool) -> Result<Vec<Uuid>, Error> {
+        //! Deletes the profile with its account, models, notes, printers and file rows in one transaction.
+        //! Returns the ids of the deleted models and notes, so they can be removed from the search index.
+        //! The stored objects of the files are left to the caller, they can only be removed once this committed.
+        // The follows of the profile are deleted with it, which changes the counts of the other side
+        let followed = sqlx::query_scalar!(
+            r#"SELECT DISTINCT p.id FROM profile p JOIN followers f ON f.profile_id = p.id OR f.follower_id = p.id
+            WHERE (f.profile_id = $1 OR f.follower_id = $1) AND p.id != $1
+            AND p.instance = '00000000-0000-0000-0000-000000000000'"#,
+            id
+        )
+        .fetch_all(&pool)
+        .await?;
+        let mut tx = pool.begin().await?;
+        sqlx::query!(r#"DELETE FROM file WHERE profile_id = $1"#, id)
+            .execute(&mut *tx)
+            .await?;
+        let mut deleted =
+            sqlx::query_scalar!(r#"DELETE FROM note WHERE actor_id = $1 RETURNING id"#, id)
+                .fetch_all(&mut *tx)
+                .await?;
+        deleted.extend(
+            sqlx::query_scalar!(
+                r#"DELETE FROM model WHERE profile_id = $1 RETURNING id"#,
+                id
+            )
+            .fetch_all(&mut *tx)
+            .await?,
+        );
+        sqlx::query!(r#"DELETE FROM printer WHERE profile_id = $1"#, id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(r#"DELETE FROM account WHERE profile_id = $1"#, id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(r#"DELETE FROM profile WHERE id = $1"#, id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        for profile_id in &followed {
+            FullProfile::refresh_follower_count(profile_id, pool.clone()).await?;
+            FullProfile::refresh_following_count(profile_id, pool.clone()).await?;
+        }
+        Ok(deleted)
+    }
+    pub async fn refresh_follower_count(id: &Uuid, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            r#"UPDATE profile SET follower_count = (SELECT COUNT(*) FROM followers WHERE profile_id = $1 AND pending = false)
+            WHERE id = $1"#,
+            id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn refresh_following_count(id: &Uuid, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            r#"UPDATE profile SET following_count = (SELECT COUNT(*) FROM followers WHERE follower_id = $1 AND pending = false)
+            WHERE id = $1"#,
+            id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_manually_approves_followers(
+        id: &Uuid,
+        manually_approves_followers: bool,
+        pool: PgPool,
+    ) -> Result<FullProfile, Error> {
+        sqlx::query_as!(FullProfile, r#"UPDATE profile SET manually_approves_followers = $1 WHERE id = $2
+        RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to"#,
+            manually_approves_followers, id).fetch_one(&pool).await
+    }
+    pub async fn set_also_known_as(
+        id: &Uuid,
+        also_known_as: &[String],
+        pool: PgPool,
+    ) -> Result<FullProfile, Error> {
+        sqlx::query_as!(FullProfile, r#"UPDATE profile SET also_known_as = $1, updated_at = NOW() WHERE id = $2
+        RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to"#,
+            also_known_as, id).fetch_one(&pool).await
+    }
+    pub async fn set_moved_to(
+        id: &Uuid,
+        moved_to: Option<&str>,
+        pool: PgPool,
+    ) -> Result<FullProfile, Error> {
+        sqlx::query_as!(FullProfile, r#"UPDATE profile SET moved_to = $1, updated_at = NOW() WHERE id = $2
+        RETURNING id, username, server_id, display_name, summary, inbox, outbox, follower_count, following_count, message_count, public_key, registered_at, updated_at, linked_printables_profile, instance, manually_approves_followers, shared_inbox, also_known_as, moved_to"#,
+            moved_to, id).fetch_one(&pool).await
+    }
     pub async fn get_by_server_id_or_create(
         server_id: &str,
         instance_id: Uuid,
+        key_service: &KeyService,
         pool: PgPool,
     ) -> anyhow::Result<FullProfile> {
         if let Ok(d) = FullProfile::get_by_server_id(server_id, pool.clone()).await {
             return Ok(d);
         }
-        FullProfile::get_from_activitypub(server_id, instance_id, pool).await
+        FullProfile::get_from_activitypub(server_id, instance_id, key_service, pool).await
     }
 }
 
@@ -191,7 +317,7 @@ impl FullProfileWithFollower {
             r#"SELECT p.id, p.username,p.instance, p.server_id, p.display_name, p.summary
                 FROM followers f
                 JOIN profile p ON p.id = f.follower_id
-                WHERE f.profile_id = $1;"#,
+                WHERE f.profile_id = $1 AND NOT f.pending;"#,
             id
         )
         .fetch_all(&pool)
@@ -204,7 +330,7 @@ impl FullProfileWithFollower {
             r#"SELECT COUNT(p.id)
                 FROM followers f
                 JOIN profile p ON p.id = f.follower_id
-                WHERE f.profile_id = $1;"#,
+                WHERE f.profile_id = $1 AND NOT f.pending;"#,
             id
         )
         .fetch_one(&pool)
@@ -227,7 +353,7 @@ impl FullProfileWithFollowing {
             r#"SELECT p.id, p.username, p.instance, p.server_id, p.display_name, p.summary
                 FROM followers f
                 JOIN profile p ON p.id = f.follower_id
-                WHERE f.follower_id = $1;"#,
+                WHERE f.follower_id = $1 AND NOT f.pending;"#,
             id
         )
         .fetch_all(&pool)
@@ -240,7 +366,7 @@ impl FullProfileWithFollowing {
             r#"SELECT COUNT(p.id)
                 FROM followers f
                 JOIN profile p ON p.id = f.follower_id
-                WHERE f.follower_id = $1;"#,
+                WHERE f.follower_id = $1 AND NOT f.pending;"#,
             id
         )
         .fetch_one(&pool)
@@ -265,3 +391,81 @@ impl UsernameAndServerId {
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::followers::CreateFollower;
+
+    const PROFILE_ID: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c67");
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_delete_refreshes_counts(pool: PgPool) {
+        let other: Uuid = sqlx::query_scalar(
+            r#"INSERT INTO profile (username, server_id, display_name, summary, inbox, outbox, public_key, instance)
+            VALUES ('other', 'http://localhost:3000/api/v1/user/other', 'other', '', '', '', '',
+            '00000000-0000-0000-0000-000000000000') RETURNING id"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        for (profile_id, follower_id) in [(PROFILE_ID, other), (other, PROFILE_ID)] {
+            CreateFollower {
+                profile_id,
+                follower_id,
+                pending: false,
+                activity_id: None,
+            }
+            .create(pool.clone())
+            .await
+            .unwrap();
+            FullProfile::refresh_follower_count(&profile_id, pool.clone())
+                .await
+                .unwrap();
+            FullProfile::refresh_following_count(&follower_id, pool.clone())
+                .await
+                .unwrap();
+        }
+        let profile = FullProfile::get_by_id(&PROFILE_ID, pool.clone())
+            .await
+            .unwrap();
+        assert_eq!((profile.follower_count, profile.following_count), (1, 1));
+        FullProfile::delete_with_content(&other, pool.clone())
+            .await
+            .unwrap();
+        let profile = FullProfile::get_by_id(&PROFILE_ID, pool).await.unwrap();
+        assert_eq!((profile.follower_count, profile.following_count), (0, 0));
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_claim_key_refresh(pool: PgPool) {
+        assert!(FullProfile::claim_key_refresh(&PROFILE_ID, pool.clone())
+            .await
+            .unwrap());
+        assert!(!FullProfile::claim_key_refresh(&PROFILE_ID, pool.clone())
+            .await
+            .unwrap());
+        sqlx::query(
+            "UPDATE profile SET public_key_refreshed_at = NOW() - INTERVAL '11 minutes' WHERE id = $1",
+        )
+        .bind(PROFILE_ID)
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert!(FullProfile::claim_key_refresh(&PROFILE_ID, pool)
+            .await
+            .unwrap());
+    }
+}
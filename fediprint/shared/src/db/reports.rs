@@ -0,0 +1,184 @@
+use super::ReportStatus;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateReport {
+    pub reporter_id: Uuid,
+    pub profile_id: Uuid,
+    pub note_id: Option<Uuid>,
+    pub model_id: Option<Uuid>,
+    pub reason: String,
+    pub activity_id: Option<String>,
+}
+
+impl CreateReport {
+    pub async fn create(self, pool: PgPool) -> Result<FullReport, Error> {
+        sqlx::query_as!(
+            FullReport,
+            r#"INSERT INTO reports (reporter_id, profile_id, note_id, model_id, reason, activity_id) VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, created_at, reporter_id, profile_id, note_id, model_id, reason, activity_id, forwarded, status AS "status!: ReportStatus", resolved_at, resolved_by"#,
+            self.reporter_id,
+            self.profile_id,
+            self.note_id,
+            self.model_id,
+            self.reason,
+            self.activity_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullReport {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub reporter_id: Uuid,
+    pub profile_id: Uuid,
+    pub note_id: Option<Uuid>,
+    pub model_id: Option<Uuid>,
+    pub reason: String,
+    pub activity_id: Option<String>,
+    pub forwarded: bool,
+    pub status: ReportStatus,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<Uuid>,
+}
+
+impl FullReport {
+    pub async fn get_by_id(id: &Uuid, pool: PgPool) -> Result<FullReport, Error> {
+        sqlx::query_as!(
+            FullReport,
+            r#"SELECT id, created_at, reporter_id, profile_id, note_id, model_id, reason, activity_id, forwarded, status AS "status!: ReportStatus", resolved_at, resolved_by
+            FROM reports WHERE id = $1"#,
+            id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn get_by_status(
+        status: ReportStatus,
+        pool: PgPool,
+    ) -> Result<Vec<FullReport>, Error> {
+        //! The review queue, oldest reports first
+        sqlx::query_as!(
+            FullReport,
+            r#"SELECT id, created_at, reporter_id, profile_id, note_id, model_id, reason, activity_id, forwarded, status AS "status!: ReportStatus", resolved_at, resolved_by
+            FROM reports WHERE status = $1 ORDER BY created_at"#,
+            status as _
+        )
+        .fetch_all(&pool)
+        .await
+    }
+
+    pub async fn mark_forwarded(
+        self,
+        activity_id: &str,
+        pool: PgPool,
+    ) -> Result<FullReport, Error> {
+        sqlx::query_as!(
+            FullReport,
+            r#"UPDATE reports SET forwarded = true, activity_id = $2 WHERE id = $1
+            RETURNING id, created_at, reporter_id, profile_id, note_id, model_id, reason, activity_id, forwarded, status AS "status!: ReportStatus", resolved_at, resolved_by"#,
+            self.id,
+            activity_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn set_status(
+        self,
+        status: ReportStatus,
+        resolved_by: &Uuid,
+        pool: PgPool,
+    ) -> Result<FullReport, Error> {
+        //! Reopening a report clears who resolved it
+        sqlx::query_as!(
+            FullReport,
+            r#"UPDATE reports SET status = $2,
+                resolved_at = CASE WHEN $2 = 'OPEN'::report_status THEN NULL ELSE NOW() END,
+                resolved_by = CASE WHEN $2 = 'OPEN'::report_status THEN NULL ELSE $3::uuid END
+            WHERE id = $1
+            RETURNING id, created_at, reporter_id, profile_id, note_id, model_id, reason, activity_id, forwarded, status AS "status!: ReportStatus", resolved_at, resolved_by"#,
+            self.id,
+            status as _,
+            resolved_by
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::uuid;
+
+    const AUTHOR: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c67");
+    const ALICE: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c68");
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles", "statuses")
+        )
+    )]
+    async fn test_report_lifecycle(pool: PgPool) {
+        let report = CreateReport {
+            reporter_id: ALICE,
+            profile_id: AUTHOR,
+            note_id: Some(uuid!("20000000-0000-0000-0000-000000000001")),
+            model_id: None,
+            reason: "spam".to_string(),
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert_eq!(report.status, ReportStatus::Open);
+        assert!(!report.forwarded);
+        assert_eq!(
+            FullReport::get_by_status(ReportStatus::Open, pool.clone())
+                .await
+                .unwrap(),
+            vec![report.clone()]
+        );
+
+        let report = report
+            .mark_forwarded("http://localhost:3000/actor#reports/1", pool.clone())
+            .await
+            .unwrap();
+        assert!(report.forwarded);
+        assert_eq!(
+            report.activity_id.as_deref(),
+            Some("http://localhost:3000/actor#reports/1")
+        );
+
+        let report = report
+            .set_status(ReportStatus::Resolved, &AUTHOR, pool.clone())
+            .await
+            .unwrap();
+        assert_eq!(report.resolved_by, Some(AUTHOR));
+        assert!(report.resolved_at.is_some());
+        assert!(FullReport::get_by_status(ReportStatus::Open, pool.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        // Reopening clears who resolved it
+        let report = report
+            .set_status(ReportStatus::Open, &AUTHOR, pool.clone())
+            .await
+            .unwrap();
+        assert_eq!((report.resolved_by, report.resolved_at), (None, None));
+        assert_eq!(
+            FullReport::get_by_id(&report.id, pool).await.unwrap(),
+            report
+        );
+    }
+}
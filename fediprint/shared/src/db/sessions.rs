@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateSession {
+    pub account_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl CreateSession {
+    pub async fn create(self, pool: PgPool) -> Result<FullSession, Error> {
+        sqlx::query_as!(
+            FullSession,
+            r#"INSERT INTO sessions (account_id, user_agent, ip) VALUES ($1, $2, $3)
+            RETURNING id, created_at, account_id, user_agent, ip, last_seen_at, revoked_at"#,
+            self.account_id,
+            self.user_agent,
+            self.ip
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullSession {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub account_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub last_seen_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl FullSession {
+    pub async fn is_active(id: &Uuid, account_id: &Uuid, pool: PgPool) -> Result<bool, Error> {
+        //! Sessions of suspended accounts don't count as active either
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM sessions s JOIN account a ON a.id = s.account_id
+                WHERE s.id = $1 AND s.account_id = $2 AND s.revoked_at IS NULL AND NOT a.suspended) AS "active!""#,
+            id,
+            account_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn touch(
+        id: &Uuid,
+        account_id: &Uuid,
+        ip: Option<&str>,
+        pool: PgPool,
+    ) -> Result<Option<FullSession>, Error> {
+        //! Called when the token of the session is renewed. Returns None for sessions that
+        //! aren't active, same as `is_active`.
+        sqlx::query_as!(
+            FullSession,
+            r#"UPDATE sessions SET last_seen_at = NOW(), ip = COALESCE($3, ip)
+            WHERE id = $1 AND account_id = $2 AND revoked_at IS NULL
+                AND NOT EXISTS (SELECT 1 FROM account a WHERE a.id = $2 AND a.suspended)
+            RETURNING id, created_at, account_id, user_agent, ip, last_seen_at, revoked_at"#,
+            id,
+            account_id,
+            ip
+        )
+        .fetch_optional(&pool)
+        .await
+    }
+
+    pub async fn get_active_by_account_id(
+        account_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Vec<FullSession>, Error> {
+        sqlx::query_as!(
+            FullSession,
+            r#"SELECT id, created_at, account_id, user_agent, ip, last_seen_at, revoked_at
+            FROM sessions WHERE account_id = $1 AND revoked_at IS NULL
+            ORDER BY last_seen_at DESC"#,
+            account_id
+        )
+        .fetch_all(&pool)
+        .await
+    }
+
+    pub async fn revoke(id: &Uuid, account_id: &Uuid, pool: PgPool) -> Result<bool, Error> {
+        //! False if the account has no such active session
+        let res = sqlx::query!(
+            r#"UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND account_id = $2 AND revoked_at IS NULL"#,
+            id,
+            account_id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn revoke_all(account_id: &Uuid, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            r#"UPDATE sessions SET revoked_at = NOW() WHERE account_id = $1 AND revoked_at IS NULL"#,
+            account_id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::account::FullAccount;
+    use uuid::uuid;
+
+    const ACCOUNT: Uuid = uuid!("018e7b20-51e5-79c2-878e-02d01f941165");
+
+    async fn create_session(pool: &PgPool) -> FullSession {
+        CreateSession {
+            account_id: ACCOUNT,
+            user_agent: Some("test".to_string()),
+            ip: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_revoke(pool: PgPool) {
+        let first = create_session(&pool).await;
+        let second = create_session(&pool).await;
+        assert!(FullSession::is_active(&first.id, &ACCOUNT, pool.clone())
+            .await
+            .unwrap());
+        // Sessions belong to their account
+        assert!(
+            !FullSession::is_active(&first.id, &Uuid::now_v7(), pool.clone())
+                .await
+                .unwrap()
+        );
+        let touched = FullSession::touch(&first.id, &ACCOUNT, Some("127.0.0.1"), pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(touched.ip.as_deref(), Some("127.0.0.1"));
+
+        assert!(FullSession::revoke(&first.id, &ACCOUNT, pool.clone())
+            .await
+            .unwrap());
+        assert!(!FullSession::revoke(&first.id, &ACCOUNT, pool.clone())
+            .await
+            .unwrap());
+        assert!(!FullSession::is_active(&first.id, &ACCOUNT, pool.clone())
+            .await
+            .unwrap());
+        assert_eq!(
+            FullSession::touch(&first.id, &ACCOUNT, None, pool.clone())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            FullSession::get_active_by_account_id(&ACCOUNT, pool.clone())
+                .await
+                .unwrap()
+                .iter()
+                .map(|v| v.id)
+                .collect::<Vec<Uuid>>(),
+            vec![second.id]
+        );
+
+        FullSession::revoke_all(&ACCOUNT, pool.clone())
+            .await
+            .unwrap();
+        assert!(!FullSession::is_active(&second.id, &ACCOUNT, pool.clone())
+            .await
+            .unwrap());
+        assert!(FullSession::get_active_by_account_id(&ACCOUNT, pool)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_suspended_account(pool: PgPool) {
+        let session = create_session(&pool).await;
+        FullAccount::set_suspended(&ACCOUNT, true, pool.clone())
+            .await
+            .unwrap();
+        assert!(!FullSession::is_active(&session.id, &ACCOUNT, pool.clone())
+            .await
+            .unwrap());
+        assert_eq!(
+            FullSession::touch(&session.id, &ACCOUNT, None, pool.clone())
+                .await
+                .unwrap(),
+            None
+        );
+        // Lifting the suspension brings the session back
+        FullAccount::set_suspended(&ACCOUNT, false, pool.clone())
+            .await
+            .unwrap();
+        assert!(FullSession::is_active(&session.id, &ACCOUNT, pool)
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,43 @@
+use super::ReportStatus;
+use serde_derive::Serialize;
+use sqlx::{Error, PgPool};
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct InstanceStats {
+    pub local_accounts: i64,
+    pub suspended_accounts: i64,
+    pub remote_profiles: i64,
+    pub known_instances: i64,
+    pub local_models: i64,
+    pub remote_models: i64,
+    pub notes: i64,
+    pub files: i64,
+    pub stored_bytes: i64,
+    pub open_reports: i64,
+    pub pending_jobs: i64,
+}
+
+impl InstanceStats {
+    pub async fn get(pool: PgPool) -> Result<InstanceStats, Error> {
+        sqlx::query_as!(
+            InstanceStats,
+            r#"SELECT
+                (SELECT COUNT(*) FROM account) AS "local_accounts!",
+                (SELECT COUNT(*) FROM account WHERE suspended) AS "suspended_accounts!",
+                (SELECT COUNT(*) FROM profile WHERE instance <> '00000000-0000-0000-0000-000000000000') AS "remote_profiles!",
+                (SELECT COUNT(*) FROM instances WHERE id <> '00000000-0000-0000-0000-000000000000') AS "known_instances!",
+                (SELECT COUNT(*) FROM model m JOIN profile p ON p.id = m.profile_id
+                    WHERE p.instance = '00000000-0000-0000-0000-000000000000') AS "local_models!",
+                (SELECT COUNT(*) FROM model m JOIN profile p ON p.id = m.profile_id
+                    WHERE p.instance <> '00000000-0000-0000-0000-000000000000') AS "remote_models!",
+                (SELECT COUNT(*) FROM note) AS "notes!",
+                (SELECT COUNT(*) FROM file) AS "files!",
+                (SELECT COALESCE(SUM(size), 0)::bigint FROM file) AS "stored_bytes!",
+                (SELECT COUNT(*) FROM reports WHERE status = $1) AS "open_reports!",
+                (SELECT COUNT(*) FROM jobs WHERE status IN ('UNPROCESSED', 'WAITING_FOR_RETRY', 'PROCESSING')) AS "pending_jobs!""#,
+            ReportStatus::Open as _
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
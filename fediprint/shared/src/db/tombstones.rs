@@ -0,0 +1,39 @@
+use sqlx::{Error, PgPool};
+
+pub struct Tombstone;
+
+impl Tombstone {
+    pub async fn create(server_id: &str, pool: PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            r#"INSERT INTO tombstones (server_id) VALUES ($1) ON CONFLICT (server_id) DO NOTHING"#,
+            server_id
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn exists(server_id: &str, pool: PgPool) -> Result<bool, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM tombstones WHERE server_id = $1) AS "exists!""#,
+            server_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_tombstone(pool: PgPool) {
+        let server_id = "https://mastodon.online/users/Mawoka/statuses/1";
+        assert!(!Tombstone::exists(server_id, pool.clone()).await.unwrap());
+        Tombstone::create(server_id, pool.clone()).await.unwrap();
+        // Retried deliveries of the same Delete are fine
+        Tombstone::create(server_id, pool.clone()).await.unwrap();
+        assert!(Tombstone::exists(server_id, pool).await.unwrap());
+    }
+}
@@ -0,0 +1,373 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullTwoFactor {
+    pub account_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub encrypted_secret: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_step: Option<i64>,
+    pub failed_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl FullTwoFactor {
+    pub async fn start(
+        account_id: &Uuid,
+        encrypted_secret: &str,
+        pool: PgPool,
+    ) -> Result<Option<FullTwoFactor>, Error> {
+        //! Stores a new secret for the enrolment. Returns nothing if 2FA is already enabled,
+        //! an unconfirmed secret is replaced.
+        sqlx::query_as!(
+            FullTwoFactor,
+            r#"INSERT INTO two_factor (account_id, encrypted_secret) VALUES ($1, $2)
+            ON CONFLICT (account_id) DO UPDATE SET encrypted_secret = $2, created_at = NOW(), last_step = NULL
+            WHERE two_factor.confirmed_at IS NULL
+            RETURNING account_id, created_at, encrypted_secret, confirmed_at, last_step, failed_attempts, locked_until"#,
+            account_id,
+            encrypted_secret
+        )
+        .fetch_optional(&pool)
+        .await
+    }
+
+    pub async fn get_by_account_id(
+        account_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Option<FullTwoFactor>, Error> {
+        sqlx::query_as!(
+            FullTwoFactor,
+            r#"SELECT account_id, created_at, encrypted_secret, confirmed_at, last_step, failed_attempts, locked_until
+            FROM two_factor WHERE account_id = $1"#,
+            account_id
+        )
+        .fetch_optional(&pool)
+        .await
+    }
+
+    pub async fn get_enabled(
+        account_id: &Uuid,
+        pool: PgPool,
+    ) -> Result<Option<FullTwoFactor>, Error> {
+        //! Only confirmed secrets count, an abandoned enrolment doesn't lock anybody out
+        sqlx::query_as!(
+            FullTwoFactor,
+            r#"SELECT account_id, created_at, encrypted_secret, confirmed_at, last_step, failed_attempts, locked_until
+            FROM two_factor WHERE account_id = $1 AND confirmed_at IS NOT NULL"#,
+            account_id
+        )
+        .fetch_optional(&pool)
+        .await
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked_until.is_some_and(|d| d > Utc::now())
+    }
+
+    pub async fn use_step(account_id: &Uuid, step: i64, pool: PgPool) -> Result<bool, Error> {
+        //! Marks the time step of an accepted code as used. Fails for steps that were used already,
+        //! so an intercepted code can't be replayed.
+        let res = sqlx::query!(
+            r#"UPDATE two_factor SET last_step = $2, failed_attempts = 0
+            WHERE account_id = $1 AND (last_step IS NULL OR last_step < $2)"#,
+            account_id,
+            step
+        )
+        .execute(&pool)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    pub async fn record_failure(
+        account_id: &Uuid,
+        max_attempts: i32,
+        lock_minutes: i32,
+        pool: PgPool,
+    ) -> Result<(), Error> {
+        //! Counts a wrong code. Reaching the limit locks the second factor and starts counting anew.
+        sqlx::query!(
+            r#"UPDATE two_factor SET
+                failed_attempts = CASE WHEN failed_attempts + 1 >= $2 THEN 0 ELSE failed_attempts + 1 END,
+                locked_until = CASE WHEN failed_attempts + 1 >= $2 THEN NOW() + make_interval(mins => $3) ELSE locked_until END
+            WHERE account_id = $1"#,
+            account_id,
+            max_attempts,
+            lock_minutes
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn confirm(
+        account_id: &Uuid,
+        step: i64,
+        recovery_code_hashes: &[String],
+        pool: PgPool,
+    ) -> Result<bool, Error> {
+        //! Enables 2FA together with a fresh set of recovery codes
+        let mut tx = pool.begin().await?;
+        let res = sqlx::query!(
+            r#"UPDATE two_factor SET confirmed_at = NOW(), last_step = $2
+            WHERE account_id = $1 AND confirmed_at IS NULL"#,
+            account_id,
+            step
+        )
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() != 1 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            r#"DELETE FROM recovery_codes WHERE account_id = $1"#,
+            account_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO recovery_codes (account_id, code_hash) SELECT $1, UNNEST($2::text[])"#,
+            account_id,
+            recovery_code_hashes
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn disable(account_id: &Uuid, pool: PgPool) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            r#"DELETE FROM recovery_codes WHERE account_id = $1"#,
+            account_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"DELETE FROM two_factor WHERE account_id = $1"#,
+            account_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullRecoveryCode {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub account_id: Uuid,
+    pub code_hash: String,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl FullRecoveryCode {
+    pub async fn redeem(account_id: &Uuid, code_hash: &str, pool: PgPool) -> Result<bool, Error> {
+        //! Every recovery code works once
+        let res = sqlx::query!(
+            r#"UPDATE recovery_codes SET used_at = NOW()
+            WHERE account_id = $1 AND code_hash = $2 AND used_at IS NULL"#,
+            account_id,
+            code_hash
+        )
+        .execute(&pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn count_unused(account_id: &Uuid, pool: PgPool) -> Result<i64, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM recovery_codes WHERE account_id = $1 AND used_at IS NULL"#,
+            account_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize)]
+pub struct CreateTwoFactorChallenge {
+    pub account_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl CreateTwoFactorChallenge {
+    pub async fn create(self, pool: PgPool) -> Result<FullTwoFactorChallenge, Error> {
+        sqlx::query_as!(
+            FullTwoFactorChallenge,
+            r#"INSERT INTO two_factor_challenges (account_id, token_hash, expires_at) VALUES ($1, $2, $3)
+            RETURNING id, created_at, account_id, token_hash, expires_at, used_at"#,
+            self.account_id,
+            self.token_hash,
+            self.expires_at
+        )
+        .fetch_one(&pool)
+        .await
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Deserialize, Clone)]
+pub struct FullTwoFactorChallenge {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub account_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl FullTwoFactorChallenge {
+    pub async fn consume(token_hash: &str, pool: PgPool) -> Result<Option<Uuid>, Error> {
+        //! Uses up an open challenge and returns its account. Every challenge allows a single try,
+        //! no matter if the code was right.
+        sqlx::query_scalar!(
+            r#"UPDATE two_factor_challenges SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING account_id"#,
+            token_hash
+        )
+        .fetch_optional(&pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use uuid::uuid;
+
+    const ACCOUNT_ID: Uuid = uuid!("018e7b20-51e5-79c2-878e-02d01f941165");
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_challenge_allows_one_try(pool: PgPool) {
+        CreateTwoFactorChallenge {
+            account_id: ACCOUNT_ID,
+            token_hash: "hash".to_string(),
+            expires_at: Utc::now() + TimeDelta::try_minutes(5).unwrap(),
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert_eq!(
+            FullTwoFactorChallenge::consume("hash", pool.clone())
+                .await
+                .unwrap(),
+            Some(ACCOUNT_ID)
+        );
+        assert_eq!(
+            FullTwoFactorChallenge::consume("hash", pool.clone())
+                .await
+                .unwrap(),
+            None
+        );
+        CreateTwoFactorChallenge {
+            account_id: ACCOUNT_ID,
+            token_hash: "expired".to_string(),
+            expires_at: Utc::now() - TimeDelta::try_minutes(1).unwrap(),
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert_eq!(
+            FullTwoFactorChallenge::consume("expired", pool)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_failed_attempts_lock(pool: PgPool) {
+        FullTwoFactor::start(&ACCOUNT_ID, "v1:secret", pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(FullTwoFactor::confirm(&ACCOUNT_ID, 1, &[], pool.clone())
+            .await
+            .unwrap());
+        for _ in 0..4 {
+            FullTwoFactor::record_failure(&ACCOUNT_ID, 5, 15, pool.clone())
+                .await
+                .unwrap();
+        }
+        let two_factor = FullTwoFactor::get_enabled(&ACCOUNT_ID, pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(two_factor.failed_attempts, 4);
+        assert!(!two_factor.is_locked());
+        FullTwoFactor::record_failure(&ACCOUNT_ID, 5, 15, pool.clone())
+            .await
+            .unwrap();
+        let two_factor = FullTwoFactor::get_enabled(&ACCOUNT_ID, pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(two_factor.is_locked());
+        assert_eq!(two_factor.failed_attempts, 0);
+        // A confirmed secret can't be replaced by a new enrolment
+        assert!(FullTwoFactor::start(&ACCOUNT_ID, "v1:other", pool)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user")
+        )
+    )]
+    async fn test_steps_and_recovery_codes_are_used_once(pool: PgPool) {
+        FullTwoFactor::start(&ACCOUNT_ID, "v1:secret", pool.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            FullTwoFactor::confirm(&ACCOUNT_ID, 10, &["code".to_string()], pool.clone())
+                .await
+                .unwrap()
+        );
+        assert!(!FullTwoFactor::use_step(&ACCOUNT_ID, 10, pool.clone())
+            .await
+            .unwrap());
+        assert!(FullTwoFactor::use_step(&ACCOUNT_ID, 11, pool.clone())
+            .await
+            .unwrap());
+        assert!(!FullTwoFactor::use_step(&ACCOUNT_ID, 11, pool.clone())
+            .await
+            .unwrap());
+        assert!(FullRecoveryCode::redeem(&ACCOUNT_ID, "code", pool.clone())
+            .await
+            .unwrap());
+        assert!(!FullRecoveryCode::redeem(&ACCOUNT_ID, "code", pool.clone())
+            .await
+            .unwrap());
+        assert_eq!(
+            FullRecoveryCode::count_unused(&ACCOUNT_ID, pool)
+                .await
+                .unwrap(),
+            0
+        );
+    }
+}
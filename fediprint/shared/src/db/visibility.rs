@@ -0,0 +1,224 @@
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+use crate::models::activitypub::NoteJoinedModel;
+
+/// The profile a note or model is read by, either from the session or a signed fetch.
+/// The rules themselves live in the `can_view_note` and `can_view_model` sql functions,
+/// so they can be used in list queries as well.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Viewer {
+    pub profile_id: Option<Uuid>,
+}
+
+impl Viewer {
+    pub fn anonymous() -> Viewer {
+        Viewer { profile_id: None }
+    }
+
+    pub fn from_profile_id(profile_id: &Uuid) -> Viewer {
+        Viewer {
+            profile_id: Some(*profile_id),
+        }
+    }
+
+    pub async fn can_view_note(&self, note_id: &Uuid, pool: PgPool) -> Result<bool, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT can_view_note($1, $2) AS "visible!""#,
+            note_id,
+            self.profile_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn can_view_model(&self, model_id: &Uuid, pool: PgPool) -> Result<bool, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT can_view_model($1, $2) AS "visible!""#,
+            model_id,
+            self.profile_id
+        )
+        .fetch_one(&pool)
+        .await
+    }
+
+    pub async fn can_view(&self, status: &NoteJoinedModel, pool: PgPool) -> Result<bool, Error> {
+        match (status.note_id, status.model_id) {
+            (Some(note_id), _) => self.can_view_note(&note_id, pool).await,
+            (None, Some(model_id)) => self.can_view_model(&model_id, pool).await,
+            (None, None) => Ok(false),
+        }
+    }
+}
+
+impl NoteJoinedModel {
+    pub async fn get_by_id_for_viewer(
+        id: &Uuid,
+        viewer: &Viewer,
+        pool: PgPool,
+    ) -> Result<NoteJoinedModel, Error> {
+        //! Hidden notes and models are reported as missing, so their existence doesn't leak
+        let status = NoteJoinedModel::get_by_id(id, pool.clone()).await?;
+        if !viewer.can_view(&status, pool).await? {
+            return Err(Error::RowNotFound);
+        }
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::blocks::CreateBlock;
+    use crate::db::followers::CreateFollower;
+    use crate::db::model::{FullModel, FullModelWithRelationsIds};
+    use uuid::uuid;
+
+    const AUTHOR: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c67");
+    const ALICE: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c68");
+    const BOB: Uuid = uuid!("018e7b20-51bd-703a-96c6-9c70cc723c69");
+    const NOTES: [Uuid; 4] = [
+        uuid!("20000000-0000-0000-0000-000000000001"),
+        uuid!("20000000-0000-0000-0000-000000000002"),
+        uuid!("20000000-0000-0000-0000-000000000003"),
+        uuid!("20000000-0000-0000-0000-000000000004"),
+    ];
+
+    async fn visible_notes(viewer: &Viewer, pool: &PgPool) -> Vec<bool> {
+        let mut visible = vec![];
+        for note_id in &NOTES {
+            visible.push(viewer.can_view_note(note_id, pool.clone()).await.unwrap());
+        }
+        visible
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles", "statuses")
+        )
+    )]
+    async fn test_can_view_note(pool: PgPool) {
+        // Public, followers-only, mentioned and private
+        assert_eq!(
+            visible_notes(&Viewer::anonymous(), &pool).await,
+            vec![true, false, false, false]
+        );
+        assert_eq!(
+            visible_notes(&Viewer::from_profile_id(&AUTHOR), &pool).await,
+            vec![true, true, true, true]
+        );
+        assert_eq!(
+            visible_notes(&Viewer::from_profile_id(&ALICE), &pool).await,
+            vec![true, false, true, false]
+        );
+        let bob = Viewer::from_profile_id(&BOB);
+        assert_eq!(
+            visible_notes(&bob, &pool).await,
+            vec![true, false, false, false]
+        );
+        let follow = CreateFollower {
+            profile_id: AUTHOR,
+            follower_id: BOB,
+            pending: true,
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        // Follow requests don't count until they are accepted
+        assert_eq!(
+            visible_notes(&bob, &pool).await,
+            vec![true, false, false, false]
+        );
+        follow.accept(pool.clone()).await.unwrap();
+        assert_eq!(
+            visible_notes(&bob, &pool).await,
+            vec![true, true, false, false]
+        );
+        assert!(!Viewer::anonymous()
+            .can_view_note(&Uuid::now_v7(), pool)
+            .await
+            .unwrap());
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles", "statuses")
+        )
+    )]
+    async fn test_can_view_model(pool: PgPool) {
+        let published = uuid!("30000000-0000-0000-0000-000000000001");
+        let draft = uuid!("30000000-0000-0000-0000-000000000002");
+        for viewer in [Viewer::anonymous(), Viewer::from_profile_id(&ALICE)] {
+            assert!(viewer
+                .can_view_model(&published, pool.clone())
+                .await
+                .unwrap());
+            assert!(!viewer.can_view_model(&draft, pool.clone()).await.unwrap());
+        }
+        let owner = Viewer::from_profile_id(&AUTHOR);
+        assert!(owner.can_view_model(&draft, pool).await.unwrap());
+    }
+
+    async fn newest_models(viewer: &Viewer, pool: &PgPool) -> (Vec<Uuid>, Vec<Uuid>) {
+        let models =
+            FullModel::get_newest_published_models_paginated(&20, &0, viewer, pool.clone())
+                .await
+                .unwrap();
+        let with_relations = FullModelWithRelationsIds::get_newest_published_models_paginated(
+            &20,
+            &0,
+            false,
+            viewer,
+            pool.clone(),
+        )
+        .await
+        .unwrap();
+        (
+            models.iter().map(|v| v.id).collect(),
+            with_relations.iter().map(|v| v.id).collect(),
+        )
+    }
+
+    #[sqlx::test(
+        migrations = "../../migrations",
+        fixtures(
+            path = "../../../app/src/routes/api/v1/fixtures",
+            scripts("local_user", "local_profiles", "statuses")
+        )
+    )]
+    async fn test_newest_models_are_visible(pool: PgPool) {
+        let published = vec![uuid!("30000000-0000-0000-0000-000000000001")];
+        // Drafts are left out even for their owner
+        for viewer in [
+            Viewer::anonymous(),
+            Viewer::from_profile_id(&AUTHOR),
+            Viewer::from_profile_id(&BOB),
+        ] {
+            assert_eq!(
+                newest_models(&viewer, &pool).await,
+                (published.clone(), published.clone())
+            );
+        }
+        CreateBlock {
+            profile_id: AUTHOR,
+            target_id: BOB,
+            activity_id: None,
+        }
+        .create(pool.clone())
+        .await
+        .unwrap();
+        assert_eq!(
+            newest_models(&Viewer::from_profile_id(&BOB), &pool).await,
+            (vec![], vec![])
+        );
+        assert_eq!(
+            newest_models(&Viewer::anonymous(), &pool).await,
+            (published.clone(), published)
+        );
+    }
+}
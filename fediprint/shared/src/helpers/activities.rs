@@ -1,16 +1,23 @@
 use std::sync::Arc;
 
 use reqwest::StatusCode;
+use serde_json::{json, Map, Value};
+use sqlx::PgPool;
 use tracing::error;
 use url::Url;
 
 use crate::{
-    db::{model::FullModelWithRelationsIds, note::FullNote, profile::FullProfile},
+    db::{
+        instances::FullInstance, model::FullModelWithRelationsIds, note::FullNote,
+        profile::FullProfile,
+    },
     models::activitypub::note::NoteResponse,
     AppState,
 };
 
 use super::instances::get_instance_by_base_url;
+use super::keys::KeyService;
+use super::sign::get_signed;
 #[derive(Debug)]
 pub enum ModelOrNote {
     Note(FullNote),
@@ -41,28 +48,23 @@ pub async fn get_remote_activity(
         Some(d) => d,
         None => return Err(GetRemoteActivtyErrors::CouldNotExtractHost),
     };
-    let instance =
-        match get_instance_by_base_url(&format!("https://{instance_host}"), state.pool.clone())
-            .await
-        {
-            Ok(d) => d,
-            Err(e) => return Err(GetRemoteActivtyErrors::FailedToFetchInstance(e.to_string())),
-        };
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(
-        "Accept",
-        reqwest::header::HeaderValue::from_str("application/activity+json").unwrap(),
-    );
-
-    let ap_client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()
-        .unwrap();
-
-    let req = ap_client.get(url).send().await;
-    let res = match req {
+    let key_service = KeyService::new(&state.env);
+    let instance = match get_instance_by_base_url(
+        &format!("https://{instance_host}"),
+        &key_service,
+        state.pool.clone(),
+    )
+    .await
+    {
         Ok(d) => d,
-        Err(_) => return Err(GetRemoteActivtyErrors::RequestFailed),
+        Err(e) => return Err(GetRemoteActivtyErrors::FailedToFetchInstance(e.to_string())),
+    };
+    let res = match get_signed(&url, &key_service, state.pool.clone()).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Fetching {} failed: {}", url, e);
+            return Err(GetRemoteActivtyErrors::RequestFailed);
+        }
     };
     if res.status() != 200 {
         return Err(GetRemoteActivtyErrors::RequestFailedWithCode(res.status()));
@@ -78,9 +80,23 @@ pub async fn get_remote_activity(
         Ok(d) => d,
         Err(e) => return Err(GetRemoteActivtyErrors::JsonParsingFailed(e.to_string())),
     };
+    store_note_response(data, instance, state).await
+}
+
+pub async fn store_note_response(
+    data: NoteResponse,
+    instance: FullInstance,
+    state: Arc<AppState>,
+) -> Result<ModelOrNote, GetRemoteActivtyErrors> {
+    //! Stores a remote note or model (detected via the `3dModel` context key) unless already known
+    let instance_host = match Url::parse(&instance.base_url) {
+        Ok(d) => d.host_str().unwrap_or_default().to_string(),
+        Err(_) => return Err(GetRemoteActivtyErrors::CouldNotExtractHost),
+    };
     let profile = FullProfile::get_by_server_id_or_create(
         &data.attributed_to,
         instance.id,
+        &KeyService::new(&state.env),
         state.pool.clone(),
     )
     .await
@@ -89,7 +105,7 @@ pub async fn get_remote_activity(
         Ok(ModelOrNote::Model(
             FullModelWithRelationsIds::create_or_get_from_note_response(
                 data,
-                instance_host.to_string(),
+                instance_host,
                 profile.id,
                 state.clone(),
             )
@@ -107,3 +123,35 @@ pub async fn get_remote_activity(
         ))
     }
 }
+
+pub fn normalize_context(context: &Value) -> Value {
+    //! Brings an `@context` into the `[namespace, {extensions}]` form `NoteResponse` expects
+    let mut namespace = "https://www.w3.org/ns/activitystreams".to_string();
+    let mut extensions = Map::new();
+    match context {
+        Value::String(d) => namespace = d.clone(),
+        Value::Array(entries) => {
+            for (i, entry) in entries.iter().enumerate() {
+                match entry {
+                    Value::String(d) if i == 0 => namespace = d.clone(),
+                    Value::Object(d) => extensions.extend(d.clone()),
+                    _ => {}
+                }
+            }
+        }
+        Value::Object(d) => extensions.extend(d.clone()),
+        _ => {}
+    }
+    json!([namespace, extensions])
+}
+
+pub async fn get_local_copy(server_id: &str, pool: PgPool) -> Option<ModelOrNote> {
+    //! Looks up an already stored note or model by its ActivityPub id
+    if let Ok(d) = FullNote::get_by_server_id(server_id, pool.clone()).await {
+        return Some(ModelOrNote::Note(d));
+    }
+    FullModelWithRelationsIds::get_by_server_id(server_id, false, pool)
+        .await
+        .ok()
+        .map(ModelOrNote::Model)
+}
@@ -1,11 +1,24 @@
 use percent_encoding::percent_decode_str;
 use std::str::FromStr;
+use strum::EnumString;
 use url::Url;
 
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum EmailVerification {
+    /// Unverified accounts can do everything
+    Optional,
+    /// Unverified accounts can log in, but not post notes, models or files
+    Posting,
+    /// Unverified accounts can't log in
+    Login,
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub jwt_secret: String,
+    pub key_encryption_key: String,
     pub public_url: String,
     pub base_domain: String,
     pub s3_base_url: String,
@@ -16,6 +29,9 @@ pub struct Config {
     pub meilisearch_url: String,
     pub meilisearch_key: String,
     pub registration_disabled: bool,
+    pub authorized_fetch: bool,
+    pub email_verification: EmailVerification,
+    pub admins: Vec<String>,
     pub smtp: SmtpData,
     pub stripe: Option<StripeData>,
 }
@@ -41,6 +57,11 @@ impl Config {
     pub fn init() -> Config {
         let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
         let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+        let key_encryption_key =
+            std::env::var("KEY_ENCRYPTION_KEY").expect("KEY_ENCRYPTION_KEY must be set");
+        if key_encryption_key == jwt_secret {
+            panic!("KEY_ENCRYPTION_KEY must differ from JWT_SECRET")
+        }
         let public_url = std::env::var("PUBLIC_URL").expect("PUBLIC_URL must be set");
         let base_domain = std::env::var("BASE_DOMAIN").expect("BASE_DOMAIN must be set");
         let s3_base_url = std::env::var("S3_BASE_URL").expect("S3_BASE_URL must be set");
@@ -51,6 +72,19 @@ impl Config {
         let registration_disabled =
             bool::from_str(&std::env::var("REGISTRATION_DISABLED").unwrap_or("false".to_string()))
                 .expect("REGISTRATION_DISABLED no valid boolean");
+        let authorized_fetch =
+            bool::from_str(&std::env::var("AUTHORIZED_FETCH").unwrap_or("false".to_string()))
+                .expect("AUTHORIZED_FETCH no valid boolean");
+        let email_verification = EmailVerification::from_str(
+            &std::env::var("EMAIL_VERIFICATION").unwrap_or("optional".to_string()),
+        )
+        .expect("EMAIL_VERIFICATION must be optional, posting or login");
+        let admins: Vec<String> = std::env::var("ADMINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty())
+            .collect();
         let meilisearch_url =
             std::env::var("MEILISEARCH_URL").expect("MEILISEARCH_URL must be set");
         let meilisearch_key =
@@ -76,9 +110,9 @@ impl Config {
                 i32::from_str(&d).expect("STRIPE__PLATFORM_FEE_PERCENT not a valid number")
             });
         let mut stripe: Option<StripeData> = None;
-        if stripe_key.is_some() {
+        if let Some(key) = stripe_key {
             stripe = Some(StripeData {
-                key: stripe_key.expect("STRIPE__KEY must be set when Stripe is enabled"),
+                key,
                 webhook_key: stripe_webhook_key
                     .expect("STRIPE__WEBHOOK_KEY must be set when Stripe is enabled"),
                 platform_fee_percent: stripe_platform_fee_percent
@@ -89,6 +123,7 @@ impl Config {
         Config {
             database_url,
             jwt_secret,
+            key_encryption_key,
             public_url,
             base_domain,
             s3_base_url,
@@ -99,6 +134,9 @@ impl Config {
             meilisearch_url,
             meilisearch_key,
             registration_disabled,
+            authorized_fetch,
+            email_verification,
+            admins,
             smtp,
             stripe,
         }
@@ -4,6 +4,9 @@ use tracing::debug;
 
 use crate::db::instances::CreateInstance;
 use crate::db::instances::FullInstance;
+use crate::db::InstanceModeration;
+use crate::helpers::keys::KeyService;
+use crate::helpers::sign::get_signed_with_accept;
 
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
@@ -64,8 +67,12 @@ pub struct Users {
     pub active_month: Option<i32>,
 }
 
-async fn get_node_info_url(url: String) -> Result<Option<String>> {
-    let nodeinfo_wellknown = reqwest::get(url)
+async fn get_node_info_url(
+    url: String,
+    key_service: &KeyService,
+    pool: PgPool,
+) -> Result<Option<String>> {
+    let nodeinfo_wellknown = get_signed_with_accept(&url, "application/json", key_service, pool)
         .await?
         .json::<WellKnownNodeInfoResponse>()
         .await?;
@@ -78,21 +85,38 @@ async fn get_node_info_url(url: String) -> Result<Option<String>> {
     Ok(None)
 }
 
-pub async fn get_instance_by_base_url(base_url: &str, pool: PgPool) -> Result<FullInstance> {
+pub async fn get_instance_by_base_url(
+    base_url: &str,
+    key_service: &KeyService,
+    pool: PgPool,
+) -> Result<FullInstance> {
     // BetterErrorHandling
     let instance_db_res = FullInstance::get_by_base_url(base_url, pool.clone()).await;
     if let Ok(v) = instance_db_res {
+        if v.moderation == InstanceModeration::Suspended {
+            bail!("{} is suspended", base_url)
+        }
         return Ok(v);
     };
-    let nodeinfo_url = get_node_info_url(format!("{}/.well-known/nodeinfo", base_url)).await?;
+    let nodeinfo_url = get_node_info_url(
+        format!("{}/.well-known/nodeinfo", base_url),
+        key_service,
+        pool.clone(),
+    )
+    .await?;
     debug!("NodeInfo URL: {:?}", &nodeinfo_url);
     if nodeinfo_url.is_none() {
         bail!("NodeInfo Url could not be found")
     }
-    let node_info = reqwest::get(nodeinfo_url.unwrap())
-        .await?
-        .json::<NodeInfo>()
-        .await?;
+    let node_info = get_signed_with_accept(
+        &nodeinfo_url.unwrap(),
+        "application/json",
+        key_service,
+        pool.clone(),
+    )
+    .await?
+    .json::<NodeInfo>()
+    .await?;
     debug!("NodeInfo: {:?}", &node_info);
     if node_info.version != "2.0" {
         bail!("Manifest version wrong")
@@ -5,6 +5,7 @@ use futures::TryStreamExt;
 use lazy_static::lazy_static;
 use reqwest::header::HeaderValue;
 use s3::Bucket;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::{io, pin::Pin};
@@ -14,6 +15,8 @@ use tracing::debug;
 use uuid::Uuid;
 
 use crate::db::file::FullFile;
+use crate::db::instances::FullInstance;
+use crate::db::InstanceModeration;
 use crate::AppState;
 
 fn parse_content_disposition(header: &str) -> HashMap<String, String> {
@@ -107,9 +110,7 @@ pub async fn handle_single_attachment(
             .ok()
             .and_then(|v| parse_content_disposition(v).get("filename").cloned())
     });
-    let req_stream = res
-        .bytes_stream()
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+    let req_stream = res.bytes_stream().map_err(io::Error::other);
     let body_reader = StreamReader::new(req_stream);
     futures::pin_mut!(body_reader);
 
@@ -152,8 +153,38 @@ pub async fn handle_media(
     profile_id: Uuid,
     state: Arc<AppState>,
 ) -> anyhow::Result<()> {
+    match FullInstance::get_moderation_of_profile(&profile_id, state.pool.clone()).await? {
+        InstanceModeration::RejectMedia | InstanceModeration::Suspended => {
+            debug!(
+                "Not caching media of {} as its instance is moderated",
+                model_id
+            );
+            return Ok(());
+        }
+        _ => (),
+    }
     for url in attachment_urls {
         handle_single_attachment(url, profile_id, &model_id, state.clone()).await?;
     }
     Ok(())
 }
+
+pub fn get_attachment_urls(attachments: &[Value]) -> Vec<&str> {
+    //! Attachments are either plain links or objects with an `url`
+    attachments
+        .iter()
+        .filter_map(|v| match v.as_str() {
+            Some(d) => Some(d),
+            None => v["url"].as_str(),
+        })
+        .collect()
+}
+
+pub async fn delete_media_of_model(model_id: &Uuid, state: Arc<AppState>) -> anyhow::Result<()> {
+    for file in FullFile::get_many_files_by_model(model_id, state.pool.clone()).await? {
+        let d = state.s3.delete_object(format!("/{}", file.id)).await?;
+        debug!("S3 Response: {:?}", d);
+        file.delete(state.pool.clone()).await?;
+    }
+    Ok(())
+}
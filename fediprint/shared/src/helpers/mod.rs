@@ -1,6 +1,10 @@
 pub mod activities;
 pub mod config;
 pub mod instances;
+pub mod keys;
 pub mod media;
 pub mod profile;
+pub mod sign;
+pub mod tokens;
+pub mod totp;
 pub mod webfinger;
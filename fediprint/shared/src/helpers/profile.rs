@@ -1,45 +1,148 @@
 #[doc(inline)]
-use chrono::DateTime;
-use reqwest::Error;
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use sqlx::PgPool;
 use tracing::debug;
-use uuid::Uuid;
+use url::Url;
+use uuid::{uuid, Uuid};
 
 use crate::{
     db::profile::{ExtendedCreateProfile, FullProfile},
+    helpers::{instances::get_instance_by_base_url, keys::KeyService, sign::get_signed},
     models::activitypub::Profile,
 };
 
+lazy_static! {
+    static ref HANDLE_REGEX: Regex =
+        Regex::new(r"^@?(?<name>[^@]+)@(?<server>[^@]+\.[a-zA-Z0-9]{1,6})$").unwrap();
+    static ref MENTION_REGEX: Regex =
+        Regex::new(r"(?:^|[^\w/@])(?<handle>@[\w.\-]+@[\w\-]+(?:\.[\w\-]+)+)").unwrap();
+}
+
+pub fn find_mentions(content: &str) -> Vec<String> {
+    //! Collects all `@name@server` handles in a text, without duplicates
+    let mut handles: Vec<String> = vec![];
+    for caps in MENTION_REGEX.captures_iter(content) {
+        let handle = caps["handle"].to_string();
+        if !handles.contains(&handle) {
+            handles.push(handle);
+        }
+    }
+    handles
+}
+
+pub async fn fetch_actor(
+    url: &str,
+    key_service: &KeyService,
+    pool: PgPool,
+) -> anyhow::Result<Profile> {
+    //! Fetches a remote actor without storing anything
+    Ok(get_signed(url, key_service, pool)
+        .await?
+        .json::<Profile>()
+        .await?)
+}
+
+async fn get_instance_id(
+    server_id: &str,
+    key_service: &KeyService,
+    pool: PgPool,
+) -> anyhow::Result<Uuid> {
+    let url = Url::parse(server_id)?;
+    let host = match url.host_str() {
+        Some(d) => d,
+        None => bail!("Host is none"),
+    };
+    Ok(
+        get_instance_by_base_url(&format!("https://{host}"), key_service, pool)
+            .await?
+            .id,
+    )
+}
+
 impl FullProfile {
+    pub async fn get_by_handle(
+        handle: &str,
+        public_url: &str,
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<FullProfile> {
+        //! Resolves a `@name@server` handle to a local profile or a remote one via WebFinger
+        let Some(caps) = HANDLE_REGEX.captures(handle) else {
+            bail!("Invalid handle")
+        };
+        let name = &caps["name"];
+        let server = &caps["server"];
+        if Url::parse(public_url)?.host_str() == Some(server) {
+            return Ok(FullProfile::get_by_username_and_instance(
+                name,
+                &uuid!("00000000-0000-0000-0000-000000000000"),
+                pool,
+            )
+            .await?);
+        }
+        let instance =
+            get_instance_by_base_url(&format!("https://{server}"), key_service, pool.clone())
+                .await?;
+        FullProfile::get_by_name_and_instance_remote(
+            name,
+            handle.trim_start_matches('@'),
+            instance,
+            key_service,
+            pool,
+        )
+        .await
+    }
+
+    pub fn get_handle(&self) -> String {
+        let host = Url::parse(&self.server_id)
+            .ok()
+            .and_then(|v| v.host_str().map(|v| v.to_string()))
+            .unwrap_or_default();
+        format!("@{}@{}", self.username, host)
+    }
+
     pub async fn get_from_activitypub(
         url: &str,
         instance_id: Uuid,
+        key_service: &KeyService,
         pool: PgPool,
     ) -> anyhow::Result<FullProfile> {
         //! Fetches the Profile by URL and saves them in the db.
         //! This does not check if the user already exists!
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            "Accept",
-            reqwest::header::HeaderValue::from_str("application/activity+json").unwrap(),
-        );
-        let ap_client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
-            .unwrap();
-        let ap_profile_response = ap_client.get(url).send().await?.json::<Profile>().await?;
-        let follower_count = OrderedCollection::get_with_ap_headers(&ap_profile_response.followers)
-            .await?
-            .total_items;
-        let following_count =
-            OrderedCollection::get_with_ap_headers(&ap_profile_response.following)
-                .await?
-                .total_items;
-        let message_count = OrderedCollection::get_with_ap_headers(&ap_profile_response.outbox)
-            .await?
-            .total_items;
+        let ap_profile_response = fetch_actor(url, key_service, pool.clone()).await?;
+        FullProfile::create_from_activitypub(ap_profile_response, instance_id, key_service, pool)
+            .await
+    }
+
+    async fn create_from_activitypub(
+        ap_profile_response: Profile,
+        instance_id: Uuid,
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<FullProfile> {
+        let follower_count = OrderedCollection::get_total_items(
+            &ap_profile_response.followers,
+            key_service,
+            pool.clone(),
+        )
+        .await?;
+        let following_count = OrderedCollection::get_total_items(
+            &ap_profile_response.following,
+            key_service,
+            pool.clone(),
+        )
+        .await?;
+        let message_count = OrderedCollection::get_total_items(
+            &ap_profile_response.outbox,
+            key_service,
+            pool.clone(),
+        )
+        .await?;
         debug!("{:?}", ap_profile_response);
         Ok(ExtendedCreateProfile {
             id: Uuid::now_v7(),
@@ -53,14 +156,99 @@ impl FullProfile {
             following_count,
             message_count,
             public_key: ap_profile_response.public_key.public_key_pem,
-            registered_at: DateTime::from(chrono::DateTime::parse_from_rfc3339(
-                &ap_profile_response.published,
-            )?),
+            // Instance actors don't carry a publishing date
+            registered_at: match ap_profile_response.published.is_empty() {
+                true => Utc::now(),
+                false => DateTime::from(chrono::DateTime::parse_from_rfc3339(
+                    &ap_profile_response.published,
+                )?),
+            },
             instance: instance_id,
+            shared_inbox: Some(ap_profile_response.endpoints.shared_inbox)
+                .filter(|d| !d.is_empty()),
+            also_known_as: ap_profile_response.also_known_as,
+            moved_to: ap_profile_response.moved_to,
         }
         .create(pool.clone())
         .await?)
     }
+
+    pub async fn get_by_server_id_or_fetch(
+        server_id: &str,
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<FullProfile> {
+        //! Looks up the profile by its server id and fetches it, including its instance, if it is unknown.
+        if let Ok(d) = FullProfile::get_by_server_id(server_id, pool.clone()).await {
+            return Ok(d);
+        }
+        let instance_id = get_instance_id(server_id, key_service, pool.clone()).await?;
+        FullProfile::get_from_activitypub(server_id, instance_id, key_service, pool).await
+    }
+
+    pub async fn create_from_fetched_actor(
+        actor: Profile,
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<FullProfile> {
+        //! Stores an actor fetched with `fetch_actor`, including its instance if it is unknown.
+        //! Used once the actor was checked, e.g. after its key verified a signature.
+        let instance_id = get_instance_id(&actor.id, key_service, pool.clone()).await?;
+        FullProfile::create_from_activitypub(actor, instance_id, key_service, pool).await
+    }
+
+    pub async fn refresh_public_key(
+        self,
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<FullProfile> {
+        //! Fetches the current public key of the remote profile and caches it in the db.
+        let ap_profile_response = fetch_actor(&self.server_id, key_service, pool.clone()).await?;
+        if ap_profile_response.public_key.public_key_pem == self.public_key {
+            return Ok(self);
+        }
+        debug!("Public key of {} changed", &self.server_id);
+        Ok(FullProfile::update_public_key(
+            &self.id,
+            &ap_profile_response.public_key.public_key_pem,
+            pool,
+        )
+        .await?)
+    }
+
+    pub async fn refresh_aliases(
+        self,
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<FullProfile> {
+        //! Fetches the current `alsoKnownAs` and `movedTo` of the remote profile, as both have to be
+        //! up to date when a `Move` is verified. Local profiles are returned as they are.
+        if self.instance == uuid!("00000000-0000-0000-0000-000000000000") {
+            return Ok(self);
+        }
+        let ap_profile_response = get_signed(&self.server_id, key_service, pool.clone())
+            .await?
+            .json::<Profile>()
+            .await?;
+        let profile = match ap_profile_response.also_known_as == self.also_known_as {
+            true => self,
+            false => {
+                FullProfile::set_also_known_as(
+                    &self.id,
+                    &ap_profile_response.also_known_as,
+                    pool.clone(),
+                )
+                .await?
+            }
+        };
+        if ap_profile_response.moved_to == profile.moved_to {
+            return Ok(profile);
+        }
+        Ok(
+            FullProfile::set_moved_to(&profile.id, ap_profile_response.moved_to.as_deref(), pool)
+                .await?,
+        )
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -76,21 +264,19 @@ pub struct OrderedCollection {
 }
 
 impl OrderedCollection {
-    async fn get_with_ap_headers(url: &str) -> Result<OrderedCollection, Error> {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            "Accept",
-            reqwest::header::HeaderValue::from_str("application/activity+json").unwrap(),
-        );
-        let ap_client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
-            .unwrap();
-        ap_client
-            .get(url)
-            .send()
+    async fn get_total_items(
+        url: &str,
+        key_service: &KeyService,
+        pool: PgPool,
+    ) -> anyhow::Result<i64> {
+        //! Actors without the collection, like instance actors, count as empty
+        if url.is_empty() {
+            return Ok(0);
+        }
+        Ok(get_signed(url, key_service, pool)
             .await?
             .json::<OrderedCollection>()
-            .await
+            .await?
+            .total_items)
     }
 }
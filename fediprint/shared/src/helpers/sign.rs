@@ -0,0 +1,153 @@
+use anyhow::bail;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sha;
+use openssl::sign::Signer;
+use sqlx::PgPool;
+use url::Url;
+use uuid::uuid;
+
+use crate::helpers::keys::KeyService;
+
+pub fn sign_data_to_base64(key: PKey<Private>, data: &[u8]) -> anyhow::Result<String> {
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+    let signature_vec = signer.sign_to_vec()?;
+    Ok(general_purpose::STANDARD.encode(signature_vec))
+}
+
+fn get_now_in_format() -> String {
+    // HTTP dates are always in GMT
+    Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string() // Wed, 18 Dec 2019 10:08:46 GMT
+}
+
+fn get_host_and_path(url: &str) -> anyhow::Result<(String, String)> {
+    let url = Url::parse(url)?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(d), Some(port)) => format!("{d}:{port}"),
+        (Some(d), None) => d.to_string(),
+        (None, _) => bail!("Host is none"),
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+    Ok((host, path))
+}
+
+pub fn sign_get_request_by_details(
+    path: &str,
+    host: &str,
+    date: &str,
+    key: PKey<Private>,
+    key_id: String,
+) -> anyhow::Result<String> {
+    // https://docs.joinmastodon.org/spec/security/#http-sign
+    let headers = "(request-target) host date";
+    let data_to_sign = format!("(request-target): get {path}\nhost: {host}\ndate: {date}");
+    let signature = sign_data_to_base64(key, data_to_sign.as_ref())?;
+    Ok(format!(
+        "keyId=\"{key_id}\",algorithm=\"rsa-sha256\",headers=\"{headers}\",signature=\"{signature}\""
+    ))
+}
+
+pub fn sign_get_request_by_url(
+    url: String,
+    key: PKey<Private>,
+    key_id: String,
+) -> anyhow::Result<(String, String)> {
+    // (Signature, Date) -header returns
+    let (host, path) = get_host_and_path(&url)?;
+    let now = get_now_in_format();
+    Ok((
+        sign_get_request_by_details(&path, &host, &now, key, key_id)?,
+        now,
+    ))
+}
+
+pub fn sign_post_request_with_hash(
+    path: &str,
+    host: &str,
+    date: &str,
+    body_hash: &str,
+    key: PKey<Private>,
+    key_id: String,
+) -> anyhow::Result<String> {
+    let headers = "(request-target) host date digest";
+    let data_to_sign = format!(
+        "(request-target): post {path}\nhost: {host}\ndate: {date}\ndigest: sha-256={body_hash}"
+    );
+    let signature = sign_data_to_base64(key, data_to_sign.as_ref())?;
+    Ok(format!(
+        "keyId=\"{key_id}\",algorithm=\"rsa-sha256\",headers=\"{headers}\",signature=\"{signature}\""
+    ))
+}
+
+pub fn sign_post_request_with_body(
+    url: &str,
+    body: &[u8],
+    key: PKey<Private>,
+    key_id: String,
+) -> anyhow::Result<(String, String, String)> {
+    // (Signature, Digest, Date) -header returns
+    let mut hasher = sha::Sha256::new();
+    hasher.update(body);
+    let hash = general_purpose::STANDARD.encode(hasher.finish());
+    let (host, path) = get_host_and_path(url)?;
+    let now = get_now_in_format();
+    Ok((
+        sign_post_request_with_hash(&path, &host, &now, &hash, key, key_id)?,
+        format!("sha-256={hash}"),
+        now,
+    ))
+}
+
+pub async fn post_signed(
+    url: &str,
+    body: String,
+    key: PKey<Private>,
+    key_id: String,
+) -> anyhow::Result<reqwest::Response> {
+    //! Sends an ActivityPub POST with Date, Digest and Signature headers set
+    let (signature, digest, date) = sign_post_request_with_body(url, body.as_ref(), key, key_id)?;
+    Ok(reqwest::Client::new()
+        .post(url)
+        .header("Content-Type", "application/activity+json")
+        .header("Accept", "application/activity+json")
+        .header("Date", date)
+        .header("Digest", digest)
+        .header("Signature", signature)
+        .body(body)
+        .send()
+        .await?)
+}
+
+pub async fn get_signed(
+    url: &str,
+    key_service: &KeyService,
+    pool: PgPool,
+) -> anyhow::Result<reqwest::Response> {
+    //! Sends an ActivityPub GET signed by the instance actor, so servers running authorized fetch answer it
+    get_signed_with_accept(url, "application/activity+json", key_service, pool).await
+}
+
+pub async fn get_signed_with_accept(
+    url: &str,
+    accept: &str,
+    key_service: &KeyService,
+    pool: PgPool,
+) -> anyhow::Result<reqwest::Response> {
+    let (key, key_id) = key_service
+        .get_signing_key(&uuid!("00000000-0000-0000-0000-000000000000"), pool)
+        .await?;
+    let (signature, date) = sign_get_request_by_url(url.to_string(), key, key_id)?;
+    Ok(reqwest::Client::new()
+        .get(url)
+        .header("Accept", accept)
+        .header("Date", date)
+        .header("Signature", signature)
+        .send()
+        .await?)
+}
@@ -0,0 +1,118 @@
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{TimeDelta, Utc};
+use openssl::hash::{hash, MessageDigest};
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use uuid::Uuid;
+
+fn get_mac(payload: &str, secret: &str) -> anyhow::Result<Vec<u8>> {
+    let key = PKey::hmac(secret.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(payload.as_bytes())?;
+    Ok(signer.sign_to_vec()?)
+}
+
+pub fn sign_token(payload: &str, secret: &str) -> anyhow::Result<String> {
+    //! Appends an HMAC of the payload, so tokens sent by email can't be forged
+    let mac = get_mac(payload, secret)?;
+    Ok(format!(
+        "{payload}.{}",
+        general_purpose::URL_SAFE_NO_PAD.encode(mac)
+    ))
+}
+
+pub fn read_token(token: &str, secret: &str) -> Option<String> {
+    //! Returns the payload if the token was signed with the secret
+    let (payload, mac) = token.rsplit_once('.')?;
+    let mac = general_purpose::URL_SAFE_NO_PAD.decode(mac).ok()?;
+    let expected = get_mac(payload, secret).ok()?;
+    if mac.len() != expected.len() || !memcmp::eq(&mac, &expected) {
+        return None;
+    }
+    Some(payload.to_string())
+}
+
+pub fn create_verification_token(
+    account_id: &Uuid,
+    code: &str,
+    secret: &str,
+) -> anyhow::Result<String> {
+    //! The token for the link in the registration email, valid for two days.
+    //! It carries the code stored in `account.verified`, which is cleared once the email is verified.
+    let expires_at = (Utc::now() + TimeDelta::try_days(2).unwrap()).timestamp();
+    sign_token(&format!("{account_id}:{code}:{expires_at}"), secret)
+}
+
+pub fn read_verification_token(token: &str, secret: &str) -> Option<(Uuid, String)> {
+    //! Returns the account id and the verification code of a valid, unexpired token
+    let payload = read_token(token, secret)?;
+    let mut parts = payload.splitn(3, ':');
+    let account_id = Uuid::parse_str(parts.next()?).ok()?;
+    let code = parts.next()?.to_string();
+    let expires_at: i64 = parts.next()?.parse().ok()?;
+    if expires_at < Utc::now().timestamp() {
+        return None;
+    }
+    Some((account_id, code))
+}
+
+pub fn hash_token(token: &str) -> anyhow::Result<String> {
+    //! One-time tokens and recovery codes are only stored hashed. Both carry at least 128 random
+    //! bits, which is what makes a plain SHA-256 enough. Anything guessable needs argon2 instead.
+    let digest = hash(MessageDigest::sha256(), token.as_bytes())?;
+    Ok(general_purpose::URL_SAFE_NO_PAD.encode(digest))
+}
+
+pub fn generate_one_time_token() -> anyhow::Result<(String, String)> {
+    //! Returns a random token and its hash
+    let mut buf = [0; 32];
+    rand_bytes(&mut buf)?;
+    let token = general_purpose::URL_SAFE_NO_PAD.encode(buf);
+    let token_hash = hash_token(&token)?;
+    Ok((token, token_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::uuid;
+
+    const SECRET: &str = "secret";
+
+    #[test]
+    fn test_read_token() {
+        let token = sign_token("payload", SECRET).unwrap();
+        assert_eq!(read_token(&token, SECRET), Some("payload".to_string()));
+        assert_eq!(read_token(&token, "other secret"), None);
+        let forged = token.replacen("payload", "pay1oad", 1);
+        assert_eq!(read_token(&forged, SECRET), None);
+        assert_eq!(read_token("payload", SECRET), None);
+        assert_eq!(read_token("payload.not base64", SECRET), None);
+    }
+
+    #[test]
+    fn test_read_verification_token() {
+        let account_id = uuid!("018e7b20-51e5-79c2-878e-02d01f941165");
+        let token = create_verification_token(&account_id, "code", SECRET).unwrap();
+        assert_eq!(
+            read_verification_token(&token, SECRET),
+            Some((account_id, "code".to_string()))
+        );
+        assert_eq!(read_verification_token(&token, "other secret"), None);
+        let expired = (Utc::now() - TimeDelta::try_minutes(1).unwrap()).timestamp();
+        let token = sign_token(&format!("{account_id}:code:{expired}"), SECRET).unwrap();
+        assert_eq!(read_verification_token(&token, SECRET), None);
+        let token = sign_token(&format!("{account_id}:code"), SECRET).unwrap();
+        assert_eq!(read_verification_token(&token, SECRET), None);
+    }
+
+    #[test]
+    fn test_generate_one_time_token() {
+        let (token, token_hash) = generate_one_time_token().unwrap();
+        assert_eq!(hash_token(&token).unwrap(), token_hash);
+        assert_ne!(token, token_hash);
+        assert_ne!(generate_one_time_token().unwrap().0, token);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::db::two_factor::{FullRecoveryCode, FullTwoFactor};
+use crate::helpers::keys::KeyService;
+use crate::helpers::tokens::hash_token;
+use chrono::Utc;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sqlx::PgPool;
+
+// RFC 6238 defaults, the only parameters most authenticator apps support
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const RECOVERY_CODE_COUNT: usize = 10;
+// Wrong codes in a row before the second factor is locked for a while
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+const LOCK_MINUTES: i32 = 15;
+const LABEL: &AsciiSet = &NON_ALPHANUMERIC.remove(b'.').remove(b'-').remove(b'_');
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let bits = u64::from_be_bytes([0, 0, 0, buf[0], buf[1], buf[2], buf[3], buf[4]]);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            let index = (bits >> (35 - i * 5)) & 0x1f;
+            out.push(BASE32_ALPHABET[index as usize] as char);
+        }
+    }
+    out
+}
+
+fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in data.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+fn get_code(key: &[u8], step: i64, digits: u32) -> anyhow::Result<String> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(&step.to_be_bytes())?;
+    let mac = signer.sign_to_vec()?;
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        mac[offset] & 0x7f,
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]);
+    Ok(format!(
+        "{:0width$}",
+        value % 10u32.pow(digits),
+        width = digits as usize
+    ))
+}
+
+pub fn generate_secret() -> anyhow::Result<String> {
+    //! A random 160 bit secret as base32, the format authenticator apps expect
+    let mut buf = [0; 20];
+    rand_bytes(&mut buf)?;
+    Ok(base32_encode(&buf))
+}
+
+pub fn get_provisioning_uri(secret: &str, account_name: &str, issuer: &str) -> String {
+    //! The `otpauth://` URI, shown as QR code to enrol an authenticator app
+    let issuer = utf8_percent_encode(issuer, LABEL).to_string();
+    let account_name = utf8_percent_encode(account_name, LABEL);
+    format!(
+        "otpauth://totp/{issuer}:{account_name}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}"
+    )
+}
+
+pub fn check_code(secret: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    //! Returns the time step the code belongs to. The neighbouring steps are accepted as well,
+    //! as clocks drift and typing the code takes a while. Steps up to `last_step` were used already.
+    check_code_at(secret, code, last_step, Utc::now().timestamp())
+}
+
+fn check_code_at(secret: &str, code: &str, last_step: Option<i64>, now: i64) -> Option<i64> {
+    let key = base32_decode(secret)?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let now = now / STEP_SECONDS;
+    (now - 1..=now + 1)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| {
+            get_code(&key, *step, DIGITS)
+                .map(|expected| openssl::memcmp::eq(expected.as_bytes(), code.as_bytes()))
+                .unwrap_or(false)
+        })
+}
+
+pub fn normalize_recovery_code(code: &str) -> String {
+    //! Recovery codes are shown grouped and in lower case, but typed in any way
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+pub fn generate_recovery_codes() -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    //! Returns the recovery codes to show once and their hashes to store
+    let mut codes = vec![];
+    let mut hashes = vec![];
+    for _ in 0..RECOVERY_CODE_COUNT {
+        // 160 bits, so the codes can't be guessed from their unsalted hashes
+        let mut buf = [0; 20];
+        rand_bytes(&mut buf)?;
+        let code = base32_encode(&buf).to_ascii_lowercase();
+        hashes.push(hash_token(&code)?);
+        let groups: Vec<&str> = (0..code.len())
+            .step_by(4)
+            .map(|i| &code[i..i + 4])
+            .collect();
+        codes.push(groups.join("-"));
+    }
+    Ok((codes, hashes))
+}
+
+pub async fn verify_second_factor(
+    key_service: &KeyService,
+    two_factor: &FullTwoFactor,
+    code: &str,
+    allow_recovery_code: bool,
+    pool: PgPool,
+) -> anyhow::Result<bool> {
+    //! Checks a TOTP code, which is used up afterwards, or, if allowed, an unused recovery code.
+    //! Wrong codes are counted, too many of them lock the second factor for a while.
+    if two_factor.is_locked() {
+        return Ok(false);
+    }
+    let secret = key_service.decrypt_secret(&two_factor.encrypted_secret)?;
+    if let Some(step) = check_code(&secret, code, two_factor.last_step) {
+        if FullTwoFactor::use_step(&two_factor.account_id, step, pool.clone()).await? {
+            return Ok(true);
+        }
+    } else if allow_recovery_code {
+        let code_hash = hash_token(&normalize_recovery_code(code))?;
+        if FullRecoveryCode::redeem(&two_factor.account_id, &code_hash, pool.clone()).await? {
+            return Ok(true);
+        }
+    }
+    FullTwoFactor::record_failure(
+        &two_factor.account_id,
+        MAX_FAILED_ATTEMPTS,
+        LOCK_MINUTES,
+        pool,
+    )
+    .await?;
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_hotp_rfc4226_vectors() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(get_code(RFC_KEY, counter as i64, 6).unwrap(), *code);
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238_sha1_vectors() {
+        let expected = [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ];
+        for (time, code) in expected {
+            assert_eq!(get_code(RFC_KEY, time / STEP_SECONDS, 8).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_base32_round_trip() {
+        assert_eq!(base32_encode(RFC_KEY), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        for len in 0..=21 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            assert_eq!(base32_decode(&base32_encode(&data)).unwrap(), data);
+        }
+        assert_eq!(
+            base32_decode("gezdgnbvgy3tqojqgezdgnbvgy3tqojq").unwrap(),
+            RFC_KEY
+        );
+        assert!(base32_decode("not base32!").is_none());
+    }
+
+    #[test]
+    fn test_check_code_window() {
+        let secret = base32_encode(RFC_KEY);
+        let now = 1234567890;
+        let step = now / STEP_SECONDS;
+        for offset in -1..=1 {
+            let code = get_code(RFC_KEY, step + offset, DIGITS).unwrap();
+            assert_eq!(
+                check_code_at(&secret, &code, None, now),
+                Some(step + offset)
+            );
+        }
+        for offset in [-2, 2] {
+            let code = get_code(RFC_KEY, step + offset, DIGITS).unwrap();
+            assert_eq!(check_code_at(&secret, &code, None, now), None);
+        }
+        assert_eq!(check_code_at(&secret, "12345", None, now), None);
+    }
+
+    #[test]
+    fn test_check_code_rejects_used_step() {
+        let secret = base32_encode(RFC_KEY);
+        let now = 1234567890;
+        let step = now / STEP_SECONDS;
+        let code = get_code(RFC_KEY, step, DIGITS).unwrap();
+        assert_eq!(
+            check_code_at(&secret, &code, Some(step - 1), now),
+            Some(step)
+        );
+        assert_eq!(check_code_at(&secret, &code, Some(step), now), None);
+        let earlier = get_code(RFC_KEY, step - 1, DIGITS).unwrap();
+        assert_eq!(check_code_at(&secret, &earlier, Some(step), now), None);
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let (codes, hashes) = generate_recovery_codes().unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for (code, hash) in codes.iter().zip(hashes) {
+            let normalized = normalize_recovery_code(&code.to_ascii_uppercase());
+            assert_eq!(base32_decode(&normalized).unwrap().len(), 20);
+            assert_eq!(hash_token(&normalized).unwrap(), hash);
+        }
+    }
+}
@@ -1,6 +1,10 @@
+use crate::db::boosts::FullBoost;
 use crate::db::file::FullFile;
-use crate::db::profile::UsernameAndServerId;
-use crate::db::ModelLicense;
+use crate::db::likes::FullLike;
+use crate::db::mentions::FullMention;
+use crate::db::profile::{FullProfile, UsernameAndServerId};
+use crate::db::visibility::Viewer;
+use crate::db::{EventAudience, ModelLicense};
 use chrono::{DateTime, Utc};
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
@@ -16,30 +20,134 @@ pub struct Profile {
     // pub attachment: Vec<Attachment>,
     // pub devices: String,
     // pub discoverable: bool,
+    #[serde(default)]
     pub endpoints: Endpoints,
     // pub featured: String,
     // pub featured_tags: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_known_as: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
+    // Instance actors may leave out the collections, name, publishing date and url
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub followers: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub following: String,
     // pub icon: Icon,
     pub id: String,
     pub inbox: String,
     // pub indexable: bool,
-    // pub manually_approves_followers: bool,
+    #[serde(default)]
+    pub manually_approves_followers: bool,
     // pub memorial: bool,
+    #[serde(default)]
     pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub outbox: String,
     pub preferred_username: String,
     pub public_key: PublicKey,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub published: String,
     // creation of account (Date)
     // pub summary: String,
     // pub tag: Vec<Tag>,
     #[serde(rename = "type")]
     pub type_field: String,
+    #[serde(default)]
     pub url: String,
 }
 
+impl Profile {
+    pub fn from_local(user: FullProfile, public_url: &str) -> Profile {
+        //! The actor of a local profile, served on its id and sent along with `Update`s
+        Profile {
+            context: (
+                "https://www.w3.org/ns/activitystreams".to_string(),
+                "https://w3id.org/security/v1".to_string(),
+                Context {
+                    curve25519key: Some("toot:Curve25519Key".to_string()),
+                    // device: "toot:Device".to_string(),
+                    ed25519key: Some("toot:Ed25519Key".to_string()),
+                    ed25519signature: Some("toot:Ed25519Signature".to_string()),
+                    encrypted_message: Some("toot:EncryptedMessage".to_string()),
+                    hashtag: Some("as:Hashtag".to_string()),
+                    property_value: Some("schema:PropertyValue".to_string()),
+                    also_known_as: AlsoKnownAs {
+                        id: "as:alsoKnownAs".to_string(),
+                        type_field: "@id".to_string(),
+                    },
+                    cipher_text: Some("toot:cipherText".to_string()),
+                    claim: Some(Claim {
+                        id: "toot:claim".to_string(),
+                        type_field: "@id".to_string(),
+                    }),
+                    // device_id: "toot:deviceId".to_string(),
+                    // devices: Devices{
+                    //     id: "toot:devices".to_string(),
+                    //     type_field: "@id".to_string()
+                    // },
+                    // discoverable: "toot:discoverable".to_string(),
+                    // featured: Featured {
+                    //     id: "toot:featured".to_string(),
+                    //     type_field: "@id".to_string()
+                    // },
+                    // featured_tags: FeaturedTags {
+                    //     id: "toot:featuredTags".to_string(),
+                    //     type_field: "@id".to_string()
+                    // },
+                    fingerprint_key: Some(FingerprintKey {
+                        id: "toot:fingerprintKey".to_string(),
+                        type_field: "@id".to_string(),
+                    }),
+                    // focal_point: FocalPoint {
+                    //     container: "@list".to_string(),
+                    //     id: "toot:focalPoint".to_string(),
+                    // },
+                    identity_key: Some(IdentityKey {
+                        id: "toot:identityKey".to_string(),
+                        type_field: "@id".to_string(),
+                    }),
+                    // indexable: "toot:indexable".to_string(),
+                    manually_approves_followers: Some("as:manuallyApprovesFollowers".to_string()),
+                    // memorial: "toot:memorial".to_string(),
+                    message_franking: Some("toot:messageFranking".to_string()),
+                    message_type: Some("toot:messageType".to_string()),
+                    moved_to: Some(MovedTo {
+                        id: "as:movedTo".to_string(),
+                        type_field: "@id".to_string(),
+                    }),
+                    public_key_base64: Some("toot:publicKeyBase64".to_string()),
+                    schema: Some("http://schema.org#".to_string()),
+                    // suspended: "toot:suspended".to_string(),
+                    toot: "http://joinmastodon.org/ns#".to_string(),
+                    value: Some("schema:value".to_string()),
+                },
+            ),
+            endpoints: Endpoints {
+                shared_inbox: format!("{}/api/v1/inbox", public_url),
+            },
+            also_known_as: user.also_known_as,
+            moved_to: user.moved_to,
+            followers: format!("{}/api/v1/user/{}/followers", public_url, user.username),
+            following: format!("{}/api/v1/user/{}/following", public_url, user.username),
+            id: format!("{}/api/v1/user/{}", public_url, user.username),
+            inbox: format!("{}/api/v1/user/{}/inbox", public_url, user.username),
+            manually_approves_followers: user.manually_approves_followers,
+            name: user.username.clone(),
+            outbox: format!("{}/api/v1/user/{}/outbox", public_url, user.username),
+            preferred_username: user.display_name,
+            public_key: PublicKey {
+                id: format!("{}/api/v1/user/{}#main-key", public_url, user.username),
+                owner: format!("{}/api/v1/user/{}", public_url, user.username),
+                public_key_pem: user.public_key,
+            },
+            published: user.registered_at.to_rfc3339(),
+            type_field: "Person".to_string(),
+            url: format!("{}/@{}", public_url, user.username),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Context {
@@ -69,11 +177,11 @@ pub struct Context {
     // pub focal_point: FocalPoint,
     pub identity_key: Option<IdentityKey>,
     // pub indexable: String,
-    // pub manually_approves_followers: String,
+    pub manually_approves_followers: Option<String>,
     // pub memorial: String,
     pub message_franking: Option<String>,
     pub message_type: Option<String>,
-    // pub moved_to: MovedTo,
+    pub moved_to: Option<MovedTo>,
     pub public_key_base64: Option<String>,
     pub schema: Option<String>,
     // pub suspended: String,
@@ -175,9 +283,32 @@ pub struct Attachment {
     pub url: String,
 }
 
+impl Attachment {
+    pub async fn get_by_model_id(
+        model_id: &Uuid,
+        public_url: &str,
+        pool: PgPool,
+    ) -> Result<Vec<Attachment>, Error> {
+        let files = FullFile::get_many_files_by_model(model_id, pool).await?;
+        Ok(files
+            .iter()
+            .map(|v| Attachment {
+                blurhash: v.thumbhash.clone(),
+                height: None,
+                width: None,
+                media_type: v.mime_type.clone(),
+                url: format!("{public_url}/api/v1/storage/download/{}", v.id),
+                name: v.description.clone().unwrap_or_default(),
+                type_field: "Document".to_string(),
+            })
+            .collect())
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Endpoints {
+    #[serde(default)]
     pub shared_inbox: String,
 }
 
@@ -201,6 +332,7 @@ pub struct PublicKey {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Tag {
+    #[serde(default)]
     pub href: String,
     pub name: String,
     #[serde(rename = "type")]
@@ -218,6 +350,13 @@ impl Tag {
     pub fn from_strs(tags: Vec<String>, base_url: &str) -> Vec<Tag> {
         tags.iter().map(|h| Tag::from_str(h, base_url)).collect()
     }
+    pub fn from_profile(profile: &FullProfile) -> Tag {
+        Tag {
+            name: profile.get_handle(),
+            href: profile.server_id.clone(),
+            type_field: String::from("Mention"),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -234,6 +373,20 @@ pub struct OrderedCollection {
     pub type_field: String,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionPage {
+    #[serde(rename = "@context")]
+    pub context: String,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    pub items: Vec<String>,
+    pub part_of: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PeopleDataPage {
@@ -260,14 +413,32 @@ pub struct FollowRequest {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    #[serde(rename = "@context")]
+    pub context: String,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub actor: String,
+    pub object: Value,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct OutboxDataPage {
     #[serde(rename = "@context")]
     pub context: (String, OutboxContext),
     pub id: String,
     #[serde(rename = "type")]
     pub type_field: String,
-    pub next: String,
-    pub prev: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
     pub part_of: String,
     pub ordered_items: Vec<OrderedItem>,
 }
@@ -374,64 +545,112 @@ pub struct NoteJoinedModel {
     pub updated_at: DateTime<Utc>,
     pub first_reply_server_id: Option<String>,
     pub title: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub audience: EventAudience,
 }
 
 impl NoteJoinedModel {
-    pub async fn get_by_profile_id(id: &Uuid, pool: PgPool) -> Result<Vec<NoteJoinedModel>, Error> {
+    pub async fn get_page_by_profile_id(
+        id: &Uuid,
+        viewer: &Viewer,
+        max_id: Option<Uuid>,
+        min_id: Option<Uuid>,
+        limit: i64,
+        pool: PgPool,
+    ) -> Result<Vec<NoteJoinedModel>, Error> {
+        //! Notes and models visible to the viewer, newest first. `max_id` pages to older items and
+        //! `min_id` to the items directly newer than the given id (uuid v7 ids sort by time).
         // Type overrides necessary, as sqlx wants everything to be Option<> in Rust which just isn't
         // true in this case. Let's see when I'll have to fix this query.
         // https://github.com/launchbadge/sqlx/issues/1266
-        sqlx::query_as!(
+        let mut items = sqlx::query_as!(
             NoteJoinedModel,
             r#"
-SELECT p.server_id  AS "profile_server_id!: String",
-       p.id         AS "profile_id!: Uuid",
-       n.id         AS note_id,
-       NULL         AS model_id,
-       n.hashtags   AS "hashtags!: Vec<String>",
-       n.content    AS "content!: String",
-       NULL         AS summary,
-       n.server_id  AS server_id,
-       NULL         AS "license!: Option<ModelLicense>",
-       n.created_at AS "created_at!: DateTime<Utc>",
-       n.updated_at AS "updated_at!: DateTime<Utc>",
-       r.server_id  AS "first_reply_server_id!: Option<String>",
-       NULL         AS "title"
-FROM profile AS p
-         LEFT JOIN note AS n ON p.id = n.actor_id
-         LEFT JOIN note AS r ON n.id = r.in_reply_to_note_id
-WHERE p.id = $1
-  AND n.id IS NOT NULL
-  AND n.audience = 'PUBLIC'
-
-UNION ALL
-SELECT p.server_id   AS "profile_server_id!: String",
-       p.id          AS "profile_id!: Uuid",
-       NULL          AS note_id,
-       m.id          AS model_id,
-       m.tags        AS "hashtags!: Vec<String>",
-       m.description AS "content!: String",
-       m.summary     AS summary,
-       m.server_id   AS server_id,
-       m.license     AS "license!: Option<ModelLicense>",
-       m.created_at  AS "created_at!: DateTime<Utc>",
-       m.updated_at  AS "updated_at!: DateTime<Utc>",
-       r.server_id   AS "first_reply_server_id!: Option<String>",
-       m.title          AS "title"
-FROM profile AS p
-         LEFT JOIN model AS m ON p.id = m.profile_id
-         LEFT JOIN note AS r ON m.id = r.in_reply_to_model_id
-         LEFT JOIN file AS f ON f.image_for_model_id = m.id
-WHERE p.id = $1
-  AND m.id IS NOT NULL
-ORDER BY "created_at!: DateTime<Utc>"
-LIMIT 15;
+SELECT *
+FROM (SELECT p.server_id  AS "profile_server_id!: String",
+             p.id         AS "profile_id!: Uuid",
+             n.id         AS note_id,
+             NULL::uuid   AS model_id,
+             n.hashtags   AS "hashtags!: Vec<String>",
+             n.content    AS "content!: String",
+             NULL         AS summary,
+             n.server_id  AS server_id,
+             NULL         AS "license!: Option<ModelLicense>",
+             n.created_at AS "created_at!: DateTime<Utc>",
+             n.updated_at AS "updated_at!: DateTime<Utc>",
+             (SELECT r.server_id FROM note AS r WHERE r.in_reply_to_note_id = n.id OR r.in_reply_to_comment_id = n.id ORDER BY r.id LIMIT 1)
+                          AS "first_reply_server_id!: Option<String>",
+             NULL         AS "title",
+             COALESCE(rn.server_id, rc.server_id, rm.server_id)
+                          AS "in_reply_to!: Option<String>",
+             n.audience   AS "audience!: EventAudience"
+      FROM profile AS p
+               JOIN note AS n ON p.id = n.actor_id
+               LEFT JOIN note AS rn ON rn.id = n.in_reply_to_note_id
+               LEFT JOIN note AS rc ON rc.id = n.in_reply_to_comment_id
+               LEFT JOIN model AS rm ON rm.id = n.in_reply_to_model_id
+      WHERE p.id = $1
+        AND can_view_note(n.id, $5)
+
+      UNION ALL
+      SELECT p.server_id   AS "profile_server_id!: String",
+             p.id          AS "profile_id!: Uuid",
+             NULL::uuid    AS note_id,
+             m.id          AS model_id,
+             m.tags        AS "hashtags!: Vec<String>",
+             m.description AS "content!: String",
+             m.summary     AS summary,
+             m.server_id   AS server_id,
+             m.license     AS "license!: Option<ModelLicense>",
+             m.created_at  AS "created_at!: DateTime<Utc>",
+             m.updated_at  AS "updated_at!: DateTime<Utc>",
+             (SELECT r.server_id FROM note AS r WHERE r.in_reply_to_model_id = m.id ORDER BY r.id LIMIT 1)
+                           AS "first_reply_server_id!: Option<String>",
+             m.title       AS "title",
+             NULL          AS "in_reply_to!: Option<String>",
+             (CASE WHEN m.published THEN 'PUBLIC' ELSE 'NOBODY' END)::event_audience
+                           AS "audience!: EventAudience"
+      FROM profile AS p
+               JOIN model AS m ON p.id = m.profile_id
+      WHERE p.id = $1
+        AND can_view_model(m.id, $5)) AS s
+WHERE ($2::uuid IS NULL OR COALESCE(s.note_id, s.model_id) < $2)
+  AND ($3::uuid IS NULL OR COALESCE(s.note_id, s.model_id) > $3)
+ORDER BY CASE WHEN $3::uuid IS NULL THEN COALESCE(s.note_id, s.model_id) END DESC,
+         COALESCE(s.note_id, s.model_id)
+LIMIT $4;
        "#,
-            id
+            id,
+            max_id,
+            min_id,
+            limit,
+            viewer.profile_id
         )
         .fetch_all(&pool)
+        .await?;
+        if min_id.is_some() {
+            items.reverse();
+        }
+        Ok(items)
+    }
+
+    pub async fn count_by_profile_id(
+        id: &Uuid,
+        viewer: &Viewer,
+        pool: PgPool,
+    ) -> Result<i64, Error> {
+        sqlx::query_scalar!(
+            r#"
+SELECT (SELECT COUNT(*) FROM note WHERE actor_id = $1 AND can_view_note(id, $2))
+     + (SELECT COUNT(*) FROM model WHERE profile_id = $1 AND can_view_model(id, $2)) AS "count!"
+       "#,
+            id,
+            viewer.profile_id
+        )
+        .fetch_one(&pool)
         .await
     }
+
     pub async fn get_by_id(id: &Uuid, pool: PgPool) -> Result<NoteJoinedModel, Error> {
         // Type overrides necessary, as sqlx wants everything to be Option<> in Rust which just isn't
         // true in this case. Let's see when I'll have to fix this query.
@@ -450,11 +669,17 @@ SELECT p.server_id  AS "profile_server_id!: String",
        NULL         AS "license!: Option<ModelLicense>",
        n.created_at AS "created_at!: DateTime<Utc>",
        n.updated_at AS "updated_at!: DateTime<Utc>",
-       r.server_id  AS "first_reply_server_id!: Option<String>",
-       NULL         AS "title"
+       (SELECT r.server_id FROM note AS r WHERE r.in_reply_to_note_id = n.id OR r.in_reply_to_comment_id = n.id ORDER BY r.id LIMIT 1)
+                    AS "first_reply_server_id!: Option<String>",
+       NULL         AS "title",
+       COALESCE(rn.server_id, rc.server_id, rm.server_id)
+                    AS "in_reply_to!: Option<String>",
+       n.audience   AS "audience!: EventAudience"
 FROM profile AS p
          LEFT JOIN note AS n ON p.id = n.actor_id
-         LEFT JOIN note AS r ON n.id = r.in_reply_to_note_id
+         LEFT JOIN note AS rn ON rn.id = n.in_reply_to_note_id
+         LEFT JOIN note AS rc ON rc.id = n.in_reply_to_comment_id
+         LEFT JOIN model AS rm ON rm.id = n.in_reply_to_model_id
 WHERE n.id = $1
 
 UNION ALL
@@ -469,12 +694,14 @@ SELECT p.server_id   AS "profile_server_id!: String",
        m.license     AS "license!: Option<ModelLicense>",
        m.created_at  AS "created_at!: DateTime<Utc>",
        m.updated_at  AS "updated_at!: DateTime<Utc>",
-       r.server_id   AS "first_reply_server_id!: Option<String>",
-       m.title          AS "title"
+       (SELECT r.server_id FROM note AS r WHERE r.in_reply_to_model_id = m.id ORDER BY r.id LIMIT 1)
+                     AS "first_reply_server_id!: Option<String>",
+       m.title          AS "title",
+       NULL          AS "in_reply_to!: Option<String>",
+       (CASE WHEN m.published THEN 'PUBLIC' ELSE 'NOBODY' END)::event_audience
+                     AS "audience!: EventAudience"
 FROM profile AS p
          LEFT JOIN model AS m ON p.id = m.profile_id
-         LEFT JOIN note AS r ON m.id = r.in_reply_to_model_id
-         LEFT JOIN file AS f ON f.image_for_model_id = m.id
 WHERE m.id = $1
 ORDER BY "created_at!: DateTime<Utc>"
        "#,
@@ -485,6 +712,27 @@ ORDER BY "created_at!: DateTime<Utc>"
     }
 }
 
+pub const PUBLIC_ADDRESS: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+pub fn get_addressing(
+    author_server_id: &str,
+    audience: &EventAudience,
+    mentioned: &[FullProfile],
+) -> (Vec<String>, Vec<String>) {
+    //! Maps the audience onto (to, cc), mentioned profiles are always addressed directly
+    let followers = format!("{author_server_id}/followers");
+    let mentioned: Vec<String> = mentioned.iter().map(|v| v.server_id.clone()).collect();
+    match audience {
+        EventAudience::Public => (
+            vec![PUBLIC_ADDRESS.to_string()],
+            [vec![followers], mentioned].concat(),
+        ),
+        EventAudience::Followers => (vec![followers], mentioned),
+        EventAudience::Mentioned => (mentioned, vec![]),
+        EventAudience::Nobody => (vec![], vec![]),
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ActivityPubModel {
@@ -497,6 +745,8 @@ pub struct ActivityPubModel {
     pub id: String,
     pub published: DateTime<Utc>,
     pub replies: Replies,
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
     pub sensitive: bool,
     pub summary: Option<String>,
     pub tag: Vec<Tag>,
@@ -506,6 +756,10 @@ pub struct ActivityPubModel {
     pub url: String,
     pub license: Option<ModelLicense>,
     pub name: Option<String>,
+    #[serde(default)]
+    pub likes: CountedCollection,
+    #[serde(default)]
+    pub shares: CountedCollection,
 }
 
 impl ActivityPubModel {
@@ -517,24 +771,22 @@ impl ActivityPubModel {
         let status = NoteJoinedModel::get_by_id(id, pool.clone()).await?;
         let id = status.note_id.unwrap_or(status.model_id.unwrap());
         let is_model = status.model_id.is_some();
-        let mut attachments: Vec<Attachment> = vec![];
-        if is_model {
-            let files = FullFile::get_many_files_by_model(&id, pool.clone()).await?;
-            attachments = files
-                .iter()
-                .map(|v| Attachment {
-                    blurhash: v.thumbhash.clone(),
-                    height: None,
-                    width: None,
-                    media_type: v.mime_type.clone(),
-                    url: format!("{public_url}/api/v1/storage/download/{}", v.id),
-                    name: v.description.clone().unwrap_or_default(),
-                    type_field: "Document".to_string(),
-                })
-                .collect();
-        }
+        let attachments = match is_model {
+            true => Attachment::get_by_model_id(&id, &public_url, pool.clone()).await?,
+            false => vec![],
+        };
 
         let user_data = UsernameAndServerId::get_by_id(&status.profile_id, pool.clone()).await?;
+        let like_count =
+            FullLike::count_by_object(status.note_id, status.model_id, pool.clone()).await?;
+        let boost_count =
+            FullBoost::count_by_object(status.note_id, status.model_id, pool.clone()).await?;
+        let object_id = status.server_id.unwrap();
+        let mentioned = match status.note_id {
+            Some(note_id) => FullMention::get_mentioned_profiles(&note_id, pool.clone()).await?,
+            None => vec![],
+        };
+        let (to, cc) = get_addressing(&user_data.server_id, &status.audience, &mentioned);
         let model_context: Value = json!({
             "Hashtag": "as:Hashtag",
             //"atomUri": "ostatus:atomUri",
@@ -553,23 +805,23 @@ impl ActivityPubModel {
             ),
             attachment: attachments,
             attributed_to: user_data.server_id,
-            cc: vec![format!(
-                "{}/api/v1/user/{}/followers",
-                public_url, user_data.username
-            )],
+            cc,
             content: status.content,
-            id: status.server_id.unwrap(),
+            id: object_id.clone(),
             published: status.created_at,
             replies: Replies {
                 first: First {
-                    items: vec![],
-                    next: format!("{}/api/v1/model/{}/replies&page=true", public_url, &id),
-                    part_of: format!("{}/api/v1/model/{}/replies", public_url, &id),
+                    items: status
+                        .first_reply_server_id
+                        .map_or(vec![], |v| vec![Value::String(v)]),
+                    next: format!("{object_id}/replies?page=true"),
+                    part_of: format!("{object_id}/replies"),
                     type_field: "CollectionPage".to_string(),
                 },
-                id: format!("{}/api/v1/model/{}/replies", public_url, &id),
+                id: format!("{object_id}/replies"),
                 type_field: "Collection".to_string(),
             },
+            in_reply_to: status.in_reply_to,
             sensitive: false,
             summary: status.summary,
             tag: status
@@ -580,16 +832,36 @@ impl ActivityPubModel {
                     name: format!("#{}", t),
                     type_field: "Hashtag".to_string(),
                 })
+                .chain(mentioned.iter().map(Tag::from_profile))
                 .collect(),
-            to: vec!["https://www.w3.org/ns/activitystreams#Public".to_string()],
+            to,
             type_field: "Note".to_string(),
             url: format!("{}/api/v1/model/{}", public_url, &id),
             license: status.license,
             name: status.title,
+            likes: CountedCollection {
+                id: format!("{object_id}/likes"),
+                type_field: "Collection".to_string(),
+                total_items: like_count,
+            },
+            shares: CountedCollection {
+                id: format!("{object_id}/shares"),
+                type_field: "Collection".to_string(),
+                total_items: boost_count,
+            },
         })
     }
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountedCollection {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub total_items: i64,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Replies {
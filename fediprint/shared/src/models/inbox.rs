@@ -6,10 +6,61 @@ use serde_json::Value;
 #[serde(rename_all = "camelCase")]
 pub struct InboxEvent {
     #[serde(rename = "@context")]
-    pub context: String,
+    pub context: Value,
+    pub id: String,
     #[serde(rename = "type")]
     pub event_type: String,
     pub to: Option<Vec<String>>,
+    pub cc: Option<Vec<String>>,
+    pub bto: Option<Vec<String>>,
+    pub bcc: Option<Vec<String>>,
     pub actor: String,
     pub object: Value,
+    pub content: Option<String>,
+    pub target: Option<String>,
+}
+
+impl InboxEvent {
+    pub fn get_addressed(&self) -> Vec<String> {
+        //! Everyone the activity is addressed to, including a referenced object like a followed profile
+        let mut addressed: Vec<String> = [&self.to, &self.cc, &self.bto, &self.bcc]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .cloned()
+            .collect();
+        if let Some(d) = self.object.as_str() {
+            addressed.push(d.to_string());
+        }
+        addressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_addressed() {
+        let event: InboxEvent = serde_json::from_value(serde_json::json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": "https://mastodon.online/users/Mawoka#follows/1",
+            "type": "Follow",
+            "actor": "https://mastodon.online/users/Mawoka",
+            "to": ["https://www.w3.org/ns/activitystreams#Public"],
+            "cc": ["https://mastodon.online/users/Mawoka/followers"],
+            "bcc": ["http://localhost:3000/api/v1/user/alice"],
+            "object": "http://localhost:3000/api/v1/user/testuser"
+        }))
+        .unwrap();
+        assert_eq!(
+            event.get_addressed(),
+            vec![
+                "https://www.w3.org/ns/activitystreams#Public",
+                "https://mastodon.online/users/Mawoka/followers",
+                "http://localhost:3000/api/v1/user/alice",
+                "http://localhost:3000/api/v1/user/testuser"
+            ]
+        );
+    }
 }
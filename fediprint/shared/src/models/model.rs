@@ -14,3 +14,13 @@ pub struct CreateModel {
     pub cost: i16,
     pub currency: stripe::Currency,
 }
+
+#[derive(Deserialize)]
+pub struct UpdateModel {
+    pub id: Uuid,
+    pub title: String,
+    pub summary: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub license: ModelLicense,
+}
@@ -1,7 +1,10 @@
 use anyhow::Result;
-use chrono::{DateTime, TimeDelta, Utc};
 use dotenvy::dotenv;
+use shared::db::jobs::{
+    claim_job, get_due_job_ids, save_failed_job, save_success_job, JobResponseFailure, JobType,
+};
 use shared::helpers::config::Config;
+use shared::helpers::keys::KeyService;
 use sqlx::postgres::{PgListener, PgPoolOptions};
 use sqlx::{Error, PgPool};
 use std::time::Duration;
@@ -9,118 +12,40 @@ use std::time::Instant;
 use tracing::debug;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use types::JobResponseFailure;
 
-use crate::tasks::email::send_register_email;
-use crate::types::JobStatus;
+use crate::tasks::deliver::deliver_activity;
+use crate::tasks::email::{send_password_reset_email, send_register_email};
 mod tasks;
-pub mod types;
-
-async fn save_failed_job(
-    data: JobResponseFailure,
-    t: u128,
-    job_id: i32,
-    pool: PgPool,
-) -> Result<(), Error> {
-    let mut retry_at: Option<DateTime<Utc>> = None;
-    let mut status: JobStatus = JobStatus::Failed;
-    if data.try_in.is_some() {
-        retry_at = Some(Utc::now() + TimeDelta::milliseconds(data.try_in.unwrap().into()));
-        status = JobStatus::WaitingForRetry;
-    }
-    sqlx::query!(
-        r#"UPDATE jobs SET retry_at = $1, failure_log = array_append(failure_log, $2), processing_times = array_append(processing_times, $3), status = $4 WHERE id = $5"#,
-        retry_at, data.failure_message, t as i64, status as _, job_id
-    ).execute(&pool).await?;
-    Ok(())
-}
-async fn save_success_job(data: String, t: u128, job_id: i32, pool: PgPool) -> Result<(), Error> {
-    sqlx::query!(
-        r#"UPDATE jobs SET return_data = $1, processing_times = array_append(processing_times, $2), status = $3 WHERE id = $4"#,
-        data, t as i64, JobStatus::Finished as _, job_id
-    ).execute(&pool).await?;
-    Ok(())
-}
 
 async fn run_job(task_id: i32, config: &Config, pool: PgPool) -> Result<(), Error> {
-    let lock_success: bool =
-        sqlx::query_scalar!(r#"SELECT pg_try_advisory_lock($1)"#, i64::from(task_id))
-            .fetch_one(&pool)
-            .await
-            .unwrap()
-            .expect("DB query failed");
-    if !lock_success {
-        debug!("Could not acquire lock, waiting for next event");
+    let Some(job) = claim_job(task_id, false, pool.clone()).await? else {
+        debug!("Job {task_id} is already taken or not due yet");
         return Ok(());
-    }
-    debug!("Yay! Acquired lock for job {task_id}");
-    let job = sqlx::query_as!(
-        types::FullJob,
-        r#"SELECT
-        id,
-        created_at,
-        started_at,
-        status AS "status!: types::JobStatus",
-        retry_at,
-        finished_at,
-        input_data,
-        return_data,
-        failure_log,
-        tries,
-        max_tries,
-        processing_times,
-        updated_at,
-        job_type AS "job_type!: types::JobType"
-    FROM
-        jobs
-    WHERE
-        id = $1"#,
-        task_id
-    )
-    .fetch_one(&pool)
-    .await?;
+    };
+    debug!("Yay! Claimed job {task_id}");
     let start_time = Instant::now();
     let data = match job.job_type {
-        types::JobType::SendRegisterEmail => {
-            send_register_email(job.clone(), config, pool.clone()).await
+        JobType::SendRegisterEmail => send_register_email(job.clone(), config, pool.clone()).await,
+        JobType::SendPasswordResetEmail => {
+            send_password_reset_email(job.clone(), config, pool.clone()).await
+        }
+        JobType::DeliverActivity => deliver_activity(job.clone(), config, pool.clone()).await,
+        JobType::ProcessInboxActivity | JobType::DeleteAccount => {
+            Err(JobResponseFailure::never_try(
+                "Inbox activities and deletions are processed by the app",
+            ))
         }
     };
     let elapsed = start_time.elapsed().as_millis();
     match data {
         Ok(d) => save_success_job(d, elapsed, job.id as i32, pool.clone()).await,
-        Err(e) => save_failed_job(e, elapsed, job.id as i32, pool.clone()).await,
+        Err(e) => save_failed_job(e, elapsed, &job, pool.clone()).await,
     }
 }
 
 async fn fetch_and_process_jobs(config: &Config, pool: PgPool) -> Result<(), Error> {
-    let jobs = sqlx::query_as!(
-        types::FullJob,
-        r#"SELECT
-        id,
-        created_at,
-        started_at,
-        status AS "status!: types::JobStatus",
-        retry_at,
-        finished_at,
-        input_data,
-        return_data,
-        failure_log,
-        tries,
-        max_tries,
-        processing_times,
-        updated_at,
-        job_type AS "job_type!: types::JobType"
-    FROM
-        jobs
-    WHERE
-        (status = 'UNPROCESSED' OR retry_at < NOW())
-        AND updated_at < NOW() - INTERVAL '15 minutes'
-    LIMIT 10"# // Limit the number of jobs fetched to avoid overloading
-    )
-    .fetch_all(&pool)
-    .await?;
-    for job in jobs {
-        run_job(job.id as i32, config, pool.clone()).await?;
+    for job_id in get_due_job_ids(false, pool.clone()).await? {
+        run_job(job_id, config, pool.clone()).await?;
     }
     Ok(())
 }
@@ -145,6 +70,11 @@ async fn main() -> Result<()> {
         .connect(&config.database_url)
         .await
         .expect("can't connect to database");
+    // The app does the same, deliveries can't be signed with plain keys
+    KeyService::new(&config)
+        .encrypt_plain_keys(pool.clone())
+        .await
+        .expect("Could not encrypt the private keys!");
     let mut listener = PgListener::connect_with(&pool).await?;
     listener.listen_all(vec!["worker_update"]).await?;
 
@@ -0,0 +1,48 @@
+use openssl::pkey::{PKey, Private};
+use shared::db::jobs::{get_backoff, DeliverActivityData, FullJob, JobResponseFailure};
+use shared::helpers::{config::Config, keys::KeyService, sign::post_signed};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+async fn get_sender_key(
+    sender: &Uuid,
+    config: &Config,
+    pool: PgPool,
+) -> Result<(PKey<Private>, String), JobResponseFailure> {
+    //! The local instance id stands for the instance actor, e.g. for forwarded reports.
+    //! Loaded on every delivery, so queued activities are signed with the current key after a rotation
+    KeyService::new(config)
+        .get_signing_key(sender, pool)
+        .await
+        .map_err(|e| JobResponseFailure::never_try(&format!("No key for the sender: {e}")))
+}
+
+pub async fn deliver_activity(
+    job: FullJob,
+    config: &Config,
+    pool: PgPool,
+) -> Result<String, JobResponseFailure> {
+    let data: DeliverActivityData =
+        serde_json::from_str(job.input_data.as_deref().unwrap_or_default())
+            .map_err(|e| JobResponseFailure::never_try(&format!("Invalid input data: {e}")))?;
+    let (key, key_id) = get_sender_key(&data.sender, config, pool).await?;
+    let res = post_signed(&data.inbox, data.activity, key, key_id)
+        .await
+        .map_err(|e| JobResponseFailure {
+            try_in: Some(get_backoff(job.tries)),
+            failure_message: format!("Delivery failed: {e}"),
+        })?;
+    let status = res.status();
+    if status.is_success() {
+        return Ok(status.to_string());
+    }
+    let msg = format!("{} answered with {status}", data.inbox);
+    // Timeouts and rate limits are worth retrying, other client errors won't go away
+    if status.is_client_error() && status.as_u16() != 408 && status.as_u16() != 429 {
+        return Err(JobResponseFailure::never_try(&msg));
+    }
+    Err(JobResponseFailure {
+        try_in: Some(get_backoff(job.tries)),
+        failure_message: msg,
+    })
+}
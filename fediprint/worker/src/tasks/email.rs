@@ -1,12 +1,20 @@
-use crate::types::{FullJob, JobResponseFailure};
 use askama::Template;
+use chrono::{TimeDelta, Utc};
 use lettre::{
     message::header::ContentType, transport::smtp::authentication::Credentials, Message,
     SmtpTransport, Transport,
 };
 use shared::{
-    db::{account::FullAccount, profile::FullProfile},
-    helpers::config::{Config, SmtpData},
+    db::{
+        account::FullAccount,
+        jobs::{FullJob, JobResponseFailure},
+        password_resets::CreatePasswordResetToken,
+        profile::FullProfile,
+    },
+    helpers::{
+        config::{Config, SmtpData},
+        tokens::{create_verification_token, generate_one_time_token},
+    },
 };
 use sqlx::PgPool;
 use uuid::Uuid;
@@ -18,7 +26,14 @@ struct RegisterTemplate<'a> {
     verify_link: &'a str,
 }
 
-async fn send_email<'a>(
+#[derive(Template)]
+#[template(path = "email/password_reset.html")]
+struct PasswordResetTemplate<'a> {
+    username: &'a str,
+    reset_link: &'a str,
+}
+
+async fn send_email(
     subject: &str,
     to: &str,
     body: &str,
@@ -58,17 +73,21 @@ pub async fn send_register_email(
     cfg: &Config,
     pool: PgPool,
 ) -> Result<String, JobResponseFailure> {
-    let user = FullAccount::get_by_id(
-        &Uuid::parse_str(&job.input_data.unwrap()).unwrap(),
-        pool.clone(),
-    )
-    .await
-    .unwrap();
+    let account_id = Uuid::parse_str(job.input_data.as_deref().unwrap_or_default())
+        .map_err(|e| JobResponseFailure::never_try(&format!("Invalid account id: {e}")))?;
+    let user = FullAccount::get_by_id(&account_id, pool.clone())
+        .await
+        .map_err(|e| JobResponseFailure::never_try(&format!("Account not found: {e}")))?;
+    let Some(code) = user.verified else {
+        return Ok("Already verified".into());
+    };
 
     let profile = FullProfile::get_by_id(&user.profile_id, pool)
         .await
-        .unwrap();
-    let link = format!("{}/verify?token={}", cfg.public_url, "PLACEHOLDER");
+        .map_err(|e| JobResponseFailure::never_try(&format!("Profile not found: {e}")))?;
+    let token = create_verification_token(&user.id, &code, &cfg.jwt_secret)
+        .map_err(|e| JobResponseFailure::never_try(&format!("Failed to sign token: {e}")))?;
+    let link = format!("{}/api/v1/auth/verify?token={}", cfg.public_url, token);
     let template = RegisterTemplate {
         username: &profile.username,
         verify_link: &link,
@@ -79,3 +98,39 @@ pub async fn send_register_email(
     send_email("Verify your email", &user.email, &html, &cfg.smtp).await?;
     Ok("".into())
 }
+
+pub async fn send_password_reset_email(
+    job: FullJob,
+    cfg: &Config,
+    pool: PgPool,
+) -> Result<String, JobResponseFailure> {
+    //! The token is created here, so it only ever exists in the email and hashed in the database
+    let account_id = Uuid::parse_str(job.input_data.as_deref().unwrap_or_default())
+        .map_err(|e| JobResponseFailure::never_try(&format!("Invalid account id: {e}")))?;
+    let user = FullAccount::get_by_id(&account_id, pool.clone())
+        .await
+        .map_err(|e| JobResponseFailure::never_try(&format!("Account not found: {e}")))?;
+    let profile = FullProfile::get_by_id(&user.profile_id, pool.clone())
+        .await
+        .map_err(|e| JobResponseFailure::never_try(&format!("Profile not found: {e}")))?;
+    let (token, token_hash) = generate_one_time_token()
+        .map_err(|e| JobResponseFailure::never_try(&format!("Failed to create token: {e}")))?;
+    CreatePasswordResetToken {
+        account_id: user.id,
+        token_hash,
+        expires_at: Utc::now() + TimeDelta::try_hours(1).unwrap(),
+    }
+    .create(pool)
+    .await
+    .map_err(|e| JobResponseFailure::try_in_30(&format!("Failed to store token: {e}")))?;
+    let link = format!("{}/auth/reset?token={}", cfg.public_url, token);
+    let template = PasswordResetTemplate {
+        username: &profile.username,
+        reset_link: &link,
+    };
+    let html = template.render().map_err(|e| {
+        JobResponseFailure::try_in_30(&format!("Failed to render email template: {e}"))
+    })?;
+    send_email("Reset your password", &user.email, &html, &cfg.smtp).await?;
+    Ok("".into())
+}
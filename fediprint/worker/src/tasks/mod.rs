@@ -1 +1,2 @@
+pub mod deliver;
 pub mod email;
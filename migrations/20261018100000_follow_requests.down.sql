@@ -0,0 +1,10 @@
+ALTER TABLE followers
+    DROP CONSTRAINT followers_profile_id_follower_id_key;
+ALTER TABLE followers
+    DROP COLUMN activity_id,
+    DROP COLUMN pending,
+    ALTER COLUMN follower_id DROP NOT NULL,
+    ALTER COLUMN profile_id DROP NOT NULL;
+
+ALTER TABLE profile
+    DROP COLUMN manually_approves_followers;
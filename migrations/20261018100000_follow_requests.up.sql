@@ -0,0 +1,10 @@
+ALTER TABLE profile
+    ADD COLUMN manually_approves_followers boolean DEFAULT false NOT NULL;
+
+ALTER TABLE followers
+    ALTER COLUMN profile_id SET NOT NULL,
+    ALTER COLUMN follower_id SET NOT NULL,
+    ADD COLUMN pending     boolean DEFAULT false NOT NULL,
+    ADD COLUMN activity_id text    DEFAULT NULL;
+ALTER TABLE followers
+    ADD UNIQUE (profile_id, follower_id);
@@ -0,0 +1,14 @@
+ALTER TABLE profile
+    DROP COLUMN shared_inbox;
+
+ALTER TABLE jobs
+    ALTER COLUMN id DROP IDENTITY IF EXISTS;
+
+DELETE FROM jobs WHERE job_type = 'DELIVER_ACTIVITY';
+ALTER TYPE job_type RENAME TO job_type_old;
+CREATE TYPE job_type AS ENUM (
+    'SEND_REGISTER_EMAIL'
+    );
+ALTER TABLE jobs
+    ALTER COLUMN job_type TYPE job_type USING job_type::text::job_type;
+DROP TYPE job_type_old;
@@ -0,0 +1,7 @@
+ALTER TYPE job_type ADD VALUE 'DELIVER_ACTIVITY';
+
+ALTER TABLE jobs
+    ALTER COLUMN id ADD GENERATED BY DEFAULT AS IDENTITY;
+
+ALTER TABLE profile
+    ADD COLUMN shared_inbox text DEFAULT NULL;
@@ -0,0 +1,14 @@
+ALTER TABLE boosts DROP CONSTRAINT boosts_profile_model_unique;
+ALTER TABLE boosts DROP CONSTRAINT boosts_profile_note_unique;
+ALTER TABLE likes DROP CONSTRAINT likes_profile_model_unique;
+ALTER TABLE likes DROP CONSTRAINT likes_profile_note_unique;
+
+ALTER TABLE boosts DROP CONSTRAINT boosts_note_or_model;
+ALTER TABLE likes DROP CONSTRAINT likes_note_or_model;
+
+ALTER TABLE boosts DROP COLUMN activity_id;
+ALTER TABLE likes DROP COLUMN activity_id;
+
+DELETE FROM boosts WHERE model_id IS NOT NULL;
+ALTER TABLE boosts DROP COLUMN model_id;
+ALTER TABLE boosts ALTER COLUMN profile_id DROP NOT NULL;
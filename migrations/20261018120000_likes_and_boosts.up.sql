@@ -0,0 +1,37 @@
+-- Likes and boosts without a single target or without a profile can't be fixed automatically
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM likes WHERE (note_id IS NULL) = (model_id IS NULL))
+        OR EXISTS (SELECT 1 FROM boosts WHERE profile_id IS NULL OR note_id IS NULL) THEN
+        RAISE EXCEPTION 'Found likes or boosts that don''t belong to exactly one note or model and profile'
+            USING HINT = 'List them with "SELECT * FROM likes WHERE (note_id IS NULL) = (model_id IS NULL)" and '
+                '"SELECT * FROM boosts WHERE profile_id IS NULL OR note_id IS NULL", fix or delete them '
+                'and run the migrations again.';
+    END IF;
+END
+$$;
+
+ALTER TABLE boosts ALTER COLUMN profile_id SET NOT NULL;
+ALTER TABLE boosts ADD COLUMN model_id uuid REFERENCES model (id) ON UPDATE CASCADE ON DELETE CASCADE;
+
+ALTER TABLE likes ADD COLUMN activity_id text;
+ALTER TABLE boosts ADD COLUMN activity_id text;
+
+ALTER TABLE likes ADD CONSTRAINT likes_note_or_model CHECK ((note_id IS NULL) <> (model_id IS NULL));
+ALTER TABLE boosts ADD CONSTRAINT boosts_note_or_model CHECK ((note_id IS NULL) <> (model_id IS NULL));
+
+-- A profile likes or boosts an object only once, repeated ones are merged into the oldest
+DELETE FROM likes l USING likes o
+WHERE l.profile_id = o.profile_id
+  AND l.note_id IS NOT DISTINCT FROM o.note_id
+  AND l.model_id IS NOT DISTINCT FROM o.model_id
+  AND (o.created_at, o.id) < (l.created_at, l.id);
+DELETE FROM boosts b USING boosts o
+WHERE b.profile_id = o.profile_id
+  AND b.note_id = o.note_id
+  AND (o.created_at, o.id) < (b.created_at, b.id);
+
+ALTER TABLE likes ADD CONSTRAINT likes_profile_note_unique UNIQUE (profile_id, note_id);
+ALTER TABLE likes ADD CONSTRAINT likes_profile_model_unique UNIQUE (profile_id, model_id);
+ALTER TABLE boosts ADD CONSTRAINT boosts_profile_note_unique UNIQUE (profile_id, note_id);
+ALTER TABLE boosts ADD CONSTRAINT boosts_profile_model_unique UNIQUE (profile_id, model_id);
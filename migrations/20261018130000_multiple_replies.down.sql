@@ -0,0 +1,9 @@
+DROP INDEX note_comment_of_model_id_idx;
+DROP INDEX note_in_reply_to_model_id_idx;
+DROP INDEX note_in_reply_to_note_id_idx;
+DROP INDEX note_in_reply_to_comment_id_idx;
+
+ALTER TABLE note
+    ADD CONSTRAINT note_in_reply_to_comment_id_key UNIQUE (in_reply_to_comment_id),
+    ADD CONSTRAINT note_in_reply_to_note_id_key UNIQUE (in_reply_to_note_id),
+    ADD CONSTRAINT note_in_reply_to_model_id_key UNIQUE (in_reply_to_model_id);
@@ -0,0 +1,9 @@
+ALTER TABLE note
+    DROP CONSTRAINT note_in_reply_to_comment_id_key,
+    DROP CONSTRAINT note_in_reply_to_note_id_key,
+    DROP CONSTRAINT note_in_reply_to_model_id_key;
+
+CREATE INDEX note_in_reply_to_comment_id_idx ON note (in_reply_to_comment_id);
+CREATE INDEX note_in_reply_to_note_id_idx ON note (in_reply_to_note_id);
+CREATE INDEX note_in_reply_to_model_id_idx ON note (in_reply_to_model_id);
+CREATE INDEX note_comment_of_model_id_idx ON note (comment_of_model_id);
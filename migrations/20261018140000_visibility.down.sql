@@ -0,0 +1,2 @@
+DROP FUNCTION can_view_model(uuid, uuid);
+DROP FUNCTION can_view_note(uuid, uuid);
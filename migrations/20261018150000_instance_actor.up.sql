@@ -0,0 +1,4 @@
+-- Keypair of the instance actor, only set for the local instance
+ALTER TABLE instances
+    ADD COLUMN public_key  text,
+    ADD COLUMN private_key text;
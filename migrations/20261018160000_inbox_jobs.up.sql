@@ -0,0 +1,10 @@
+ALTER TYPE job_type ADD VALUE 'PROCESS_INBOX_ACTIVITY';
+
+-- Ids of received activities, so retried deliveries are only handled once
+CREATE TABLE processed_activities
+(
+    activity_id text PRIMARY KEY,
+    received_at timestamptz NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX processed_activities_received_at_idx ON processed_activities (received_at);
@@ -0,0 +1,5 @@
+ALTER TABLE instances
+    DROP COLUMN moderation,
+    DROP COLUMN moderation_comment;
+
+DROP TYPE instance_moderation;
@@ -0,0 +1,10 @@
+CREATE TYPE instance_moderation AS ENUM (
+    'NONE',
+    'SILENCED',
+    'SUSPENDED',
+    'REJECT_MEDIA'
+    );
+
+ALTER TABLE instances
+    ADD COLUMN moderation         instance_moderation DEFAULT 'NONE' NOT NULL,
+    ADD COLUMN moderation_comment text                DEFAULT NULL;
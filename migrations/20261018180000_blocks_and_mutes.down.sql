@@ -0,0 +1,35 @@
+CREATE OR REPLACE FUNCTION can_view_note(uuid, uuid) RETURNS boolean
+    LANGUAGE sql
+    STABLE
+AS
+$$
+SELECT EXISTS (SELECT 1
+               FROM note AS n
+               WHERE n.id = $1
+                 AND (n.audience = 'PUBLIC'
+                   OR n.actor_id = $2
+                   OR (n.audience IN ('FOLLOWERS', 'MENTIONED') AND EXISTS (SELECT 1
+                                                                             FROM _mentions AS m
+                                                                             WHERE m.note_id = n.id
+                                                                               AND m.profile_id = $2))
+                   OR (n.audience = 'FOLLOWERS' AND EXISTS (SELECT 1
+                                                             FROM followers AS f
+                                                             WHERE f.profile_id = n.actor_id
+                                                               AND f.follower_id = $2
+                                                               AND NOT f.pending))))
+$$;
+
+CREATE OR REPLACE FUNCTION can_view_model(uuid, uuid) RETURNS boolean
+    LANGUAGE sql
+    STABLE
+AS
+$$
+SELECT EXISTS (SELECT 1
+               FROM model AS m
+               WHERE m.id = $1
+                 AND (m.published OR m.profile_id = $2))
+$$;
+
+DROP FUNCTION is_profile_hidden(uuid, uuid);
+DROP TABLE mutes;
+DROP TABLE blocks;
@@ -0,0 +1,68 @@
+-- A block hides both profiles from each other and is federated, a mute only hides the target locally
+CREATE TABLE blocks
+(
+    id          uuid        DEFAULT uuid_generate_v7()                           NOT NULL PRIMARY KEY,
+    created_at  timestamptz DEFAULT CURRENT_TIMESTAMP                            NOT NULL,
+    profile_id  uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    target_id   uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    activity_id text,
+    CONSTRAINT blocks_profile_target_unique UNIQUE (profile_id, target_id)
+);
+
+CREATE TABLE mutes
+(
+    id         uuid        DEFAULT uuid_generate_v7()                           NOT NULL PRIMARY KEY,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP                            NOT NULL,
+    profile_id uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    target_id  uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    CONSTRAINT mutes_profile_target_unique UNIQUE (profile_id, target_id)
+);
+
+CREATE INDEX blocks_target_id_idx ON blocks (target_id);
+
+-- Whether the content of a profile is left out of the listings of a viewer: the viewer blocked or muted
+-- the profile, or the profile blocked the viewer. A NULL viewer is an anonymous request.
+CREATE FUNCTION is_profile_hidden(uuid, uuid) RETURNS boolean
+    LANGUAGE sql
+    STABLE
+AS
+$$
+SELECT EXISTS (SELECT 1 FROM blocks AS b WHERE b.profile_id = $2 AND b.target_id = $1)
+           OR EXISTS (SELECT 1 FROM mutes AS m WHERE m.profile_id = $2 AND m.target_id = $1)
+           OR EXISTS (SELECT 1 FROM blocks AS b WHERE b.profile_id = $1 AND b.target_id = $2)
+$$;
+
+-- Profiles blocked by the author can't read any of the author's notes or models
+CREATE OR REPLACE FUNCTION can_view_note(uuid, uuid) RETURNS boolean
+    LANGUAGE sql
+    STABLE
+AS
+$$
+SELECT EXISTS (SELECT 1
+               FROM note AS n
+               WHERE n.id = $1
+                 AND NOT EXISTS (SELECT 1 FROM blocks AS b WHERE b.profile_id = n.actor_id AND b.target_id = $2)
+                 AND (n.audience = 'PUBLIC'
+                   OR n.actor_id = $2
+                   OR (n.audience IN ('FOLLOWERS', 'MENTIONED') AND EXISTS (SELECT 1
+                                                                             FROM _mentions AS m
+                                                                             WHERE m.note_id = n.id
+                                                                               AND m.profile_id = $2))
+                   OR (n.audience = 'FOLLOWERS' AND EXISTS (SELECT 1
+                                                             FROM followers AS f
+                                                             WHERE f.profile_id = n.actor_id
+                                                               AND f.follower_id = $2
+                                                               AND NOT f.pending))))
+$$;
+
+CREATE OR REPLACE FUNCTION can_view_model(uuid, uuid) RETURNS boolean
+    LANGUAGE sql
+    STABLE
+AS
+$$
+SELECT EXISTS (SELECT 1
+               FROM model AS m
+               WHERE m.id = $1
+                 AND NOT EXISTS (SELECT 1 FROM blocks AS b WHERE b.profile_id = m.profile_id AND b.target_id = $2)
+                 AND (m.published OR m.profile_id = $2))
+$$;
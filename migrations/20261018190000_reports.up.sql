@@ -0,0 +1,21 @@
+CREATE TYPE report_status AS ENUM ('OPEN', 'RESOLVED', 'DISMISSED');
+
+-- Reports of local users as well as received `Flag` activities, the profile is the reported one
+-- or the author of the reported note or model
+CREATE TABLE reports
+(
+    id          uuid          DEFAULT uuid_generate_v7()                           NOT NULL PRIMARY KEY,
+    created_at  timestamptz   DEFAULT CURRENT_TIMESTAMP                            NOT NULL,
+    reporter_id uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE CASCADE   NOT NULL,
+    profile_id  uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE CASCADE   NOT NULL,
+    note_id     uuid REFERENCES note (id) ON UPDATE CASCADE ON DELETE SET NULL,
+    model_id    uuid REFERENCES model (id) ON UPDATE CASCADE ON DELETE SET NULL,
+    reason      text                                                               NOT NULL,
+    activity_id text,
+    forwarded   boolean       DEFAULT false                                        NOT NULL,
+    status      report_status DEFAULT 'OPEN'                                       NOT NULL,
+    resolved_at timestamptz,
+    resolved_by uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE SET NULL
+);
+
+CREATE INDEX reports_status_idx ON reports (status, created_at);
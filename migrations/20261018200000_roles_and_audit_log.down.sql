@@ -0,0 +1,6 @@
+DROP TABLE audit_log;
+DROP TYPE audit_action;
+
+ALTER TABLE account DROP COLUMN suspended;
+ALTER TABLE account DROP COLUMN role;
+DROP TYPE account_role;
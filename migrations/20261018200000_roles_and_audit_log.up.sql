@@ -0,0 +1,20 @@
+CREATE TYPE account_role AS ENUM ('USER', 'MODERATOR', 'ADMIN');
+
+ALTER TABLE account ADD COLUMN role account_role DEFAULT 'USER' NOT NULL;
+ALTER TABLE account ADD COLUMN suspended boolean DEFAULT false NOT NULL;
+
+CREATE TYPE audit_action AS ENUM ('SET_ROLE', 'SUSPEND_ACCOUNT', 'UNSUSPEND_ACCOUNT', 'DELETE_ACCOUNT', 'UNPUBLISH_MODEL',
+    'PURGE_PROFILE', 'SET_INSTANCE_MODERATION', 'IMPORT_INSTANCE_MODERATION', 'SET_REPORT_STATUS');
+
+-- Every action of admins and moderators, the target is the id or domain the action was taken on
+CREATE TABLE audit_log
+(
+    id         uuid        DEFAULT uuid_generate_v7()                            NOT NULL PRIMARY KEY,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP                             NOT NULL,
+    actor_id   uuid REFERENCES profile (id) ON UPDATE CASCADE ON DELETE SET NULL,
+    action     audit_action                                                      NOT NULL,
+    target     text                                                              NOT NULL,
+    details    text
+);
+
+CREATE INDEX audit_log_created_at_idx ON audit_log (created_at);
@@ -0,0 +1,10 @@
+DELETE FROM jobs WHERE job_type = 'DELETE_ACCOUNT';
+ALTER TYPE job_type RENAME TO job_type_old;
+CREATE TYPE job_type AS ENUM (
+    'SEND_REGISTER_EMAIL',
+    'DELIVER_ACTIVITY',
+    'PROCESS_INBOX_ACTIVITY'
+    );
+ALTER TABLE jobs
+    ALTER COLUMN job_type TYPE job_type USING job_type::text::job_type;
+DROP TYPE job_type_old;
@@ -0,0 +1,2 @@
+ALTER TABLE profile DROP COLUMN moved_to;
+ALTER TABLE profile DROP COLUMN also_known_as;
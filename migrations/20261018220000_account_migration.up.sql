@@ -0,0 +1,2 @@
+ALTER TABLE profile ADD COLUMN also_known_as text[] DEFAULT '{}' NOT NULL;
+ALTER TABLE profile ADD COLUMN moved_to text;
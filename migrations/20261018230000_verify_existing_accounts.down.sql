@@ -0,0 +1,2 @@
+-- The verification codes of the existing accounts can't be restored
+SELECT 1;
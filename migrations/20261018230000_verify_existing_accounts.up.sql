@@ -0,0 +1,2 @@
+-- Accounts registered before the verification emails were sent never got a working link
+UPDATE account SET verified = NULL;
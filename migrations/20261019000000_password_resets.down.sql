@@ -0,0 +1,13 @@
+DROP TABLE password_reset_tokens;
+
+DELETE FROM jobs WHERE job_type = 'SEND_PASSWORD_RESET_EMAIL';
+ALTER TYPE job_type RENAME TO job_type_old;
+CREATE TYPE job_type AS ENUM (
+    'SEND_REGISTER_EMAIL',
+    'DELIVER_ACTIVITY',
+    'PROCESS_INBOX_ACTIVITY',
+    'DELETE_ACCOUNT'
+    );
+ALTER TABLE jobs
+    ALTER COLUMN job_type TYPE job_type USING job_type::text::job_type;
+DROP TYPE job_type_old;
@@ -0,0 +1,11 @@
+ALTER TYPE job_type ADD VALUE 'SEND_PASSWORD_RESET_EMAIL';
+
+CREATE TABLE password_reset_tokens
+(
+    id         uuid        DEFAULT uuid_generate_v7()                                     NOT NULL PRIMARY KEY,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP                                      NOT NULL,
+    account_id uuid REFERENCES account (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    token_hash text                                                                       NOT NULL UNIQUE,
+    expires_at timestamptz                                                                NOT NULL,
+    used_at    timestamptz DEFAULT NULL
+);
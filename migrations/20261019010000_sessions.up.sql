@@ -0,0 +1,12 @@
+CREATE TABLE sessions
+(
+    id           uuid        DEFAULT uuid_generate_v7()                                     NOT NULL PRIMARY KEY,
+    created_at   timestamptz DEFAULT CURRENT_TIMESTAMP                                      NOT NULL,
+    account_id   uuid REFERENCES account (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    user_agent   text,
+    ip           text,
+    last_seen_at timestamptz DEFAULT CURRENT_TIMESTAMP                                      NOT NULL,
+    revoked_at   timestamptz DEFAULT NULL
+);
+
+CREATE INDEX sessions_account_id_idx ON sessions (account_id);
@@ -0,0 +1,21 @@
+-- The secret is stored once enrolment starts, 2FA is only enforced after the first code was confirmed
+CREATE TABLE two_factor
+(
+    account_id   uuid REFERENCES account (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL PRIMARY KEY,
+    created_at   timestamptz DEFAULT CURRENT_TIMESTAMP                                      NOT NULL,
+    secret       text                                                                       NOT NULL,
+    confirmed_at timestamptz DEFAULT NULL,
+    -- The last accepted time step, so a code can't be used twice
+    last_step    bigint      DEFAULT NULL
+);
+
+CREATE TABLE recovery_codes
+(
+    id         uuid        DEFAULT uuid_generate_v7()                                     NOT NULL PRIMARY KEY,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP                                      NOT NULL,
+    account_id uuid REFERENCES account (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    code_hash  text                                                                       NOT NULL,
+    used_at    timestamptz DEFAULT NULL
+);
+
+CREATE INDEX recovery_codes_account_id_idx ON recovery_codes (account_id);
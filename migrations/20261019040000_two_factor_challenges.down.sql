@@ -0,0 +1,4 @@
+DROP TABLE two_factor_challenges;
+ALTER TABLE two_factor DROP COLUMN locked_until;
+ALTER TABLE two_factor DROP COLUMN failed_attempts;
+ALTER TABLE two_factor RENAME COLUMN encrypted_secret TO secret;
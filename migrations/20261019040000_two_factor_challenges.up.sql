@@ -0,0 +1,15 @@
+-- The secret is encrypted with the key-encryption key, the plain ones from before are encrypted on startup
+ALTER TABLE two_factor RENAME COLUMN secret TO encrypted_secret;
+-- Wrong codes lock the second factor for a while, so it can't be brute-forced
+ALTER TABLE two_factor ADD COLUMN failed_attempts integer DEFAULT 0 NOT NULL;
+ALTER TABLE two_factor ADD COLUMN locked_until timestamptz DEFAULT NULL;
+
+CREATE TABLE two_factor_challenges
+(
+    id         uuid        DEFAULT uuid_generate_v7()                                     NOT NULL PRIMARY KEY,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP                                      NOT NULL,
+    account_id uuid REFERENCES account (id) ON UPDATE CASCADE ON DELETE CASCADE NOT NULL,
+    token_hash text                                                                       NOT NULL UNIQUE,
+    expires_at timestamptz                                                                NOT NULL,
+    used_at    timestamptz DEFAULT NULL
+);
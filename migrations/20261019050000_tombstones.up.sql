@@ -0,0 +1,6 @@
+-- Ids of remote notes and models deleted by their author, so a late or replayed Create doesn't restore them
+CREATE TABLE tombstones
+(
+    server_id  text PRIMARY KEY,
+    deleted_at timestamptz NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+ALTER TABLE profile
+    DROP COLUMN public_key_refreshed_at;
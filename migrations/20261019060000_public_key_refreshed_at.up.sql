@@ -0,0 +1,3 @@
+-- When the key of a remote profile was last refetched after a signature didn't verify
+ALTER TABLE profile
+    ADD COLUMN public_key_refreshed_at timestamptz;